@@ -1,58 +1,106 @@
 use ptchecker::logics::*;
+use ptchecker::logics::classify::{classify, fragments, Class};
+use ptchecker::logics::ctl::GlobalCtl;
 use ptchecker::logics::parser::*;
+use ptchecker::logics::props::*;
+use ptchecker::logics::space::{NetSpace, StateSpace};
 use ptchecker::logics::transys::*;
 use ptchecker::ltl::*;
 use ptchecker::ltl::checker::*;
+use ptchecker::ltl::fairness::*;
 use ptchecker::ltl::translator::*;
 use ptchecker::petri::*;
 use ptchecker::petri::parser::*;
+use ptchecker::petri::reach::{ReachChecker, SearchOrder};
+use ptchecker::petri::stubborn::Reduction;
+use ptchecker::store::StoreMode;
 use ptchecker::utils::*;
 
 use std::env;
 use std::path::Path;
 use std::process::exit;
 
-fn ltl_check(model: &PTNet, input: &Formula) {
-    // Simple tests:
-    let test1 = Formula {
-        name: "test1".to_string(),
-        ty: FormulaTy::Forall(Box::new(FormulaTy::Until(
-            Box::new(FormulaTy::Prop(PTAtom::Fireability("a".to_string()))),
-            Box::new(FormulaTy::Prop(PTAtom::Fireability("b".to_string()))),
-        )))
-    };
+fn ltl_check(model: &PTNet, input: &Formula, fairness: &[Fairness], por: bool, store: StoreMode, cached: bool) -> Option<bool> {
+    let (auto, fin, formulas) = build_automaton_cav01(input)?;
+    // Stubborn sets may postpone a transition forever, which fairness forbids
+    let reduction = if por && fairness.is_empty() { Reduction::for_formula(model, &input.ty) } else { Reduction::Off };
+    if cached {
+        // Build the whole state space first, the product is then explored over it
+        let tran = TranSys::from_petri(model);
+        let mut checker = LTLChecker::new(tran, model, auto, fin, formulas, reduction, store);
+        checker.fairness = fairness.to_vec();
+        Some(report(checker, input))
+    }
+    else {
+        let mut checker = LTLChecker::new(NetSpace::new(model), model, auto, fin, formulas, reduction, store);
+        checker.fairness = fairness.to_vec();
+        Some(report(checker, input))
+    }
+    // build_automaton_pstv95(input);
+}
 
-    let test2 = Formula {
-        name: "test2".to_string(),
-        ty: FormulaTy::Forall(Box::new(FormulaTy::Not(
-            Box::new(FormulaTy::And(
-                Box::new(FormulaTy::Global(Box::new(FormulaTy::Finally(
-                    Box::new(FormulaTy::Prop(PTAtom::Fireability("p".to_string()))),
-                )))),
-                Box::new(FormulaTy::Finally(Box::new(FormulaTy::And(
-                    Box::new(FormulaTy::Prop(PTAtom::Fireability("q".to_string()))),
-                    Box::new(FormulaTy::Global(Box::new(FormulaTy::Neg(PTAtom::Fireability("r".to_string())))))
-                )))),
-            ))
-        )))
-    };
-    
-    if let Some((auto, fin)) = build_automaton_cav01(input) {
-        // let tran = TranSys::from_petri(&model);
-        let mut checker = LTLChecker::new(&model, auto, fin.clone());
-        let res = checker.check();
-        println!("checking res for formula {:?}: {:?}", input.ty, res);
-        if !res {
-            println!("Counterexample: ");
-            for state1 in checker.stack_1.iter() {
-                println!("{:?}", state1.1);
+// Formulas outside LTL go to the engine of their class, E phi over an LTL path formula is
+// checked as the negation of A !phi
+fn dispatch(model: &PTNet, f: &Formula, fairness: &[Fairness], por: bool, store: StoreMode, cached: bool) {
+    let class = classify(&f.ty);
+    let res = match class {
+        Class::Ltl => {
+            ltl_check(model, f, fairness, por, store, cached);
+            return;
+        },
+        Class::Propositional => {
+            let space = NetSpace::new(model);
+            eval_state(&f.ty, model, &space.marking(&space.initial()))
+        },
+        Class::Reachability => ReachChecker::new(model, SearchOrder::Bfs, store).check(&f.ty).map(|report| report.holds),
+        Class::Ctl => {
+            if cached {
+                GlobalCtl::new(&TranSys::from_petri(model), &[]).and_then(|mut ctl| ctl.holds(&f.ty))
             }
-            for state2 in checker.stack_2.iter() {
-                println!("loop {:?}", state2.1);
+            else {
+                GlobalCtl::new(&NetSpace::new(model), &[]).and_then(|mut ctl| ctl.holds(&f.ty))
             }
+        },
+        Class::CtlStar => match &f.ty {
+            FormulaTy::Exists(inner) => {
+                let dual = FormulaTy::Forall(Box::new(FormulaTy::Not(inner.clone())));
+                if classify(&dual) == Class::Ltl {
+                    ltl_check(model, &Formula { name: f.name.clone(), ty: dual }, fairness, por, store, cached).map(|b| !b)
+                }
+                else {
+                    None
+                }
+            },
+            _ => None,
+        },
+    };
+    let kind = match fragments(&f.ty) {
+        Some(frag) => format!("{}; {}", class, frag),
+        None => class.to_string(),
+    };
+    match res {
+        Some(res) => println!("{}: {} ({})", f.name, if res { "TRUE" } else { "FALSE" }, kind),
+        None => println!("{}: unsupported formula ({})", f.name, kind),
+    }
+}
+
+fn report<S: StateSpace>(mut checker: LTLChecker<S>, input: &Formula) -> bool {
+    let res = checker.check();
+    println!("checking res for formula {:?}: {:?}", input.ty, res);
+    println!("exploration: {}", checker.stats);
+    if !res {
+        println!("Counterexample: ");
+        for state1 in checker.stack_1.iter() {
+            println!("{:?}", checker.space.marking(&state1.1));
+        }
+        for state2 in checker.stack_2.iter() {
+            println!("loop {:?}", checker.space.marking(&state2.1));
+        }
+        for met in checker.fairness_met.iter() {
+            println!("{}", met);
         }
     }
-    // build_automaton_pstv95(input);
+    res
 }
 
 fn old_main() {
@@ -63,28 +111,115 @@ fn old_main() {
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: ptchecker <path>\n");
+    // Partial-order reduction is on unless --no-por is given
+    let mut por = true;
+    let mut store = StoreMode::Exact;
+    let mut cached = false;
+    // Formulas given with --formula or --props replace the MCC files
+    let mut custom = Vec::new();
+    // Fairness assumptions as given, resolved once the net is read
+    let mut fair_groups = Vec::new();
+    let mut paths = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--no-por" => por = false,
+            "--cached" => cached = true,
+            "--formula" | "--props" => custom.extend(read_custom(arg, iter.next())),
+            "--weak-fair" | "--strong-fair" => match iter.next() {
+                Some(names) => {
+                    let kind = if arg == "--weak-fair" { FairKind::Weak } else { FairKind::Strong };
+                    fair_groups.push((kind, names.split(',').map(|t| String::from(t.trim())).collect()));
+                },
+                None => {
+                    println!("Missing argument to {}\n", arg);
+                    exit(1);
+                }
+            },
+            "--store" => match iter.next().and_then(|name| StoreMode::from_name(name)) {
+                Some(mode) => store = mode,
+                None => {
+                    println!("Unknown store mode\n");
+                    exit(1);
+                }
+            },
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.len() != 1 {
+        println!("Usage: ptchecker [--no-por] [--store exact|compact|bitstate] [--cached] [--formula F]... [--props FILE] [--weak-fair T1,T2]... [--strong-fair T1,T2]... <path>\n");
         exit(0);
     }
-    if !validate_path(args[1].as_str()) {
+    if !validate_path(paths[0].as_str()) {
         println!("Invalid input path\n");
         exit(1);
     }
-    let model_path = Path::new(args[1].as_str()).join("model.pnml");
+    let model_path = Path::new(paths[0].as_str()).join("model.pnml");
     let nets = parse_pnml(model_path.to_str().unwrap()).unwrap_or(Vec::new());
     if nets.len() == 0 {
         println!("No model found, exiting\n");
         exit(0);
     }
     // println!("read nets: {:#?}", nets[0]);
-    let input_path = Path::new(args[1].as_str()).join("LTLFireability.xml");
-    if let Ok(formulas) = parse_formulas(input_path.to_str().unwrap()) {
-        for f in formulas.iter() {
-            // ltl_check(f);
-            ltl_check(&nets[0], f);
-            // break;
+    let mut fairness = Vec::new();
+    for (kind, names) in fair_groups {
+        match Fairness::resolve(kind, names, &nets[0]) {
+            Ok(f) => fairness.push(f),
+            Err(name) => {
+                println!("Unknown transition {} in fairness assumption\n", name);
+                exit(1);
+            }
+        }
+    }
+    if !custom.is_empty() {
+        for f in bound(&nets[0], custom).iter() {
+            dispatch(&nets[0], f, &fairness, por, store, cached);
+        }
+        return;
+    }
+    for file in ["LTLFireability.xml", "LTLCardinality.xml"] {
+        let input_path = Path::new(paths[0].as_str()).join(file);
+        match parse_formulas(input_path.to_str().unwrap()) {
+            Ok(formulas) => for f in bound(&nets[0], formulas).iter() {
+                // ltl_check(f);
+                dispatch(&nets[0], f, &fairness, por, store, cached);
+                // break;
+            },
+            Err(e) => println!("{}: {}", file, e),
+        }
+    }
+}
+
+// Formulas whose names all resolve against the net, the others are reported and skipped
+fn bound(model: &PTNet, formulas: Vec<Formula>) -> Vec<Formula> {
+    formulas.into_iter()
+        .filter_map(|mut f| match f.bind(model) {
+            Ok(()) => Some(f),
+            Err(e) => {
+                println!("skipping {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+// Formulas of a --formula or --props argument, exiting on a syntax error
+fn read_custom(flag: &str, value: Option<&String>) -> Vec<Formula> {
+    let Some(value) = value else {
+        println!("Missing argument to {}\n", flag);
+        exit(1);
+    };
+    let res = if flag == "--formula" {
+        parse_property(value).map(|ty| vec![Formula { name: value.clone(), ty }])
+    }
+    else {
+        parse_props_file(value)
+    };
+    match res {
+        Ok(formulas) => formulas,
+        Err(e) => {
+            println!("Invalid formula:\n{}", e);
+            exit(1);
         }
-        // ltl_check(&nets[0], &formulas[2]);
     }
 }
\ No newline at end of file
@@ -1,5 +1,7 @@
 use ptchecker::logics::*;
 use ptchecker::logics::parser::*;
+use ptchecker::logics::printer::*;
+use ptchecker::logics::props::*;
 use ptchecker::petri::*;
 use ptchecker::petri::parser::*;
 use ptchecker::utils::*;
@@ -8,7 +10,26 @@ use std::env;
 use std::fmt::{Debug, Display};
 use std::path::Path;
 use std::process::exit;
-use ptchecker::logics::ctl::almc;
+use ptchecker::logics::classify::{classify, fragments, is_ctl, Class};
+use ptchecker::logics::ctl::{FairConstraint, GlobalCtl, Witness};
+use ptchecker::logics::space::{NetSpace, StateSpace};
+use ptchecker::ltl::checker::LTLChecker;
+use ptchecker::ltl::translator::build_automaton_cav01;
+use ptchecker::petri::stubborn::Reduction;
+use ptchecker::petri::disk::DiskExplorer;
+use ptchecker::petri::reach::{reachability_query, ReachChecker, SearchOrder};
+use ptchecker::petri::unfolding::Prefix;
+use ptchecker::store::StoreMode;
+use ptchecker::symbolic::bdd::FALSE;
+use ptchecker::symbolic::ctl::CtlChecker;
+use ptchecker::symbolic::mdd::EMPTY;
+use ptchecker::symbolic::saturation::ReachableSet;
+
+const MAX_PREFIX_EVENTS: usize = 200000;
+
+const REACHABILITY: [&str; 2] = ["ReachabilityFireability.xml", "ReachabilityCardinality.xml"];
+const CTL: [&str; 2] = ["CTLFireability.xml", "CTLCardinality.xml"];
+const LTL: [&str; 2] = ["LTLFireability.xml", "LTLCardinality.xml"];
 
 fn old_main() {
     // let nets = pnets_read_ptnets_from("data/SatelliteMemory-PT-X00100Y0003.pnml");
@@ -18,32 +39,448 @@ fn old_main() {
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: ptchecker <path>\n");
+    let mut engine = String::from("auto");
+    let mut por = true;
+    let mut on_the_fly = false;
+    let mut store = StoreMode::Exact;
+    let mut order = SearchOrder::Bfs;
+    let mut threads = 1;
+    let mut memory = 1024;
+    // Formulas given with --formula or --props replace the MCC files
+    let mut custom = Vec::new();
+    let mut export = None;
+    // Witnesses of the CTL verdicts, as text or DOT
+    let mut witness = None;
+    // Fairness constraints for CTL, as state formulas or transition names
+    let mut fair_states = Vec::new();
+    let mut fair_fires = Vec::new();
+    let mut paths = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--engine" => engine = iter.next().cloned().unwrap_or_default(),
+            "--export" => export = iter.next().cloned(),
+            "--witness" => match iter.next().map(|s| s.as_str()) {
+                Some(format @ ("text" | "dot")) => witness = Some(format.to_string()),
+                _ => {
+                    println!("Unknown witness format\n");
+                    exit(1);
+                }
+            },
+            "--no-por" => por = false,
+            "--on-the-fly" => on_the_fly = true,
+            "--formula" | "--props" => custom.extend(read_custom(arg, iter.next())),
+            "--fair" => fair_states.extend(read_custom("--formula", iter.next())),
+            "--fair-fire" => match iter.next() {
+                Some(names) => fair_fires.push(names.split(',').map(String::from).collect::<Vec<_>>()),
+                None => {
+                    println!("Missing argument to --fair-fire\n");
+                    exit(1);
+                }
+            },
+            "--memory" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => memory = n,
+                None => {
+                    println!("Invalid memory budget\n");
+                    exit(1);
+                }
+            },
+            "--threads" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => threads = n,
+                None => {
+                    println!("Invalid thread count\n");
+                    exit(1);
+                }
+            },
+            "--order" => match iter.next().and_then(|name| SearchOrder::from_name(name)) {
+                Some(o) => order = o,
+                None => {
+                    println!("Unknown search order\n");
+                    exit(1);
+                }
+            },
+            "--store" => match iter.next().and_then(|name| StoreMode::from_name(name)) {
+                Some(mode) => store = mode,
+                None => {
+                    println!("Unknown store mode\n");
+                    exit(1);
+                }
+            },
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.len() != 1 {
+        println!("Usage: ptchecker [--engine auto|explicit|reach|unfolding|symbolic|bdd|disk] [--order bfs|dfs|best] [--no-por] [--on-the-fly] [--store exact|compact|bitstate] [--threads N] [--memory MB] [--formula F]... [--props FILE] [--fair F]... [--fair-fire T1,T2]... [--witness text|dot] [--export xml|spot|ltl2ba|nusmv] <path>\n");
         exit(0);
     }
-    if !validate_path(args[1].as_str()) {
+    if !validate_path(paths[0].as_str()) {
         println!("Invalid input path\n");
         exit(1);
     }
-    let model_path = Path::new(args[1].as_str()).join("model1.pnml");
+    let model_path = Path::new(paths[0].as_str()).join("model1.pnml");
     let nets = parse_pnml(model_path.to_str().unwrap()).unwrap_or(Vec::new());
     if nets.len() == 0 {
         println!("No model found, exiting\n");
         exit(0);
     }
-    println!("read nets: {:#?}", nets[0]);
-    let input_path = Path::new(args[1].as_str()).join("CTLFireability.xml");
-    if let Ok(formulas) = parse_formulas(input_path.to_str().unwrap()) {
-        // for f in formulas {
-        //     println!("formula: {}\n", f.ty);
-        // }
-        println!("{}", test(&nets[0], &formulas[16]))
+    let mut fairness = Vec::new();
+    for mut f in fair_states {
+        if let Err(e) = f.bind(&nets[0]) {
+            println!("Invalid fairness constraint: {}\n", e);
+            exit(1);
+        }
+        fairness.push(FairConstraint::States(f.ty));
+    }
+    for names in fair_fires {
+        match FairConstraint::fires(&names, &nets[0]) {
+            Ok(c) => fairness.push(c),
+            Err(name) => {
+                println!("Unknown transition {} in fairness constraint\n", name);
+                exit(1);
+            }
+        }
+    }
+    let dir = Path::new(paths[0].as_str());
+    let formulas = |files: &[&str]| if custom.is_empty() { read_formulas(dir, files) } else { custom.clone() };
+    if let Some(format) = export {
+        let all: Vec<&str> = [REACHABILITY, CTL, LTL].concat();
+        export_formulas(&nets[0], &formulas(&all), &format);
+        return;
+    }
+    match engine.as_str() {
+        "auto" => {
+            let all: Vec<&str> = [REACHABILITY, CTL, LTL].concat();
+            for f in bound(&nets[0], formulas(&all)).iter() {
+                if let Some(w) = auto_check(&nets[0], f, order, store, por, on_the_fly, &fairness) {
+                    print_witness(&nets[0], &w, witness.as_deref());
+                }
+            }
+        },
+        "explicit" => {
+            println!("read nets: {:#?}", nets[0]);
+            deadlock_check(&nets[0], por, store, threads);
+            for f in bound(&nets[0], custom.clone()).iter() {
+                match test(&nets[0], f, on_the_fly, &fairness) {
+                    Some(w) => {
+                        println!("{}: {}", f.name, if w.holds { "TRUE" } else { "FALSE" });
+                        print_witness(&nets[0], &w, witness.as_deref());
+                    },
+                    None => println!("{}: unsupported formula", f.name),
+                }
+            }
+            if !custom.is_empty() {
+                return;
+            }
+            let input_path = Path::new(paths[0].as_str()).join("CTLFireability.xml");
+            if let Ok(formulas) = parse_formulas(input_path.to_str().unwrap()) {
+                // for f in formulas {
+                //     println!("formula: {}\n", f.ty);
+                // }
+                println!("{:?}", test(&nets[0], &formulas[16], on_the_fly, &fairness).map(|w| w.holds))
+            }
+        },
+        "reach" => reach_check(&nets[0], &bound(&nets[0], formulas(&REACHABILITY)), order, store),
+        "unfolding" => unfolding_check(&nets[0], &bound(&nets[0], formulas(&REACHABILITY))),
+        "symbolic" => symbolic_check(&nets[0], &bound(&nets[0], formulas(&REACHABILITY))),
+        "bdd" => bdd_check(&nets[0], &bound(&nets[0], formulas(&CTL))),
+        "disk" => disk_check(&nets[0], &bound(&nets[0], formulas(&REACHABILITY)), memory),
+        _ => {
+            println!("Unknown engine: {}\n", engine);
+            exit(1);
+        }
+    }
+}
+
+// Deadlocks survive the deadlock-preserving stubborn set reduction, CTL below still needs the full graph
+fn deadlock_check(model: &PTNet, por: bool, store: StoreMode, threads: usize) {
+    use ptchecker::logics::transys::*;
+    let reduction = if por { Reduction::Deadlock } else { Reduction::Off };
+    let tran = if threads > 1 {
+        TranSys::from_petri_parallel(model, reduction, store, threads).unwrap()
+    }
+    else {
+        TranSys::from_petri_with(model, reduction, store)
+    };
+    println!("exploration: {}", tran.stats());
+    let dead = tran.deadlocks();
+    if dead.is_empty() {
+        println!("deadlock: FALSE");
+    }
+    else {
+        println!("deadlock: TRUE, e.g. {:?}", tran.config_of(dead[0]));
+    }
+}
+
+// Each formula goes to the engine of its class: the initial marking for propositional formulas,
+// the reachability engine for EF/AG, the LTL checker and the explicit CTL checker.
+// E phi with phi an LTL path formula is checked as the negation of A !phi.
+// Under fairness constraints only CTL formulas are checked, all on fair paths. Returns the witness
+// of a verdict of the CTL checker.
+fn auto_check(model: &PTNet, f: &Formula, order: SearchOrder, store: StoreMode, por: bool, on_the_fly: bool, fairness: &[FairConstraint]) -> Option<Witness> {
+    let class = classify(&f.ty);
+    let mut witness = None;
+    let mut explained = None;
+    let mut explain = |fairness: &[FairConstraint]| {
+        explained = test(model, f, on_the_fly, fairness);
+        explained.as_ref().map(|w| w.holds)
+    };
+    let res = match class {
+        _ if !fairness.is_empty() => if is_ctl(&f.ty) { explain(fairness) } else { None },
+        Class::Propositional => {
+            let space = NetSpace::new(model);
+            eval_state(&f.ty, model, &space.marking(&space.initial()))
+        },
+        Class::Reachability => ReachChecker::new(model, order, store).check(&f.ty).map(|report| {
+            witness = report.witness;
+            report.holds
+        }),
+        Class::Ltl => ltl_holds(model, f, por, store),
+        Class::Ctl => explain(&[]),
+        Class::CtlStar => match &f.ty {
+            FormulaTy::Exists(inner) => {
+                let dual = FormulaTy::Forall(Box::new(FormulaTy::Not(inner.clone())));
+                if classify(&dual) == Class::Ltl {
+                    ltl_holds(model, &Formula { name: f.name.clone(), ty: dual }, por, store).map(|b| !b)
+                }
+                else {
+                    None
+                }
+            },
+            _ => None,
+        },
+    };
+    let kind = match fragments(&f.ty) {
+        Some(frag) => format!("{}; {}", class, frag),
+        None => class.to_string(),
+    };
+    match res {
+        Some(res) => println!("{}: {} ({})", f.name, if res { "TRUE" } else { "FALSE" }, kind),
+        None => println!("{}: unsupported formula ({})", f.name, kind),
+    }
+    if let (Some(res), Some(seq)) = (res, witness) {
+        println!("  {} {:?}", if res { "witness:" } else { "counterexample:" }, names(model, &seq));
+    }
+    explained
+}
+
+// A phi by nested DFS over states generated on the fly
+fn ltl_holds(model: &PTNet, f: &Formula, por: bool, store: StoreMode) -> Option<bool> {
+    let (auto, fin, formulas) = build_automaton_cav01(f)?;
+    let reduction = if por { Reduction::for_formula(model, &f.ty) } else { Reduction::Off };
+    Some(LTLChecker::new(NetSpace::new(model), model, auto, fin, formulas, reduction, store).check())
+}
+
+// EF/AG queries explored on the fly, each stopping at its first witness or violation
+fn reach_check(model: &PTNet, formulas: &[Formula], order: SearchOrder, store: StoreMode) {
+    let checker = ReachChecker::new(model, order, store);
+    for f in formulas.iter() {
+        let Some(report) = checker.check(&f.ty) else {
+            println!("{}: unsupported formula", f.name);
+            continue;
+        };
+        println!("{}: {} ({} states)", f.name, if report.holds { "TRUE" } else { "FALSE" }, report.states);
+        if let Some(seq) = report.witness {
+            println!("  {} {:?}", if report.holds { "witness:" } else { "counterexample:" }, names(model, &seq));
+        }
+    }
+}
+
+// Deadlock and EF/AG reachability queries answered on a complete finite prefix
+fn unfolding_check(model: &PTNet, formulas: &[Formula]) {
+    let prefix = match Prefix::build_bounded(model, MAX_PREFIX_EVENTS) {
+        Some(prefix) => prefix,
+        None => {
+            println!("Prefix exceeds {} events or the work to find them, giving up", MAX_PREFIX_EVENTS);
+            return;
+        }
+    };
+    println!("prefix: {} events, {} conditions, {} cut-offs",
+             prefix.events.len(), prefix.conditions.len(), prefix.cutoffs());
+    match prefix.deadlock() {
+        Some(seq) => println!("deadlock: TRUE, reached by {:?}", names(model, &seq)),
+        None => println!("deadlock: FALSE"),
+    }
+    for f in formulas.iter() {
+        let Some((phi, negated)) = reachability_query(&f.ty) else { continue };
+        // AG phi is checked as the negation of EF !phi
+        let witness = prefix.reachable(|m| {
+            eval_state(&phi, model, &prefix.to_config(m)) == Some(!negated)
+        });
+        let res = witness.is_some() != negated;
+        println!("{}: {}", f.name, if res { "TRUE" } else { "FALSE" });
+        if let Some(seq) = witness {
+            println!("  {} {:?}", if negated { "counterexample:" } else { "witness:" }, names(model, &seq));
+        }
+    }
+}
+
+// Reachable set by saturation: state count, place bounds and EF/AG queries
+fn symbolic_check(model: &PTNet, formulas: &[Formula]) {
+    let mut reach = ReachableSet::saturate(model);
+    println!("reachable states: {} ({} MDD nodes)", reach.count(), reach.mdd.len());
+    for p in model.place_indices() {
+        println!("bound of {}: {}", model.places[&p].name, reach.bound(p));
+    }
+    for f in formulas.iter() {
+        let Some((phi, negated)) = reachability_query(&f.ty) else { continue };
+        let Some(sat) = reach.satisfying(&phi, model) else {
+            println!("{}: unsupported atom", f.name);
+            continue;
+        };
+        // EF phi holds iff some reachable marking satisfies phi, AG phi iff all of them do
+        let res = if negated { sat == reach.root } else { sat != EMPTY };
+        println!("{}: {}", f.name, if res { "TRUE" } else { "FALSE" });
+    }
+}
+
+// CTL formulas checked symbolically over the binary encoded reachable markings
+fn bdd_check(model: &PTNet, formulas: &[Formula]) {
+    let mut checker = CtlChecker::new(model);
+    println!("reachable states: {} ({} BDD nodes)", checker.count(checker.reach), checker.bdd.len());
+    println!("deadlock: {}", if checker.dead == FALSE { "FALSE" } else { "TRUE" });
+    for f in formulas.iter() {
+        match checker.check(&f.ty, model) {
+            Some(res) => println!("{}: {}", f.name, if res { "TRUE" } else { "FALSE" }),
+            None => println!("{}: unsupported formula", f.name),
+        }
+    }
+}
+
+// State count, deadlock and EF/AG queries in one breadth-first pass spilling to disk
+fn disk_check(model: &PTNet, formulas: &[Formula], memory: usize) {
+    let mut queries = Vec::new();
+    for f in formulas.iter() {
+        if let Some((phi, negated)) = reachability_query(&f.ty) {
+            queries.push((f.name.clone(), phi, negated));
+        }
+    }
+    // EF phi holds once some marking satisfies phi, AG phi fails once some marking violates it
+    let mut decided = vec![false; queries.len()];
+    let mut explorer = DiskExplorer::new(model, &env::temp_dir(), memory << 20);
+    let res = explorer.explore(|config| {
+        for (i, (_, phi, negated)) in queries.iter().enumerate() {
+            if !decided[i] && eval_state(phi, model, config) == Some(!negated) {
+                decided[i] = true;
+            }
+        }
+        false
+    });
+    match res {
+        Ok(report) => {
+            println!("reachable states: {} in {} layers", report.states, report.layers);
+            match report.deadlock {
+                Some(config) => println!("deadlock: TRUE, e.g. {:?}", config),
+                None => println!("deadlock: FALSE"),
+            }
+            for (i, (name, _, negated)) in queries.iter().enumerate() {
+                println!("{}: {}", name, if decided[i] != *negated { "TRUE" } else { "FALSE" });
+            }
+        },
+        Err(e) => println!("Disk exploration failed: {}", e),
+    }
+}
+
+// Formulas of all the given files found in the directory, reporting the malformed ones
+fn read_formulas(dir: &Path, files: &[&str]) -> Vec<Formula> {
+    let mut res = Vec::new();
+    for file in files.iter() {
+        let path = dir.join(file);
+        if !path.exists() {
+            continue;
+        }
+        match parse_formulas(path.to_str().unwrap()) {
+            Ok(formulas) => res.extend(formulas),
+            Err(e) => println!("{}: {}", file, e),
+        }
+    }
+    res
+}
+
+// Formulas whose names all resolve against the net, the others are reported and skipped
+fn bound(model: &PTNet, formulas: Vec<Formula>) -> Vec<Formula> {
+    formulas.into_iter()
+        .filter_map(|mut f| match f.bind(model) {
+            Ok(()) => Some(f),
+            Err(e) => {
+                println!("skipping {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+// Print the formulas for another checker, noting the ones it can't take
+fn export_formulas(model: &PTNet, formulas: &[Formula], format: &str) {
+    match format {
+        "xml" => print!("{}", to_mcc_xml(formulas)),
+        "spot" | "ltl2ba" | "nusmv" => {
+            for f in formulas.iter() {
+                let res = match format {
+                    "spot" => to_spot(&f.ty),
+                    "ltl2ba" => to_ltl2ba(&f.ty).map(|(res, atoms)| {
+                        let legend: Vec<String> = atoms.iter().enumerate()
+                            .map(|(i, a)| format!("a{} = {}", i, FormulaTy::Prop(a.clone())))
+                            .collect();
+                        format!("{}\n  where {}", res, legend.join(", "))
+                    }),
+                    _ => to_nusmv(&f.ty, model).map(|res| format!("{}\n", res)),
+                };
+                match (format, res) {
+                    ("nusmv", Some(res)) => print!("-- {}\n{}", f.name, res),
+                    ("nusmv", None) => println!("-- {}: cannot be exported\n", f.name),
+                    (_, Some(res)) => println!("{}: {}", f.name, res),
+                    (_, None) => println!("{}: cannot be exported", f.name),
+                }
+            }
+        },
+        _ => {
+            println!("Unknown export format: {}\n", format);
+            exit(1);
+        }
+    }
+}
+
+// Formulas of a --formula or --props argument, exiting on a syntax error
+fn read_custom(flag: &str, value: Option<&String>) -> Vec<Formula> {
+    let Some(value) = value else {
+        println!("Missing argument to {}\n", flag);
+        exit(1);
+    };
+    let res = if flag == "--formula" {
+        parse_property(value).map(|ty| vec![Formula { name: value.clone(), ty }])
+    }
+    else {
+        parse_props_file(value)
+    };
+    match res {
+        Ok(formulas) => formulas,
+        Err(e) => {
+            println!("Invalid formula:\n{}", e);
+            exit(1);
+        }
     }
 }
 
-fn test(model: &PTNet, formula: &Formula) -> bool {
+fn names(model: &PTNet, seq: &[usize]) -> Vec<String> {
+    seq.iter().map(|t| model.transitions[t].name.clone()).collect()
+}
+
+// CTL on states generated on demand, or on the TranSys built beforehand, labelling all of them at
+// once; path quantifiers range over fair paths only under fairness constraints
+fn test(model: &PTNet, formula: &Formula, on_the_fly: bool, fairness: &[FairConstraint]) -> Option<Witness> {
     use ptchecker::logics::transys::*;
-    let tran = TranSys::from_petri(&model);
-    almc(tran, 0, formula.ty.clone())
-}
\ No newline at end of file
+    if on_the_fly {
+        let space = NetSpace::new(model);
+        return GlobalCtl::new(&space, fairness)?.explain(&formula.ty);
+    }
+    let tran = TranSys::from_petri(model);
+    GlobalCtl::new(&tran, fairness)?.explain(&formula.ty)
+}
+
+fn print_witness(model: &PTNet, w: &Witness, format: Option<&str>) {
+    match format {
+        Some("text") => print!("{}", w.to_text(model)),
+        Some("dot") => print!("{}", w.to_dot(model)),
+        _ => {},
+    }
+}
@@ -1,4 +1,6 @@
 pub mod logics;
 pub mod ltl;
 pub mod petri;
+pub mod store;
+pub mod symbolic;
 pub mod utils;
\ No newline at end of file
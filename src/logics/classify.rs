@@ -0,0 +1,251 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use crate::logics::FormulaTy;
+
+// Most specific logic a formula belongs to, checked in declaration order: AG p is reachability
+// before LTL or CTL, and A X p, in both LTL and CTL, is LTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    // No temporal operator or path quantifier, decided on the initial marking
+    Propositional,
+    // EF phi or AG phi with phi propositional
+    Reachability,
+    // A single outer A over a path formula without quantifiers, past-time operators over pure
+    // past formulas only
+    Ltl,
+    // Every temporal operator directly under a quantifier, and every quantifier directly over one
+    Ctl,
+    CtlStar,
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Class::Propositional => "propositional",
+            Class::Reachability => "reachability",
+            Class::Ltl => "LTL",
+            Class::Ctl => "CTL",
+            Class::CtlStar => "CTL*",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Syntactic fragments of the path formula under the outer quantifier. They are sufficient
+// conditions: a formula outside a fragment may still define such a property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fragments {
+    // Violations have a finite bad prefix: only G, R and X once negations are pushed to the atoms
+    pub safety: bool,
+    // Satisfaction has a finite good prefix: only F, U and X in negation normal form
+    pub cosafety: bool,
+    // No X, so the property can't tell stuttering-equivalent runs apart
+    pub stutter_insensitive: bool,
+}
+
+impl fmt::Display for Fragments {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = [(self.safety, "safety"), (self.cosafety, "co-safety"), (self.stutter_insensitive, "stutter-insensitive")]
+            .iter()
+            .filter(|(holds, _)| *holds)
+            .map(|(_, name)| *name)
+            .collect();
+        write!(f, "{}", if names.is_empty() { String::from("none") } else { names.join(", ") })
+    }
+}
+
+pub fn classify(f: &FormulaTy) -> Class {
+    if is_propositional(f) {
+        Class::Propositional
+    }
+    else if is_reachability(f) {
+        Class::Reachability
+    }
+    else if is_ltl(f) {
+        Class::Ltl
+    }
+    else if is_ctl(f) {
+        Class::Ctl
+    }
+    else {
+        Class::CtlStar
+    }
+}
+
+// Fragments of a propositional, reachability or LTL formula, None for branching-time ones
+pub fn fragments(f: &FormulaTy) -> Option<Fragments> {
+    let path = match classify(f) {
+        Class::Propositional => f,
+        Class::Reachability | Class::Ltl => match f {
+            FormulaTy::Forall(inner) | FormulaTy::Exists(inner) => inner,
+            _ => return None,
+        },
+        Class::Ctl | Class::CtlStar => return None,
+    };
+    Some(Fragments {
+        safety: is_safety(path, true),
+        cosafety: is_safety(path, false),
+        stutter_insensitive: is_stutter_insensitive(path),
+    })
+}
+
+pub fn is_propositional(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::True | FormulaTy::False | FormulaTy::Prop(_) | FormulaTy::Neg(_) => true,
+        FormulaTy::Not(inner) => is_propositional(inner),
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs) => is_propositional(lhs) && is_propositional(rhs),
+        _ => false,
+    }
+}
+
+pub fn is_reachability(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::Exists(inner) => matches!(&**inner, FormulaTy::Finally(phi) if is_propositional(phi)),
+        FormulaTy::Forall(inner) => matches!(&**inner, FormulaTy::Global(phi) if is_propositional(phi)),
+        _ => false,
+    }
+}
+
+pub fn is_ltl(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::Forall(inner) => is_path(inner),
+        _ => false,
+    }
+}
+
+pub fn is_ctl(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::True | FormulaTy::False | FormulaTy::Prop(_) | FormulaTy::Neg(_) => true,
+        FormulaTy::Not(inner) => is_ctl(inner),
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs) => is_ctl(lhs) && is_ctl(rhs),
+        FormulaTy::Forall(inner) | FormulaTy::Exists(inner) => match &**inner {
+            FormulaTy::Next(arg) | FormulaTy::Global(arg) | FormulaTy::Finally(arg) => is_ctl(arg),
+            FormulaTy::Until(lhs, rhs) | FormulaTy::Release(lhs, rhs)
+            | FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) => is_ctl(lhs) && is_ctl(rhs),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Formulas without X or Y, whose truth survives adding or removing repeated states
+pub fn is_stutter_insensitive(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::Next(_) | FormulaTy::Yesterday(_) => false,
+        FormulaTy::Not(inner) | FormulaTy::Global(inner) | FormulaTy::Finally(inner)
+        | FormulaTy::Forall(inner) | FormulaTy::Exists(inner)
+        | FormulaTy::Once(inner) | FormulaTy::Historically(inner) => is_stutter_insensitive(inner),
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs) | FormulaTy::Until(lhs, rhs)
+        | FormulaTy::Release(lhs, rhs) | FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs)
+        | FormulaTy::Since(lhs, rhs) => is_stutter_insensitive(lhs) && is_stutter_insensitive(rhs),
+        _ => true,
+    }
+}
+
+// Pure past formulas: atoms under boolean connectives and past-time operators only, decided
+// by the prefix of the run up to the current position
+pub fn is_past(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::True | FormulaTy::False | FormulaTy::Prop(_) | FormulaTy::Neg(_) => true,
+        FormulaTy::Not(inner) | FormulaTy::Yesterday(inner) | FormulaTy::Once(inner) | FormulaTy::Historically(inner) => is_past(inner),
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs) | FormulaTy::Since(lhs, rhs) => is_past(lhs) && is_past(rhs),
+        _ => false,
+    }
+}
+
+// Temporal operators and boolean connectives over state formulas, no quantifier
+fn is_path(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::True | FormulaTy::False | FormulaTy::Prop(_) | FormulaTy::Neg(_) => true,
+        FormulaTy::Not(inner) | FormulaTy::Next(inner) | FormulaTy::Global(inner) | FormulaTy::Finally(inner) => is_path(inner),
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs)
+        | FormulaTy::Until(lhs, rhs) | FormulaTy::Release(lhs, rhs)
+        | FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) => is_path(lhs) && is_path(rhs),
+        FormulaTy::Yesterday(_) | FormulaTy::Since(_, _) | FormulaTy::Once(_) | FormulaTy::Historically(_) => is_past(f),
+        FormulaTy::Forall(_) | FormulaTy::Exists(_) => false,
+    }
+}
+
+// Syntactic safety of f under the given polarity; co-safety of f is safety of its negation.
+// G, R and W stay safe when positive, F, U and M when negated, since !F a = G !a, !(a U b) = !a R !b
+// and !(a M b) = !a W !b.
+// Pure past formulas are decided at the current position, like atoms.
+fn is_safety(f: &FormulaTy, positive: bool) -> bool {
+    match f {
+        FormulaTy::True | FormulaTy::False | FormulaTy::Prop(_) | FormulaTy::Neg(_) => true,
+        FormulaTy::Not(inner) => is_safety(inner, !positive),
+        FormulaTy::Next(inner) => is_safety(inner, positive),
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs) => is_safety(lhs, positive) && is_safety(rhs, positive),
+        FormulaTy::Global(inner) => positive && is_safety(inner, positive),
+        FormulaTy::Finally(inner) => !positive && is_safety(inner, positive),
+        FormulaTy::Release(lhs, rhs) | FormulaTy::WeakUntil(lhs, rhs) => positive && is_safety(lhs, positive) && is_safety(rhs, positive),
+        FormulaTy::Until(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) => !positive && is_safety(lhs, positive) && is_safety(rhs, positive),
+        FormulaTy::Yesterday(_) | FormulaTy::Since(_, _) | FormulaTy::Once(_) | FormulaTy::Historically(_) => is_past(f),
+        FormulaTy::Forall(_) | FormulaTy::Exists(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::Formula;
+    use crate::logics::props::parse_property;
+    use crate::petri::stubborn::Reduction;
+    use crate::petri::test_net;
+
+    // Class and (safety, co-safety, stutter-insensitive) of each formula
+    #[test]
+    fn classes_and_fragments() {
+        let table = [
+            ("p >= 1", Class::Propositional, Some((true, true, true))),
+            ("!(p >= 1 & fireable(t))", Class::Propositional, Some((true, true, true))),
+            ("E F p >= 1", Class::Reachability, Some((false, true, true))),
+            ("A G p >= 1", Class::Reachability, Some((true, false, true))),
+            ("A G (p >= 1 -> F q >= 1)", Class::Ltl, Some((false, false, true))),
+            ("A (p >= 1 U q >= 1)", Class::Ltl, Some((false, true, true))),
+            ("A (p >= 1 W q >= 1)", Class::Ltl, Some((true, false, true))),
+            ("A !(p >= 1 U q >= 1)", Class::Ltl, Some((true, false, true))),
+            ("A X p >= 1", Class::Ltl, Some((true, true, false))),
+            ("A G (p >= 1 -> X q >= 1)", Class::Ltl, Some((true, false, false))),
+            ("A F G X p >= 1", Class::Ltl, Some((false, false, false))),
+            ("A G (Y p >= 1)", Class::Ltl, Some((true, false, false))),
+            ("A F (O (Y p >= 1))", Class::Ltl, Some((false, true, false))),
+            ("A G (p >= 1 S q >= 1)", Class::Ltl, Some((true, false, true))),
+            ("A F (H p >= 1)", Class::Ltl, Some((false, true, true))),
+            ("E (p >= 1 U q >= 1)", Class::Ctl, None),
+            ("A G E F p >= 1", Class::Ctl, None),
+            ("A G p >= 1 & E X q >= 1", Class::Ctl, None),
+            ("E G F p >= 1", Class::CtlStar, None),
+            ("A G F p >= 1 | E F q >= 1", Class::CtlStar, None),
+            ("A F (Y (E F p >= 1))", Class::CtlStar, None),
+        ];
+        for (text, class, expected) in table {
+            let f = parse_property(text).unwrap();
+            assert_eq!(classify(&f), class, "{}", text);
+            let found = fragments(&f).map(|fr| (fr.safety, fr.cosafety, fr.stutter_insensitive));
+            assert_eq!(found, expected, "{}", text);
+        }
+    }
+
+    // X and Y anywhere in the formula rule out stubborn set reduction, which may drop stuttering steps
+    #[test]
+    fn next_and_yesterday_are_stutter_sensitive() {
+        let net = test_net(&[("p", 1), ("q", 0)], &[("t", &["p"], &["q"])]);
+        for (text, insensitive) in [
+            ("A G F p >= 1", true),
+            ("A (p >= 1 U (q >= 1 R O p >= 1))", true),
+            ("A X p >= 1", false),
+            ("A G (p >= 1 | X q >= 1)", false),
+            ("A (p >= 1 U X q >= 1)", false),
+            ("A F (H (Y p >= 1))", false),
+            ("A G (p >= 1 S Y q >= 1)", false),
+            ("A (p >= 1 W (q >= 1 M X p >= 1))", false),
+        ] {
+            let mut f = Formula { name: String::from(text), ty: parse_property(text).unwrap() };
+            f.bind(&net).unwrap();
+            assert_eq!(is_stutter_insensitive(&f.ty), insensitive, "{}", text);
+            assert_eq!(Reduction::for_formula(&net, &f.ty) == Reduction::Off, !insensitive, "{}", text);
+        }
+    }
+}
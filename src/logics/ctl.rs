@@ -1,241 +1,687 @@
-use std::collections::{HashSet, HashMap};
-use crate::logics::{FormulaTy, PTAtom};
-use crate::logics::transys::{State, TranSys};
+use std::collections::{HashSet, HashMap, VecDeque};
+use crate::logics::FormulaTy;
+use crate::logics::intern::{FormulaId, FormulaStore, Node};
+use crate::logics::space::StateSpace;
+use crate::logics::transys::Config;
+use crate::petri::PTNet;
+use crate::utils::sccs;
 
+// Büchi fairness constraint: fair paths go through states satisfying the formula, or fire one of
+// the transitions, infinitely often
+#[derive(Debug, Clone)]
+pub enum FairConstraint {
+    States(FormulaTy),
+    Fires(Vec<usize>),
+}
+
+impl FairConstraint {
+    // Constraint over the named transitions, or the first name that isn't one of the net
+    pub fn fires(names: &[String], net: &PTNet) -> Result<FairConstraint, String> {
+        let mut transitions = Vec::new();
+        for name in names.iter() {
+            match net.index_map.get_by_left(name) {
+                Some(t) if net.transitions.contains_key(t) => transitions.push(*t),
+                _ => return Err(name.clone()),
+            }
+        }
+        Ok(FairConstraint::Fires(transitions))
+    }
+}
+
+// Path formula under a quantifier, with its operands labelled
+enum Path {
+    Next(Vec<bool>),
+    Finally(Vec<bool>),
+    Global(Vec<bool>),
+    Until(Vec<bool>, Vec<bool>),
+    Release(Vec<bool>, Vec<bool>),
+    WeakUntil(Vec<bool>, Vec<bool>),
+    StrongRelease(Vec<bool>, Vec<bool>),
+}
 
-pub fn visit_eu(T: &TranSys, s: State, f: FormulaTy, info: &mut HashMap<(State, String), bool>, marks: & mut HashSet<State>) {
-    marks.insert(s);
-    let fc = f.clone();
-    if info.contains_key(&(s, f.to_string())) {
-        if let Some(true) = info.get(&(s, f.to_string())) {
-            info.insert((s, f.to_string()), true);
+// Path as states, fired transitions and the position a lasso loops back to, with the
+// (position, subformula) pairs holding along it
+type PathEvidence = (Vec<usize>, Vec<usize>, Option<usize>, Vec<(usize, FormulaId)>);
+
+// CTL labelling all reachable states at once, innermost subformulas first, with EX, EU and EG
+// as the primitives and the other operators read through them. Path quantifiers range over fair
+// paths: the maximal ones without constraints, as for the BDD engine, else the infinite ones
+// meeting every constraint infinitely often.
+pub struct GlobalCtl<'a, S: StateSpace> {
+    space: &'a S,
+    store: FormulaStore,
+    // Reachable states numbered densely from the initial one, with (transition, state) edges out
+    // and predecessors in
+    states: Vec<S::State>,
+    succ: Vec<Vec<(usize, usize)>>,
+    pred: Vec<Vec<usize>>,
+    fair_states: Vec<Vec<bool>>,
+    fair_fires: Vec<Vec<usize>>,
+    // States where a fair path starts, E_f G true
+    fair: Vec<bool>,
+    labels: HashMap<FormulaId, Vec<bool>>,
+}
+
+impl<'a, S: StateSpace> GlobalCtl<'a, S> {
+    // None when a constraint formula isn't a CTL state formula the engine handles
+    pub fn new(space: &'a S, constraints: &[FairConstraint]) -> Option<GlobalCtl<'a, S>> {
+        let mut index = HashMap::new();
+        let mut states = vec![space.initial()];
+        let mut succ: Vec<Vec<(usize, usize)>> = vec![Vec::new()];
+        index.insert(space.initial(), 0);
+        let mut i = 0;
+        while i < states.len() {
+            for (t, s1) in space.successors(&states[i]) {
+                let j = *index.entry(s1.clone()).or_insert_with(|| {
+                    states.push(s1);
+                    succ.push(Vec::new());
+                    states.len() - 1
+                });
+                succ[i].push((t, j));
+            }
+            i += 1;
         }
-    } else if let FormulaTy::Until(f1, f2) = fc {
-        check(T, s, *f2.clone(), info);
-        if let Some(true) = info.get(&(s, f2.to_string())) {
-            info.insert((s, f.to_string()), true);
+        let mut pred = vec![Vec::new(); states.len()];
+        for (i, edges) in succ.iter().enumerate() {
+            for (_, j) in edges.iter() {
+                pred[*j].push(i);
+            }
+        }
+        let all = vec![true; states.len()];
+        let mut res = GlobalCtl {
+            space,
+            store: FormulaStore::new(),
+            states,
+            succ,
+            pred,
+            fair_states: Vec::new(),
+            fair_fires: Vec::new(),
+            fair: all,
+            labels: HashMap::new(),
+        };
+        // Constraint formulas are labelled over all maximal paths
+        let mut fair_states = Vec::new();
+        for c in constraints.iter() {
+            match c {
+                FairConstraint::States(f) => {
+                    let id = res.store.intern(f);
+                    fair_states.push(res.label(id)?);
+                },
+                FairConstraint::Fires(transitions) => res.fair_fires.push(transitions.clone()),
+            }
         }
+        res.fair_states = fair_states;
+        res.labels.clear();
+        res.fair = res.eg(&vec![true; res.states.len()]);
+        Some(res)
+    }
 
-        check(T, s,*f1.clone(), info);
-        if let Some(false) = info.get(&(s, f1.to_string())) {
-            return;
+    // Truth at the initial state, None for formulas outside CTL
+    pub fn holds(&mut self, f: &FormulaTy) -> Option<bool> {
+        Some(self.sat(f)?.contains(&self.states[0]))
+    }
+
+    // States satisfying the formula, None for formulas outside CTL
+    pub fn sat(&mut self, f: &FormulaTy) -> Option<HashSet<S::State>> {
+        let id = self.store.intern(f);
+        let sat = self.label(id)?;
+        Some(self.states.iter().zip(sat.iter()).filter(|(_, b)| **b).map(|(s, _)| s.clone()).collect())
+    }
+
+    fn label(&mut self, id: FormulaId) -> Option<Vec<bool>> {
+        if let Some(sat) = self.labels.get(&id) {
+            return Some(sat.clone());
         }
+        let res = match self.store.node(id).clone() {
+            Node::True => vec![true; self.states.len()],
+            Node::False => vec![false; self.states.len()],
+            Node::Prop(a) => self.states.iter().map(|s| self.space.eval(s, &a)).collect::<Option<_>>()?,
+            Node::Neg(a) => self.states.iter().map(|s| self.space.eval(s, &a).map(|b| !b)).collect::<Option<_>>()?,
+            Node::Not(f) => not(&self.label(f)?),
+            Node::And(f1, f2) => and(&self.label(f1)?, &self.label(f2)?),
+            Node::Or(f1, f2) => or(&self.label(f1)?, &self.label(f2)?),
+            Node::Exists(inner) => {
+                let path = self.path(inner)?;
+                self.exists(&path)
+            },
+            // A phi = !E !phi
+            Node::Forall(inner) => {
+                let dual = self.negate_path(inner)?;
+                let dual = self.store.mk(Node::Exists(dual));
+                not(&self.label(dual)?)
+            },
+            _ => return None,
+        };
+        self.labels.insert(id, res.clone());
+        Some(res)
+    }
+
+    fn path(&mut self, id: FormulaId) -> Option<Path> {
+        let res = match self.store.node(id).clone() {
+            Node::Next(f) => Path::Next(self.label(f)?),
+            Node::Finally(f) => Path::Finally(self.label(f)?),
+            Node::Global(f) => Path::Global(self.label(f)?),
+            Node::Until(f1, f2) => Path::Until(self.label(f1)?, self.label(f2)?),
+            Node::Release(f1, f2) => Path::Release(self.label(f1)?, self.label(f2)?),
+            Node::WeakUntil(f1, f2) => Path::WeakUntil(self.label(f1)?, self.label(f2)?),
+            Node::StrongRelease(f1, f2) => Path::StrongRelease(self.label(f1)?, self.label(f2)?),
+            _ => return None,
+        };
+        Some(res)
+    }
 
-        if let Some(ref set) = T.states_from(&s) {
-            for s1 in &**set {
-                if ! marks.contains(&s1) {
-                    visit_eu(T, *s1, f.clone(), info, marks);
+    // The path formula holding exactly on the paths where this one fails
+    fn negate_path(&mut self, id: FormulaId) -> Option<FormulaId> {
+        let node = match self.store.node(id).clone() {
+            Node::Next(a) => Node::Next(self.store.mk(Node::Not(a))),
+            Node::Finally(a) => Node::Global(self.store.mk(Node::Not(a))),
+            Node::Global(a) => Node::Finally(self.store.mk(Node::Not(a))),
+            // !(a U b) = !b W (!a & !b), !(a W b) = !b U (!a & !b)
+            Node::Until(a, b) | Node::WeakUntil(a, b) => {
+                let (not_a, not_b) = (self.store.mk(Node::Not(a)), self.store.mk(Node::Not(b)));
+                let stop = self.store.mk(Node::And(not_a, not_b));
+                if let Node::Until(_, _) = self.store.node(id) { Node::WeakUntil(not_b, stop) } else { Node::Until(not_b, stop) }
+            },
+            // !(a R b) = !a U !b, !(a M b) = !a W !b
+            Node::Release(a, b) => Node::Until(self.store.mk(Node::Not(a)), self.store.mk(Node::Not(b))),
+            Node::StrongRelease(a, b) => Node::WeakUntil(self.store.mk(Node::Not(a)), self.store.mk(Node::Not(b))),
+            _ => return None,
+        };
+        Some(self.store.mk(node))
+    }
+
+    // States starting a fair path of the path formula. A path satisfying a U b, a R b, ... only
+    // needs to be fair from the state where the formula is settled, so the target of EX and EU is
+    // narrowed to the states starting a fair path.
+    fn exists(&self, path: &Path) -> Vec<bool> {
+        let all = vec![true; self.states.len()];
+        match path {
+            Path::Next(a) => self.ex(&and(a, &self.fair)),
+            Path::Finally(a) => self.eu(&all, &and(a, &self.fair)),
+            Path::Global(a) => self.eg(a),
+            Path::Until(a, b) => self.eu(a, &and(b, &self.fair)),
+            // a W b = a U b | G a
+            Path::WeakUntil(a, b) => or(&self.eu(a, &and(b, &self.fair)), &self.eg(a)),
+            // a M b = b U (a & b), a R b = a M b | G b
+            Path::StrongRelease(a, b) => self.eu(b, &and(&and(a, b), &self.fair)),
+            Path::Release(a, b) => or(&self.eu(b, &and(&and(a, b), &self.fair)), &self.eg(b)),
+        }
+    }
+
+    // States with a successor in sat
+    fn ex(&self, sat: &[bool]) -> Vec<bool> {
+        self.succ.iter().map(|edges| edges.iter().any(|(_, j)| sat[*j])).collect()
+    }
+
+    // States reaching one of sat2 through states of sat1
+    fn eu(&self, sat1: &[bool], sat2: &[bool]) -> Vec<bool> {
+        let mut res = sat2.to_vec();
+        let mut queue: VecDeque<usize> = (0..res.len()).filter(|i| res[*i]).collect();
+        while let Some(j) = queue.pop_front() {
+            for i in self.pred[j].iter() {
+                if sat1[*i] && !res[*i] {
+                    res[*i] = true;
+                    queue.push_back(*i);
                 }
             }
         }
+        res
     }
-}
 
-pub fn checkEU(T: &TranSys, sr: State, f: FormulaTy, info: &mut HashMap<(State, String), bool>, marks: & mut HashSet<State>) {
-    visit_eu(T, sr, f, info, marks);
-}
+    // States of sat starting a fair path within sat, those reaching inside sat a fair component
+    fn eg(&self, sat: &[bool]) -> Vec<bool> {
+        let (goal, _) = self.fair_components(sat);
+        self.eu(sat, &goal)
+    }
 
-pub fn visit_au(T: &TranSys, s: State, f: FormulaTy, info: &mut HashMap<(State, String), bool>, cp: &mut Vec<State>) {
-    let fc = f.clone();
-    if info.contains_key(&(s, f.to_string())) {
-        if let Some(false) = info.get(&(s, f.to_string())) {
-            for s1 in cp.iter() {
-                info.insert((*s1, f.to_string()), false);
+    // States of the strongly connected components of sat with a cycle meeting every constraint, or
+    // without constraints of the deadlocks of sat too, with the component of each state of sat
+    fn fair_components(&self, sat: &[bool]) -> (Vec<bool>, Vec<usize>) {
+        let components = sccs(sat, |v, i| self.succ[v].get(i).map(|(_, w)| *w));
+        let mut comp = vec![usize::MAX; sat.len()];
+        for (c, scc) in components.iter().enumerate() {
+            for v in scc.iter() {
+                comp[*v] = c;
             }
         }
-    } else if let FormulaTy::Forall(boxed_formula) = fc {
-        if let FormulaTy::Until(f1, f2) = *boxed_formula {
-            check(T, s,*f2.clone(), info);
-            if let Some(true) = info.get(&(s, f2.to_string())) {
-                info.insert((s, f.to_string()), true);
-                return;
+        let mut goal = vec![false; sat.len()];
+        for (c, scc) in components.iter().enumerate() {
+            let fired: HashSet<usize> = scc.iter()
+                .flat_map(|v| self.succ[*v].iter().filter(|(_, w)| comp[*w] == c).map(|(t, _)| *t))
+                .collect();
+            let fair = if scc.len() == 1 && !self.succ[scc[0]].iter().any(|(_, w)| *w == scc[0]) {
+                self.fair_states.is_empty() && self.fair_fires.is_empty() && self.succ[scc[0]].is_empty()
             }
-
-            check(T, s,*f1.clone(), info);
-            if let Some(false) = info.get(&(s,f1.to_string())) {
-                info.insert((s, f.to_string()), false);
-                for s1 in cp.iter() {
-                    info.insert((*s1, f.to_string()), false);
+            else {
+                self.fair_states.iter().all(|f| scc.iter().any(|v| f[*v]))
+                    && self.fair_fires.iter().all(|f| f.iter().any(|t| fired.contains(t)))
+            };
+            if fair {
+                for v in scc.iter() {
+                    goal[*v] = true;
                 }
-                return;
             }
+        }
+        (goal, comp)
+    }
+
+    // Evidence for the value of the formula at the initial state, None for formulas outside CTL
+    pub fn explain(&mut self, f: &FormulaTy) -> Option<Witness> {
+        let id = self.store.intern(f);
+        self.label(id)?;
+        Some(self.explain_at(id, 0))
+    }
 
-            cp.push(s);
-            if let Some(&ref set) = T.states_from(&s) {
-                for s1 in set {
-                    if !cp.contains(&s1) {
-                        visit_au(T, *s1, f.clone(), info, cp);
-                    } else {
-                        for s1 in cp.iter() {
-                            info.insert((*s1, f.to_string()), false);
+    // Witness of a labelled formula at a state: the path of a true E or a false A formula, and the
+    // subformulas with quantifiers that decide it
+    fn explain_at(&mut self, id: FormulaId, s: usize) -> Witness {
+        let holds = self.labels[&id][s];
+        let mut res = Witness {
+            formula: self.store.to_formula(id),
+            holds,
+            markings: Vec::new(),
+            fired: Vec::new(),
+            loop_to: None,
+            children: Vec::new(),
+        };
+        let mut path = vec![s];
+        let mut decide = Vec::new();
+        match self.store.node(id).clone() {
+            Node::Not(f) => decide.push((0, f)),
+            Node::And(f1, f2) | Node::Or(f1, f2) => {
+                // Both operands when both are needed, else one with the value of the whole
+                let both = holds == matches!(self.store.node(id), Node::And(_, _));
+                for f in [f1, f2] {
+                    if both || self.labels[&f][s] == holds {
+                        decide.push((0, f));
+                        if !both {
+                            break;
                         }
                     }
                 }
+            },
+            Node::Exists(inner) if holds => {
+                (path, res.fired, res.loop_to, decide) = self.exists_path(inner, s);
+            },
+            Node::Forall(inner) if !holds => {
+                let dual = self.negate_path(inner).unwrap();
+                (path, res.fired, res.loop_to, decide) = self.exists_path(dual, s);
+            },
+            _ => {},
+        }
+        res.markings = path.iter().map(|v| self.space.marking(&self.states[*v])).collect();
+        for (i, mut f) in decide {
+            // The evidence for !f is the one for f
+            while let Node::Not(inner) = *self.store.node(f) {
+                f = inner;
+            }
+            if self.quantified(f) {
+                let child = self.explain_at(f, path[i]);
+                res.children.push((i, child));
             }
-            info.insert((s, f.to_string()), true);
-            cp.pop();
         }
+        res
     }
-}
 
+    fn quantified(&self, id: FormulaId) -> bool {
+        self.store.subformulas(id).iter().any(|f| matches!(self.store.node(*f), Node::Exists(_) | Node::Forall(_)))
+    }
 
-pub fn checkAU(T: &TranSys, sr: State, f: FormulaTy, info: &mut HashMap<(State, String), bool>) {
-    let mut cp = Vec::new();
-    visit_au(T, sr, f, info, &mut cp);
-}
+    // Fair path from s satisfying the labelled path formula
+    fn exists_path(&self, id: FormulaId, s: usize) -> PathEvidence {
+        let label = |f: FormulaId| &self.labels[&f];
+        let all = vec![true; self.states.len()];
+        // Until over the masks, with the subformulas holding before the end and at it
+        let until = |through: &[bool], goal: &[bool], before: Vec<FormulaId>, end: Vec<FormulaId>| {
+            let (path, fired) = self.reach(s, through, &and(goal, &self.fair));
+            let mut decide = Vec::new();
+            for i in 0..path.len() {
+                let fs = if i + 1 == path.len() { &end } else { &before };
+                decide.extend(fs.iter().map(|f| (i, *f)));
+            }
+            (path, fired, None, decide)
+        };
+        let global = |a: FormulaId| {
+            let (path, fired, loop_to) = self.lasso(s, label(a));
+            let decide = (0..path.len()).map(|i| (i, a)).collect();
+            (path, fired, loop_to, decide)
+        };
+        match self.store.node(id).clone() {
+            Node::Next(a) => {
+                let (t, j) = *self.succ[s].iter().find(|(_, j)| label(a)[*j] && self.fair[*j]).unwrap();
+                (vec![s, j], vec![t], None, vec![(1, a)])
+            },
+            Node::Finally(b) => until(&all, label(b), vec![], vec![b]),
+            Node::Global(a) => global(a),
+            Node::Until(a, b) => until(label(a), label(b), vec![a], vec![b]),
+            Node::WeakUntil(a, b) => {
+                if self.eu(label(a), &and(label(b), &self.fair))[s] { until(label(a), label(b), vec![a], vec![b]) } else { global(a) }
+            },
+            Node::StrongRelease(a, b) => until(label(b), &and(label(a), label(b)), vec![b], vec![a, b]),
+            Node::Release(a, b) => {
+                let goal = and(label(a), label(b));
+                if self.eu(label(b), &and(&goal, &self.fair))[s] { until(label(b), &goal, vec![b], vec![a, b]) } else { global(b) }
+            },
+            _ => unreachable!(),
+        }
+    }
 
-pub fn simplify(f: FormulaTy) -> FormulaTy {
-    match f {
-        FormulaTy::Not(f) => {
-            return FormulaTy::Not(Box::new(simplify(*f)));
+    // Shortest path from s to a state of goal through states of through, as states and fired
+    // transitions; the labels guarantee there is one
+    fn reach(&self, s: usize, through: &[bool], goal: &[bool]) -> (Vec<usize>, Vec<usize>) {
+        let mut parent = vec![None; self.states.len()];
+        parent[s] = Some((s, 0));
+        let mut queue = VecDeque::from([s]);
+        while let Some(v) = queue.pop_front() {
+            if goal[v] {
+                let (mut path, mut fired) = (vec![v], Vec::new());
+                let mut at = v;
+                while at != s {
+                    let (u, t) = parent[at].unwrap();
+                    path.push(u);
+                    fired.push(t);
+                    at = u;
+                }
+                path.reverse();
+                fired.reverse();
+                return (path, fired);
+            }
+            if !through[v] {
+                continue;
+            }
+            for (t, w) in self.succ[v].iter() {
+                if parent[*w].is_none() {
+                    parent[*w] = Some((v, *t));
+                    queue.push_back(*w);
+                }
+            }
         }
-        FormulaTy::Or(f1, f2) => {
-            return FormulaTy::Or(Box::new(simplify(*f1)), Box::new(simplify(*f2)));
+        unreachable!()
+    }
+
+    // Fair path from s within sat: to a fair component, then around a cycle of it meeting every
+    // constraint back to the state it was entered at, or to a deadlock without constraints
+    fn lasso(&self, s: usize, sat: &[bool]) -> (Vec<usize>, Vec<usize>, Option<usize>) {
+        let (goal, comp) = self.fair_components(sat);
+        let (mut path, mut fired) = self.reach(s, sat, &goal);
+        let entry = *path.last().unwrap();
+        if self.succ[entry].is_empty() {
+            return (path, fired, None);
         }
-        FormulaTy::And(f1, f2) => {
-            return FormulaTy::And(Box::new(simplify(*f1)), Box::new(simplify(*f2)));
+        let loop_to = path.len() - 1;
+        let inside: Vec<bool> = comp.iter().map(|c| *c == comp[entry]).collect();
+        let walk = |path: &mut Vec<usize>, fired: &mut Vec<usize>, goal: &[bool]| {
+            let (steps, ts) = self.reach(*path.last().unwrap(), &inside, goal);
+            path.extend(&steps[1..]);
+            fired.extend(ts);
+        };
+        for f in self.fair_states.iter() {
+            walk(&mut path, &mut fired, &and(f, &inside));
         }
-        FormulaTy::Next(f) => {
-            return FormulaTy::Next(Box::new(simplify(*f)));
+        for f in self.fair_fires.iter() {
+            let fires = |v: usize| self.succ[v].iter().find(|(t, w)| inside[*w] && f.contains(t));
+            let source: Vec<bool> = (0..sat.len()).map(|v| inside[v] && fires(v).is_some()).collect();
+            walk(&mut path, &mut fired, &source);
+            let (t, w) = *fires(*path.last().unwrap()).unwrap();
+            path.push(w);
+            fired.push(t);
         }
-        FormulaTy::Global(f1) => {
-            return FormulaTy::Until(Box::new(FormulaTy::False), f1);
+        // Around the cycle over at least one edge
+        if path.len() - 1 == loop_to {
+            let (t, w) = *self.succ[entry].iter().find(|(_, w)| inside[*w]).unwrap();
+            path.push(w);
+            fired.push(t);
+        }
+        let back: Vec<bool> = (0..sat.len()).map(|v| v == entry).collect();
+        walk(&mut path, &mut fired, &back);
+        path.pop();
+        (path, fired, Some(loop_to))
+    }
+}
+
+// Evidence for the value of a CTL formula at a state. A true E formula or a false A formula comes
+// with a path from the state: fired[i] leads from markings[i] to the next marking, or for a lasso
+// the last one back to markings[loop_to]. Children give the evidence for the subformulas with
+// quantifiers that decide the value, at positions of the path.
+#[derive(Debug, Clone)]
+pub struct Witness {
+    pub formula: FormulaTy,
+    pub holds: bool,
+    pub markings: Vec<Config>,
+    pub fired: Vec<usize>,
+    pub loop_to: Option<usize>,
+    pub children: Vec<(usize, Witness)>,
+}
+
+impl Witness {
+    pub fn to_text(&self, net: &PTNet) -> String {
+        let mut out = String::new();
+        self.write_text(net, "", &mut out);
+        out
+    }
+
+    fn write_text(&self, net: &PTNet, indent: &str, out: &mut String) {
+        out.push_str(&format!("{}: {}\n", self.formula, if self.holds { "TRUE" } else { "FALSE" }));
+        for (i, m) in self.markings.iter().enumerate() {
+            out.push_str(&format!("{}  {}: {}\n", indent, i, marking_text(net, m, " ")));
+            if let Some(t) = self.fired.get(i) {
+                let to = if i + 1 == self.markings.len() { format!(", back to {}", self.loop_to.unwrap()) } else { String::new() };
+                out.push_str(&format!("{}    {}{}\n", indent, net.transitions[t].name, to));
+            }
         }
-        FormulaTy::Finally(f1) => {
-            return FormulaTy::Until(Box::new(FormulaTy::True), f1);
+        for (i, child) in self.children.iter() {
+            out.push_str(&format!("{}  at {}, ", indent, i));
+            child.write_text(net, &format!("{}    ", indent), out);
         }
-        FormulaTy::Until(f1, f2) => {
-            return FormulaTy::Until(Box::new(simplify(*f1)), Box::new(simplify(*f2)));
+    }
+
+    // Graph of the path markings, with the fired transitions on the edges and dashed edges to
+    // the paths of the subformulas
+    pub fn to_dot(&self, net: &PTNet) -> String {
+        let mut out = String::from("digraph witness {\n  node [shape=box];\n");
+        out.push_str(&format!("  label=\"{}\";\n", dot_escape(&self.title())));
+        self.write_dot(net, &mut 0, &mut out);
+        out.push_str("}\n");
+        out
+    }
+
+    fn title(&self) -> String {
+        format!("{}: {}", self.formula, if self.holds { "TRUE" } else { "FALSE" })
+    }
+
+    // Nodes are named after the witness number and the path position, returns the witness number
+    fn write_dot(&self, net: &PTNet, next: &mut usize, out: &mut String) -> usize {
+        let w = *next;
+        *next += 1;
+        for (i, m) in self.markings.iter().enumerate() {
+            out.push_str(&format!("  n{}_{} [label=\"{}\"];\n", w, i, dot_escape(&marking_text(net, m, "\n"))));
         }
-        FormulaTy::Forall(f) => {
-            return FormulaTy::Forall(Box::new(simplify(*f)));
+        for (i, t) in self.fired.iter().enumerate() {
+            let to = if i + 1 == self.markings.len() { self.loop_to.unwrap() } else { i + 1 };
+            out.push_str(&format!("  n{}_{} -> n{}_{} [label=\"{}\"];\n", w, i, w, to, dot_escape(&net.transitions[t].name)));
         }
-        FormulaTy::Exists(f) => {
-            return FormulaTy::Exists(Box::new(simplify(*f)));
+        for (i, child) in self.children.iter() {
+            let c = child.write_dot(net, next, out);
+            out.push_str(&format!("  n{}_{} -> n{}_0 [style=dashed, label=\"{}\"];\n", w, i, c, dot_escape(&child.title())));
         }
-        _ => return f
+        w
     }
 }
 
-pub fn check(T: &TranSys, s: State, f: FormulaTy, info: &mut HashMap<(State, String), bool>) {
-    let fc = f.clone();
-    if let None = info.get(&(s, f.to_string())) {
-        match fc {
-            FormulaTy:: True => {
-                info.insert((s, f.to_string()), true);
-            }
-            FormulaTy:: False => {
-                info.insert((s, f.to_string()), false);
-            }
-            FormulaTy::Prop(f1) => {
-                let name = match f1 {
-                    PTAtom::Fireability(name) => {name}
-                    _ => { panic!("Cardinality not supported") }
-                };
-                if let Some(&ref set) = T.label_of(&name) {
-                    info.insert((s, f.to_string()),
-                                if set.contains(&s) { true } else { false }
-                    );
-                }
-            }
-            FormulaTy::Not(f1) => {
-                check(T, s, *f1.clone(), info);
-                if let Some(&b) = info.get(&(s,f1.to_string())) {
-                    info.insert((s, f.to_string()), !b);
-                }
-            }
-            FormulaTy::Or(f1, f2) => {
-                check(T, s, *f1.clone(), info);
-                if let Some(&b) = info.get(&(s,f1.to_string())) {
-                    if b {
-                        info.insert((s, f.to_string()), true);
-                    } else {
-                        check(T, s, *f2.clone(), info);
-                        if let Some(&b) = info.get(&(s, f2.to_string())) {
-                            info.insert((s, f.to_string()), b);
-                        }
-                    }
-                }
-            }
-            FormulaTy::And(f1, f2) => {
-                check(T, s, *f1.clone(), info);
-                if let Some(&b) = info.get(&(s,f1.to_string())) {
-                    if b {
-                        check(T, s, *f2.clone(), info);
-                        if let Some(&b) = info.get(&(s, f2.to_string())) {
-                            info.insert((s, f.to_string()), b);
-                        }
-                    } else {
-                        info.insert((s, f.to_string()), false);
-                    }
-                }
-            }
-            FormulaTy::Next(f1) => {
-                if let Some(&ref set) = T.states_from(&s) {
-                    for s1 in set {
-                        check(T, *s1, *f1.clone(), info);
-                        if let Some(&b) = info.get(&(*s1, f1.to_string())) {
-                            info.insert((s, f.to_string()), true);
-                            return;
-                        }
-                    }
-                }
-                info.insert((s, f.to_string()), false);
-            }
+// Marked places by name, in index order, omega counts as w
+fn marking_text(net: &PTNet, m: &Config, sep: &str) -> String {
+    let mut places: Vec<(&usize, &usize)> = m.iter().filter(|(_, n)| **n > 0).collect();
+    places.sort();
+    if places.is_empty() {
+        return String::from("(empty)");
+    }
+    places.iter()
+        .map(|(p, n)| {
+            let name = &net.places[p].name;
+            if **n == usize::MAX { format!("{}=w", name) } else { format!("{}={}", name, n) }
+        })
+        .collect::<Vec<_>>()
+        .join(sep)
+}
 
-            FormulaTy::Forall(f1) => {
-                match *f1 {
-                    FormulaTy::Next(f2) => {
-                        if let Some(&ref set) = T.states_from(&s) {
-                            for s1 in set {
-                                check(T, *s1,*f2.clone(), info);
-                                if let Some(false) = info.get(&(*s1, f2.to_string())) {
-                                    info.insert((s, f.to_string()), false);
-                                    return;
-                                }
-                            }
-                            info.insert((s, f.to_string()), true);
-                        }
-                    }
-                    FormulaTy::Until(_, _) => {
-                        checkAU(T, s, f.clone(), info);
-                    }
-                    _ => {}
-                }
-            }
-            FormulaTy::Exists(f1) => {
-                match *f1 {
-                    FormulaTy::Next(f2) => {
-                        if let Some(&ref set) = T.states_from(&s) {
-                            for s1 in set {
-                                check(T, *s1,*f2.clone(), info);
-                                if let Some(true) = info.get(&(*s1, f2.to_string())) {
-                                    info.insert((s, f.to_string()), true);
-                                    return;
-                                }
-                            }
-                        }
-                        info.insert((s, f.to_string()), false);
-                    }
-                    FormulaTy::Until(_, _) => {
-                        let mut marks = HashSet::new();
-                        checkEU(T, s, f.clone(), info, &mut marks);
-                    }
-                    _ => {}
-                }
-            }
-            _ => {
-                println!("Malformed term {:?}", fc);
-            }
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn not(sat: &[bool]) -> Vec<bool> {
+    sat.iter().map(|b| !b).collect()
+}
+
+fn and(sat1: &[bool], sat2: &[bool]) -> Vec<bool> {
+    sat1.iter().zip(sat2.iter()).map(|(a, b)| *a && *b).collect()
+}
+
+fn or(sat1: &[bool], sat2: &[bool]) -> Vec<bool> {
+    sat1.iter().zip(sat2.iter()).map(|(a, b)| *a || *b).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::Formula;
+    use crate::logics::props::parse_property;
+    use crate::logics::transys::TranSys;
+    use crate::petri::{Arc, ArcTy, Marking, Place, Transition};
+
+    // One token moving between the places along the transitions (name, from, to), starting on the first place
+    fn net(places: &[&str], transitions: &[(&str, &str, &str)]) -> PTNet {
+        let mut net = PTNet::default();
+        for (i, p) in places.iter().enumerate() {
+            let init = Marking::Plain(if i == 0 { 1 } else { 0 });
+            net.insert_place(Place { name: p.to_string(), page: String::new(), init, producers: Vec::new(), consumers: Vec::new() });
+        }
+        for (t, from, to) in transitions.iter() {
+            net.insert_transition(Transition { name: t.to_string(), page: String::new(), conditions: Vec::new(), effects: Vec::new() });
+            net.insert_arc(Arc { id: format!("{}-in", t), ty: ArcTy::Plain(1) }, from.to_string(), t.to_string());
+            net.insert_arc(Arc { id: format!("{}-out", t), ty: ArcTy::Plain(1) }, t.to_string(), to.to_string());
         }
+        net
+    }
+
+    fn formula(net: &PTNet, text: &str) -> FormulaTy {
+        let mut f = Formula { name: String::from(text), ty: parse_property(text).unwrap() };
+        f.bind(net).unwrap();
+        f.ty
+    }
+
+    // Each state is named after the place holding the token; checks the states labelled by every formula
+    fn check(net: &PTNet, constraints: &[FairConstraint], cases: &[(&str, &str)]) {
+        let tran = TranSys::from_petri(net);
+        let mut ctl = GlobalCtl::new(&tran, constraints).unwrap();
+        for (text, expected) in cases.iter() {
+            let mut names: Vec<String> = ctl.sat(&formula(net, text)).unwrap().iter()
+                .map(|s| {
+                    let config = tran.config_of(*s).unwrap();
+                    let p = config.iter().find(|(_, n)| **n > 0).unwrap().0;
+                    net.places[p].name.clone()
+                })
+                .collect();
+            names.sort();
+            assert_eq!(names.join(" "), *expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        let net = net(&["a", "b", "c"], &[("t1", "a", "b"), ("t2", "b", "c"), ("t3", "c", "a")]);
+        check(&net, &[], &[
+            ("E X b = 1", "a"),
+            ("A X b = 1", "a"),
+            ("E (a = 1 U b = 1)", "a b"),
+            ("E G c = 0", ""),
+            ("A F c = 1", "a b c"),
+            ("A G c = 0", ""),
+            ("A (c = 0 U c = 1)", "a b c"),
+            ("A (b = 1 R c = 0)", "a b"),
+            ("A (c = 0 W b = 1)", "a b"),
+            ("E (a = 1 W c = 1)", "c"),
+        ]);
     }
-}
 
-pub fn almc(T: TranSys, s: State, f: FormulaTy) -> bool {
-    let mut info: HashMap<(State, String), bool> = HashMap::new();
-    let f_simpl = simplify(f);
-    println!("{}", f_simpl.to_string());
-    check(&T, s, f_simpl.clone(), &mut info);
-    let result = info.get(&(s, f_simpl.to_string()));
-    *result.unwrap()
-}
\ No newline at end of file
+    // Paths end at the deadlock d, where EG holds for what d satisfies and AX for anything
+    #[test]
+    fn deadlock() {
+        let net = net(&["a", "b", "d"], &[("t1", "a", "b"), ("t2", "b", "d")]);
+        check(&net, &[], &[
+            ("deadlock", "d"),
+            ("E X d = 1", "b"),
+            ("E X true", "a b"),
+            ("A X false", "d"),
+            ("E G d = 1", "d"),
+            ("E G a = 0", "b d"),
+            ("A F d = 1", "a b d"),
+            ("A G a = 0", "b d"),
+            ("E (a = 1 U d = 1)", "d"),
+            ("A (d = 0 U d = 1)", "a b d"),
+            ("A (a = 1 R d = 0)", "a"),
+            ("A (a = 1 W b = 1)", "a b"),
+            ("E (a = 0 W a = 1)", "a b d"),
+        ]);
+    }
+
+    // a branches to b and c, which both lead to d looping on itself
+    #[test]
+    fn diamond() {
+        let net = net(&["a", "b", "c", "d"], &[("t1", "a", "b"), ("t2", "a", "c"), ("t3", "b", "d"), ("t4", "c", "d"), ("t5", "d", "d")]);
+        check(&net, &[], &[
+            ("E X b = 1", "a"),
+            ("A X b = 1", ""),
+            ("A X (b = 1 | c = 1)", "a"),
+            ("A X d = 1", "b c d"),
+            ("E F b = 1", "a b"),
+            ("A F b = 1", "b"),
+            ("E G b = 0", "a c d"),
+            ("A G b = 0", "c d"),
+            ("E (a = 1 U b = 1)", "a b"),
+            ("A (a = 1 U b = 1)", "b"),
+            ("A (d = 0 U d = 1)", "a b c d"),
+            ("A (b = 1 R d = 0)", "b"),
+            ("A (d = 0 W b = 1)", "b"),
+            ("E (d = 0 W b = 1)", "a b"),
+        ]);
+    }
+
+    // a can loop forever, but fair paths leave it for b; both kinds of constraint give the same labels
+    #[test]
+    fn fairness() {
+        let net = net(&["a", "b"], &[("t0", "a", "a"), ("t1", "a", "b"), ("t2", "b", "b")]);
+        check(&net, &[], &[
+            ("E G a = 1", "a"),
+            ("A F b = 1", "b"),
+        ]);
+        let fair = [
+            FairConstraint::States(formula(&net, "b = 1")),
+            FairConstraint::fires(&[String::from("t2")], &net).unwrap(),
+        ];
+        for c in fair {
+            check(&net, &[c], &[
+                ("true", "a b"),
+                ("E G a = 1", ""),
+                ("E G true", "a b"),
+                ("A F b = 1", "a b"),
+                ("A G A F b = 1", "a b"),
+                ("E X a = 1", "a"),
+                ("A X a = 1", ""),
+                ("E (a = 1 U b = 1)", "a b"),
+                ("A (a = 1 W b = 1)", "a b"),
+                ("E (a = 1 W false)", ""),
+                ("A (false R a = 1)", ""),
+            ]);
+        }
+        // No path fires t0 and t2 both infinitely often, so no state has a fair path
+        let never = [
+            FairConstraint::fires(&[String::from("t0")], &net).unwrap(),
+            FairConstraint::fires(&[String::from("t2")], &net).unwrap(),
+        ];
+        check(&net, &never, &[
+            ("E G true", ""),
+            ("E F b = 1", ""),
+            ("A G false", "a b"),
+        ]);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::logics::{FormulaSet, FormulaTy, PTAtom};
+
+// Handle to a formula interned in a FormulaStore. Operands are interned before the operators over
+// them, so ids order every formula after all of its subformulas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FormulaId(pub u32);
+
+// One operator of a formula, with its operands already interned
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node {
+    True,
+    False,
+    Prop(PTAtom),
+    Neg(PTAtom),
+    Not(FormulaId),
+    Or(FormulaId, FormulaId),
+    And(FormulaId, FormulaId),
+    Next(FormulaId),
+    Global(FormulaId),
+    Finally(FormulaId),
+    Until(FormulaId, FormulaId),
+    Release(FormulaId, FormulaId),
+    WeakUntil(FormulaId, FormulaId),
+    StrongRelease(FormulaId, FormulaId),
+    Forall(FormulaId),
+    Exists(FormulaId),
+    Yesterday(FormulaId),
+    Since(FormulaId, FormulaId),
+    Once(FormulaId),
+    Historically(FormulaId),
+}
+
+impl Node {
+    pub fn children(&self) -> Vec<FormulaId> {
+        match self {
+            Node::True | Node::False | Node::Prop(_) | Node::Neg(_) => vec![],
+            Node::Not(inner) | Node::Next(inner) | Node::Global(inner) | Node::Finally(inner)
+            | Node::Forall(inner) | Node::Exists(inner)
+            | Node::Yesterday(inner) | Node::Once(inner) | Node::Historically(inner) => vec![*inner],
+            Node::Or(lhs, rhs) | Node::And(lhs, rhs) | Node::Until(lhs, rhs) | Node::Release(lhs, rhs)
+            | Node::WeakUntil(lhs, rhs) | Node::StrongRelease(lhs, rhs) | Node::Since(lhs, rhs) => vec![*lhs, *rhs],
+        }
+    }
+}
+
+// Hash-consing table: structurally equal formulas get the same id, so shared subformulas are
+// stored, translated and labelled once, and compared in constant time
+#[derive(Debug, Clone)]
+pub struct FormulaStore {
+    nodes: Vec<Node>,
+    ids: HashMap<Node, FormulaId>,
+}
+
+impl Default for FormulaStore {
+    fn default() -> Self {
+        FormulaStore::new()
+    }
+}
+
+impl FormulaStore {
+    pub const TRUE: FormulaId = FormulaId(0);
+    pub const FALSE: FormulaId = FormulaId(1);
+
+    pub fn new() -> FormulaStore {
+        let mut store = FormulaStore {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+        };
+        store.mk(Node::True);
+        store.mk(Node::False);
+        store
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn mk(&mut self, node: Node) -> FormulaId {
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
+        }
+        let id = FormulaId(self.nodes.len() as u32);
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    pub fn node(&self, id: FormulaId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    pub fn intern(&mut self, f: &FormulaTy) -> FormulaId {
+        let node = match f {
+            FormulaTy::True => Node::True,
+            FormulaTy::False => Node::False,
+            FormulaTy::Prop(atom) => Node::Prop(atom.clone()),
+            FormulaTy::Neg(atom) => Node::Neg(atom.clone()),
+            FormulaTy::Not(inner) => Node::Not(self.intern(inner)),
+            FormulaTy::Or(lhs, rhs) => Node::Or(self.intern(lhs), self.intern(rhs)),
+            FormulaTy::And(lhs, rhs) => Node::And(self.intern(lhs), self.intern(rhs)),
+            FormulaTy::Next(inner) => Node::Next(self.intern(inner)),
+            FormulaTy::Global(inner) => Node::Global(self.intern(inner)),
+            FormulaTy::Finally(inner) => Node::Finally(self.intern(inner)),
+            FormulaTy::Until(lhs, rhs) => Node::Until(self.intern(lhs), self.intern(rhs)),
+            FormulaTy::Release(lhs, rhs) => Node::Release(self.intern(lhs), self.intern(rhs)),
+            FormulaTy::WeakUntil(lhs, rhs) => Node::WeakUntil(self.intern(lhs), self.intern(rhs)),
+            FormulaTy::StrongRelease(lhs, rhs) => Node::StrongRelease(self.intern(lhs), self.intern(rhs)),
+            FormulaTy::Forall(inner) => Node::Forall(self.intern(inner)),
+            FormulaTy::Exists(inner) => Node::Exists(self.intern(inner)),
+            FormulaTy::Yesterday(inner) => Node::Yesterday(self.intern(inner)),
+            FormulaTy::Since(lhs, rhs) => Node::Since(self.intern(lhs), self.intern(rhs)),
+            FormulaTy::Once(inner) => Node::Once(self.intern(inner)),
+            FormulaTy::Historically(inner) => Node::Historically(self.intern(inner)),
+        };
+        self.mk(node)
+    }
+
+    pub fn to_formula(&self, id: FormulaId) -> FormulaTy {
+        let b = |id: &FormulaId| Box::new(self.to_formula(*id));
+        match self.node(id) {
+            Node::True => FormulaTy::True,
+            Node::False => FormulaTy::False,
+            Node::Prop(atom) => FormulaTy::Prop(atom.clone()),
+            Node::Neg(atom) => FormulaTy::Neg(atom.clone()),
+            Node::Not(inner) => FormulaTy::Not(b(inner)),
+            Node::Or(lhs, rhs) => FormulaTy::Or(b(lhs), b(rhs)),
+            Node::And(lhs, rhs) => FormulaTy::And(b(lhs), b(rhs)),
+            Node::Next(inner) => FormulaTy::Next(b(inner)),
+            Node::Global(inner) => FormulaTy::Global(b(inner)),
+            Node::Finally(inner) => FormulaTy::Finally(b(inner)),
+            Node::Until(lhs, rhs) => FormulaTy::Until(b(lhs), b(rhs)),
+            Node::Release(lhs, rhs) => FormulaTy::Release(b(lhs), b(rhs)),
+            Node::WeakUntil(lhs, rhs) => FormulaTy::WeakUntil(b(lhs), b(rhs)),
+            Node::StrongRelease(lhs, rhs) => FormulaTy::StrongRelease(b(lhs), b(rhs)),
+            Node::Forall(inner) => FormulaTy::Forall(b(inner)),
+            Node::Exists(inner) => FormulaTy::Exists(b(inner)),
+            Node::Yesterday(inner) => FormulaTy::Yesterday(b(inner)),
+            Node::Since(lhs, rhs) => FormulaTy::Since(b(lhs), b(rhs)),
+            Node::Once(inner) => FormulaTy::Once(b(inner)),
+            Node::Historically(inner) => FormulaTy::Historically(b(inner)),
+        }
+    }
+
+    // The formula and all of its subformulas, each once, in id order
+    pub fn subformulas(&self, id: FormulaId) -> BTreeSet<FormulaId> {
+        let mut res = BTreeSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if res.insert(id) {
+                stack.extend(self.node(id).children());
+            }
+        }
+        res
+    }
+
+    // Set of formulas in the syntax of logics::props, for traces
+    pub fn show(&self, set: &FormulaSet) -> String {
+        let elems: Vec<String> = set.iter().map(|id| self.to_formula(id).to_string()).collect();
+        format!("{{{}}}", elems.join(", "))
+    }
+}
@@ -1,18 +1,156 @@
 use std::collections::HashMap;
-use std::collections::HashSet;
-use std::collections::hash_set::Iter;
 use std::fmt;
 use std::fmt::Formatter;
-use std::hash::{Hash, Hasher};
+use std::hash::Hash;
 
+pub mod classify;
 pub mod parser;
+pub mod printer;
+pub mod props;
 pub mod ctl;
+pub mod intern;
+pub mod space;
+pub mod text;
 pub mod transys;
 
+use crate::logics::intern::FormulaId;
+use crate::logics::transys::Config;
+use crate::petri::PTNet;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PTAtom {
-    Cardinality(String, String),
-    Fireability(String),
+    Cardinality(IntExpr, CmpOp, IntExpr),
+    // Some of the transitions is fireable
+    Fireability(Vec<String>),
+    // No transition of the net is fireable
+    Deadlock,
+}
+
+// Integer expressions over the token counts of a marking. Places are kept by name, and
+// Formula::bind resolves them once against the net's index map.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IntExpr {
+    Const(usize),
+    // Tokens summed over the places listed by tokens-count, by name and by index once bound
+    Tokens(Vec<String>, Vec<usize>),
+    Sum(Vec<IntExpr>),
+    Product(Vec<IntExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CmpOp {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+impl IntExpr {
+    // Value on a marking, None if the places aren't bound or one is missing from the marking.
+    // Arithmetic saturates, so an omega count (usize::MAX) stays above every constant.
+    pub fn eval(&self, config: &Config) -> Option<usize> {
+        match self {
+            IntExpr::Const(n) => Some(*n),
+            IntExpr::Tokens(names, places) => {
+                if places.len() != names.len() {
+                    return None;
+                }
+                places.iter().try_fold(0usize, |acc, p| Some(acc.saturating_add(*config.get(p)?)))
+            },
+            IntExpr::Sum(terms) => terms.iter()
+                .try_fold(0usize, |acc, e| Some(acc.saturating_add(e.eval(config)?))),
+            IntExpr::Product(terms) => terms.iter()
+                .try_fold(1usize, |acc, e| Some(acc.saturating_mul(e.eval(config)?))),
+        }
+    }
+
+    fn unbound(&self, net: &PTNet, places: &mut Vec<String>) {
+        match self {
+            IntExpr::Const(_) => {},
+            IntExpr::Tokens(names, _) => {
+                let known = |name: &String| net.index_map.get_by_left(name).is_some_and(|p| net.places.contains_key(p));
+                places.extend(names.iter().filter(|name| !known(name)).cloned());
+            },
+            IntExpr::Sum(terms) | IntExpr::Product(terms) => {
+                for e in terms.iter() {
+                    e.unbound(net, places);
+                }
+            },
+        }
+    }
+
+    // Only called on expressions without unbound places
+    fn resolve(&mut self, net: &PTNet) {
+        match self {
+            IntExpr::Const(_) => {},
+            IntExpr::Tokens(names, places) => *places = names.iter().map(|name| net.index_map.get_by_left(name).copied().unwrap()).collect(),
+            IntExpr::Sum(terms) | IntExpr::Product(terms) => {
+                for e in terms.iter_mut() {
+                    e.resolve(net);
+                }
+            },
+        }
+    }
+
+    // Indices of the places read by the expression, None if one of them isn't a place of the net
+    pub fn places(&self, net: &PTNet) -> Option<Vec<usize>> {
+        let mut res = Vec::new();
+        match self {
+            IntExpr::Const(_) => {},
+            IntExpr::Tokens(places, _) => {
+                for p in places.iter() {
+                    let idx = *net.index_map.get_by_left(p)?;
+                    if !net.places.contains_key(&idx) {
+                        return None;
+                    }
+                    res.push(idx);
+                }
+            },
+            IntExpr::Sum(terms) | IntExpr::Product(terms) => {
+                for e in terms.iter() {
+                    res.extend(e.places(net)?);
+                }
+            },
+        }
+        res.sort();
+        res.dedup();
+        Some(res)
+    }
+}
+
+impl fmt::Display for IntExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", printer::props_int_expr(self))
+    }
+}
+
+impl CmpOp {
+    pub fn holds(&self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Ge => lhs >= rhs,
+            CmpOp::Gt => lhs > rhs,
+        }
+    }
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Eq => "=",
+            CmpOp::Ne => "!=",
+            CmpOp::Ge => ">=",
+            CmpOp::Gt => ">",
+        };
+        write!(f, "{}", op)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -21,6 +159,59 @@ pub struct Formula {
     pub ty: FormulaTy,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormulaError {
+    // An element of a formula file that can't be read, with its path from the property id
+    Malformed { path: String, reason: String },
+    // Names of a formula that aren't transitions or places of the net
+    Unbound { formula: String, transitions: Vec<String>, places: Vec<String> },
+}
+
+impl FormulaError {
+    pub fn malformed(path: &str, reason: &str) -> FormulaError {
+        FormulaError::Malformed { path: String::from(path), reason: String::from(reason) }
+    }
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FormulaError::Malformed { path, reason } => write!(f, "malformed formula at {}: {}", path, reason),
+            FormulaError::Unbound { formula, transitions, places } => {
+                let mut unknown = Vec::new();
+                if !transitions.is_empty() {
+                    unknown.push(format!("unknown transitions {}", transitions.join(", ")));
+                }
+                if !places.is_empty() {
+                    unknown.push(format!("unknown places {}", places.join(", ")));
+                }
+                write!(f, "{}: {}", formula, unknown.join("; "))
+            },
+        }
+    }
+}
+
+impl std::error::Error for FormulaError {}
+
+impl Formula {
+    // Check that every transition and place named by the formula exists in the net,
+    // so that atoms never fail to evaluate, and resolve the places to their indices
+    pub fn bind(&mut self, net: &PTNet) -> Result<(), FormulaError> {
+        let mut transitions = Vec::new();
+        let mut places = Vec::new();
+        self.ty.unbound(net, &mut transitions, &mut places);
+        if transitions.is_empty() && places.is_empty() {
+            self.ty.resolve(net);
+            return Ok(());
+        }
+        transitions.sort();
+        transitions.dedup();
+        places.sort();
+        places.dedup();
+        Err(FormulaError::Unbound { formula: self.name.clone(), transitions, places })
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum FormulaTy {
     True,
@@ -35,243 +226,205 @@ pub enum FormulaTy {
     Finally(Box<Self>),
     Until(Box<Self>, Box<Self>),
     Release(Box<Self>, Box<Self>),
+    // a W b is a U b or G a, a M b is a R b with a eventually holding, that is b U (a & b)
+    WeakUntil(Box<Self>, Box<Self>),
+    StrongRelease(Box<Self>, Box<Self>),
     Forall(Box<Self>),
     Exists(Box<Self>),
+    // Past-time operators: the previous position exists and satisfies the operand, the rhs held
+    // at some position up to now and the lhs ever since, some position up to now, all of them
+    Yesterday(Box<Self>),
+    Since(Box<Self>, Box<Self>),
+    Once(Box<Self>),
+    Historically(Box<Self>),
 }
 
-impl std::fmt::Debug for FormulaTy {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl FormulaTy {
+    fn unbound(&self, net: &PTNet, transitions: &mut Vec<String>, places: &mut Vec<String>) {
         match self {
-            FormulaTy::True => {
-                write!(f, "True");
-            },
-            FormulaTy::False => {
-                write!(f, "False");
-            },
-            FormulaTy::Prop(atom) => {
-                match atom {
-                    PTAtom::Cardinality(lhs, rhs) => {
-                        write!(f, "Card({} <= {})", lhs, rhs);
-                    },
-                    PTAtom::Fireability(inner) => {
-                        write!(f, "Fire({})", inner);
-                    },
-                }
-            },
-            FormulaTy::Neg(atom) => {
-                match atom {
-                    PTAtom::Cardinality(lhs, rhs) => {
-                        write!(f, "!Card({} <= {})", lhs, rhs);
-                    },
-                    PTAtom::Fireability(inner) => {
-                        write!(f, "!Fire({})", inner);
-                    },
-                }
-            },
-            FormulaTy::Not(inner) => {
-                write!(f, "!");
-                (*inner.clone()).fmt(f);
-            },
-            FormulaTy::Or(lhs, rhs) => {
-                write!(f, "(");
-                (*lhs.clone()).fmt(f);
-                write!(f, " | ");
-                (*rhs.clone()).fmt(f);
-                write!(f, ")");
-            },
-            FormulaTy::And(lhs, rhs) => {
-                write!(f, "(");
-                (*lhs.clone()).fmt(f);
-                write!(f, " & ");
-                (*rhs.clone()).fmt(f);
-                write!(f, ")");
+            FormulaTy::Prop(atom) | FormulaTy::Neg(atom) => match atom {
+                PTAtom::Fireability(names) => {
+                    let known = |name: &String| net.index_map.get_by_left(name).is_some_and(|t| net.transitions.contains_key(t));
+                    transitions.extend(names.iter().filter(|name| !known(name)).cloned());
+                },
+                PTAtom::Cardinality(lhs, _, rhs) => {
+                    lhs.unbound(net, places);
+                    rhs.unbound(net, places);
+                },
+                PTAtom::Deadlock => {},
             },
-            FormulaTy::Finally(inner) => {
-                write!(f, "F ");
-                (*inner.clone()).fmt(f);
+            FormulaTy::Not(inner) | FormulaTy::Next(inner) | FormulaTy::Global(inner) | FormulaTy::Finally(inner)
+            | FormulaTy::Forall(inner) | FormulaTy::Exists(inner)
+            | FormulaTy::Yesterday(inner) | FormulaTy::Once(inner) | FormulaTy::Historically(inner) => inner.unbound(net, transitions, places),
+            FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs)
+            | FormulaTy::Until(lhs, rhs) | FormulaTy::Release(lhs, rhs)
+            | FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) | FormulaTy::Since(lhs, rhs) => {
+                lhs.unbound(net, transitions, places);
+                rhs.unbound(net, transitions, places);
             },
-            FormulaTy::Next(inner) => {
-                write!(f, "X ");
-                (*inner.clone()).fmt(f);
-            },
-            FormulaTy::Global(inner) => {
-                write!(f, "G ");
-                (*inner.clone()).fmt(f);
-            },
-            FormulaTy::Until(lhs, rhs) => {
-                (*lhs.clone()).fmt(f);
-                write!(f, " U ");
-                (*rhs.clone()).fmt(f);
-            },
-            FormulaTy::Release(lhs, rhs) => {
-                (*lhs.clone()).fmt(f);
-                write!(f, " R ");
-                (*rhs.clone()).fmt(f);
+            FormulaTy::True | FormulaTy::False => {},
+        }
+    }
+
+    fn resolve(&mut self, net: &PTNet) {
+        match self {
+            FormulaTy::Prop(PTAtom::Cardinality(lhs, _, rhs)) | FormulaTy::Neg(PTAtom::Cardinality(lhs, _, rhs)) => {
+                lhs.resolve(net);
+                rhs.resolve(net);
             },
-            FormulaTy::Forall(inner) => {
-                write!(f, "A ");
-                (*inner.clone()).fmt(f);
+            FormulaTy::Prop(_) | FormulaTy::Neg(_) | FormulaTy::True | FormulaTy::False => {},
+            FormulaTy::Not(inner) | FormulaTy::Next(inner) | FormulaTy::Global(inner) | FormulaTy::Finally(inner)
+            | FormulaTy::Forall(inner) | FormulaTy::Exists(inner)
+            | FormulaTy::Yesterday(inner) | FormulaTy::Once(inner) | FormulaTy::Historically(inner) => inner.resolve(net),
+            FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs)
+            | FormulaTy::Until(lhs, rhs) | FormulaTy::Release(lhs, rhs)
+            | FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) | FormulaTy::Since(lhs, rhs) => {
+                lhs.resolve(net);
+                rhs.resolve(net);
             },
-            _ => {},
         }
-        return Ok(());
+    }
+}
+
+// Both print the command-line syntax of logics::props, which parses back to the same formula
+impl std::fmt::Debug for FormulaTy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", printer::props(self))
     }
 }
 
 impl fmt::Display for FormulaTy {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self {
-            FormulaTy::True => {
-                write!(f, "True");
-            },
-            FormulaTy::False => {
-                write!(f, "False");
-            },
-            FormulaTy::Prop(atom) => {
-                match atom {
-                    PTAtom::Cardinality(lhs, rhs) => {
-                        write!(f, "Card({} <= {})", lhs, rhs);
-                    },
-                    PTAtom::Fireability(inner) => {
-                        write!(f, "Fire({})", inner);
-                    },
-                }
-            },
-            FormulaTy::Neg(atom) => {
-                match atom {
-                    PTAtom::Cardinality(lhs, rhs) => {
-                        write!(f, "!Card({} <= {})", lhs, rhs);
-                    },
-                    PTAtom::Fireability(inner) => {
-                        write!(f, "!Fire({})", inner);
-                    },
-                }
-            },
-            FormulaTy::Not(inner) => {
-                write!(f, "!");
-                (*inner.clone()).fmt(f);
-            },
-            FormulaTy::Or(lhs, rhs) => {
-                write!(f, "(");
-                (*lhs.clone()).fmt(f);
-                write!(f, " | ");
-                (*rhs.clone()).fmt(f);
-                write!(f, ")");
-            },
-            FormulaTy::And(lhs, rhs) => {
-                write!(f, "(");
-                (*lhs.clone()).fmt(f);
-                write!(f, " & ");
-                (*rhs.clone()).fmt(f);
-                write!(f, ")");
-            },
-            FormulaTy::Finally(inner) => {
-                write!(f, "F ");
-                (*inner.clone()).fmt(f);
-            },
-            FormulaTy::Next(inner) => {
-                write!(f, "X ");
-                (*inner.clone()).fmt(f);
-            },
-            FormulaTy::Global(inner) => {
-                write!(f, "G ");
-                (*inner.clone()).fmt(f);
-            },
-            FormulaTy::Until(lhs, rhs) => {
-                (*lhs.clone()).fmt(f);
-                write!(f, " U ");
-                (*rhs.clone()).fmt(f);
-            },
-            FormulaTy::Release(lhs, rhs) => {
-                (*lhs.clone()).fmt(f);
-                write!(f, " R ");
-                (*rhs.clone()).fmt(f);
-            },
-            FormulaTy::Forall(inner) => {
-                write!(f, "A ");
-                (*inner.clone()).fmt(f);
-            },
-            _ => {},
-        }
-        return Ok(());
+        write!(f, "{}", printer::props(self))
     }
 }
 
-// Wrapper for hashset to implement hash thus enable storage by bimap.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct FormulaSet {
-    pub set: HashSet<FormulaTy>,
+// Evaluate a state formula (no path quantifiers or temporal operators) on a single marking.
+// Returns None for formulas that need a path, or atoms that can't be decided on a marking.
+pub fn eval_state(f: &FormulaTy, net: &PTNet, config: &Config) -> Option<bool> {
+    match f {
+        FormulaTy::True => Some(true),
+        FormulaTy::False => Some(false),
+        FormulaTy::Prop(atom) => eval_atom(atom, net, config),
+        FormulaTy::Neg(atom) => eval_atom(atom, net, config).map(|b| !b),
+        FormulaTy::Not(inner) => eval_state(inner, net, config).map(|b| !b),
+        FormulaTy::And(lhs, rhs) => Some(eval_state(lhs, net, config)? && eval_state(rhs, net, config)?),
+        FormulaTy::Or(lhs, rhs) => Some(eval_state(lhs, net, config)? || eval_state(rhs, net, config)?),
+        _ => None,
+    }
 }
 
-impl Hash for FormulaSet {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        for elem in self.set.iter() {
-            elem.hash(state);
-        }
+pub(crate) fn eval_atom(atom: &PTAtom, net: &PTNet, config: &Config) -> Option<bool> {
+    match atom {
+        PTAtom::Fireability(names) => {
+            let mut res = false;
+            for name in names.iter() {
+                let tran = net.transitions.get(net.index_map.get_by_left(name)?)?;
+                res = res || net.is_fireable(config, tran);
+            }
+            Some(res)
+        },
+        PTAtom::Cardinality(lhs, op, rhs) => {
+            Some(op.holds(lhs.eval(config)?, rhs.eval(config)?))
+        },
+        PTAtom::Deadlock => Some(!net.transitions.values().any(|tran| net.is_fireable(config, tran))),
     }
 }
 
-impl FromIterator<FormulaTy> for FormulaSet {
-    #[inline]
-    fn from_iter<I: IntoIterator<Item = FormulaTy>>(iter: I) -> FormulaSet {
-        let mut set: HashSet<FormulaTy> = HashSet::with_hasher(Default::default());
-        set.extend(iter);
-        FormulaSet {
-            set: set,
+// Set of interned formulas as a bitset over their ids, which are the subformula indices of the
+// store they come from. Trailing zero words are trimmed, so equal sets have the same words and
+// hash alike, and elements iterate in id order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct FormulaSet {
+    words: Vec<u64>,
+}
+
+impl FromIterator<FormulaId> for FormulaSet {
+    fn from_iter<I: IntoIterator<Item = FormulaId>>(iter: I) -> FormulaSet {
+        let mut res = FormulaSet::new();
+        for id in iter {
+            res.insert(id);
         }
+        res
     }
 }
 
 impl FormulaSet {
     pub fn new() -> Self {
         FormulaSet {
-            set: HashSet::new(),
+            words: Vec::new(),
+        }
+    }
+
+    fn from_words(mut words: Vec<u64>) -> FormulaSet {
+        while words.last() == Some(&0) {
+            words.pop();
         }
+        FormulaSet { words }
+    }
+
+    fn position(value: &FormulaId) -> (usize, u64) {
+        (value.0 as usize / 64, 1 << (value.0 % 64))
     }
 
     pub fn len(&self) -> usize {
-        self.set.len()
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
     }
 
-    pub fn insert(&mut self, value: FormulaTy) {
-        self.set.insert(value);
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
     }
 
-    pub fn remove(&mut self, value: &FormulaTy) {
-        self.set.remove(value);
+    pub fn iter(&self) -> impl Iterator<Item = FormulaId> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, w)| {
+            (0..64).filter(move |bit| w & (1 << bit) != 0).map(move |bit| FormulaId((i * 64 + bit) as u32))
+        })
     }
 
-    pub fn contains(&self, value: &FormulaTy) -> bool {
-        self.set.contains(value)
+    pub fn insert(&mut self, value: FormulaId) {
+        let (word, mask) = FormulaSet::position(&value);
+        if self.words.len() <= word {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= mask;
     }
 
-    pub fn union(&self, other: &FormulaSet) -> FormulaSet {
-        let mut res = HashSet::new();
-        for i in self.set.union(&other.set) {
-            res.insert(i.clone());
+    pub fn remove(&mut self, value: &FormulaId) {
+        let (word, mask) = FormulaSet::position(value);
+        if let Some(w) = self.words.get_mut(word) {
+            *w &= !mask;
+            *self = FormulaSet::from_words(std::mem::take(&mut self.words));
         }
-        FormulaSet {
-            set: res,
+    }
+
+    pub fn contains(&self, value: &FormulaId) -> bool {
+        let (word, mask) = FormulaSet::position(value);
+        self.words.get(word).is_some_and(|w| w & mask != 0)
+    }
+
+    // One word per 64 subformulas, a single one for most formulas
+    pub fn is_subset(&self, other: &FormulaSet) -> bool {
+        self.words.len() <= other.words.len()
+            && self.words.iter().zip(other.words.iter()).all(|(l, r)| l & !r == 0)
+    }
+
+    pub fn union(&self, other: &FormulaSet) -> FormulaSet {
+        let (long, short) = if self.words.len() >= other.words.len() { (self, other) } else { (other, self) };
+        let mut words = long.words.clone();
+        for (w, s) in words.iter_mut().zip(short.words.iter()) {
+            *w |= s;
         }
+        FormulaSet { words }
     }
 
     pub fn intersection(&self, other: &FormulaSet) -> FormulaSet {
-        let mut res = HashSet::new();
-        for i in self.set.intersection(&other.set) {
-            res.insert(i.clone());
-        }
-        FormulaSet {
-            set: res,
-        }
+        FormulaSet::from_words(self.words.iter().zip(other.words.iter()).map(|(l, r)| l & r).collect())
     }
 
     pub fn difference(&self, other: &FormulaSet) -> FormulaSet {
-        let mut res = HashSet::new();
-        for i in self.set.difference(&other.set) {
-            res.insert(i.clone());
-        }
-        FormulaSet {
-            set: res,
-        }
+        let words = self.words.iter().enumerate()
+            .map(|(i, w)| w & !other.words.get(i).copied().unwrap_or(0))
+            .collect();
+        FormulaSet::from_words(words)
     }
-}
\ No newline at end of file
+}
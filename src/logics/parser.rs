@@ -1,157 +1,203 @@
 use crate::logics::*;
 use std::error::Error;
 use std::fs;
-use std::process::exit;
-use roxmltree::Document;
+use roxmltree::{Document, Node};
 
 pub fn parse_formulas(path: &str) -> Result<Vec<Formula>, Box<dyn Error>> {
     let raw_string = fs::read_to_string(path)?;
     let mut formulas = Vec::new();
 
     let doc = Document::parse(raw_string.as_str())?;
-    // let mut i = 0;
-    for p in doc.root_element().children() {
-        if p.is_element() {
-            // Property node
-            // println!("parsing formula {}", i);
-            let mut formula = Formula {
-                name: String::new(),
-                ty: FormulaTy::False,
-            };
-            for c in p.children() {
-                if c.is_element() {
-                    match c.tag_name().name() {
-                        "id" => formula.name = String::from(c.text().unwrap_or("")),
-                        "formula" => {
-                            formula.ty = parse_formula(&c.first_element_child().unwrap());
-                        },
-                        _ => {}
-                    }
-                }
+    for (i, p) in doc.root_element().children().filter(|p| p.is_element()).enumerate() {
+        // Property node, known by its position until its id is read
+        let mut name = None;
+        let mut ty = None;
+        for c in p.children().filter(|c| c.is_element()) {
+            match c.tag_name().name() {
+                "id" => name = Some(String::from(c.text().unwrap_or("").trim())),
+                "formula" => {
+                    let path = match &name {
+                        Some(name) => format!("{}/formula", name),
+                        None => format!("property[{}]/formula", i + 1),
+                    };
+                    ty = Some(parse_formula(&only_child(&c, &path)?, &path)?);
+                },
+                _ => {}
             }
-            formulas.push(formula);
+        }
+        let name = name.unwrap_or_else(|| format!("property[{}]", i + 1));
+        match ty {
+            Some(ty) => formulas.push(Formula { name, ty }),
+            None => return Err(Box::new(FormulaError::malformed(&name, "property without formula"))),
         }
     }
 
     Ok(formulas)
 }
 
-fn parse_formula(node: &roxmltree::Node) -> FormulaTy {
-    // let formula_node = node.first_element_child().unwrap();
-    let formula_node = node.clone();
-    match formula_node.tag_name().name() {
+fn children<'a, 'input>(node: &Node<'a, 'input>) -> Vec<Node<'a, 'input>> {
+    node.children().filter(|c| c.is_element()).collect()
+}
+
+fn only_child<'a, 'input>(node: &Node<'a, 'input>, path: &str) -> Result<Node<'a, 'input>, FormulaError> {
+    let elements = children(node);
+    if elements.len() != 1 {
+        return Err(FormulaError::malformed(path, &format!("expected one element, found {}", elements.len())));
+    }
+    Ok(elements[0])
+}
+
+fn text(node: &Node, path: &str) -> Result<String, FormulaError> {
+    match node.text().map(|t| t.trim()) {
+        Some(t) if !t.is_empty() => Ok(String::from(t)),
+        _ => Err(FormulaError::malformed(path, "missing text")),
+    }
+}
+
+fn parse_formula(node: &Node, parent: &str) -> Result<FormulaTy, FormulaError> {
+    let tag = node.tag_name().name();
+    let path = format!("{}/{}", parent, tag);
+    let unary = |f: fn(Box<FormulaTy>) -> FormulaTy| -> Result<FormulaTy, FormulaError> {
+        Ok(f(Box::new(parse_formula(&only_child(node, &path)?, &path)?)))
+    };
+    // Conjunctions and disjunctions with more than two operands are folded to the left
+    let chain = |f: fn(Box<FormulaTy>, Box<FormulaTy>) -> FormulaTy| -> Result<FormulaTy, FormulaError> {
+        let elements = children(node);
+        if elements.len() < 2 {
+            return Err(FormulaError::malformed(&path, "expected at least two operands"));
+        }
+        let mut ret = parse_formula(&elements[0], &path)?;
+        for e in elements[1..].iter() {
+            ret = f(Box::new(ret), Box::new(parse_formula(e, &path)?));
+        }
+        Ok(ret)
+    };
+    match tag {
+        "true" => Ok(FormulaTy::True),
+        "false" => Ok(FormulaTy::False),
+        "deadlock" => Ok(FormulaTy::Prop(PTAtom::Deadlock)),
         "is-fireable" => {
-            let transition = formula_node.first_element_child().unwrap();
-            return FormulaTy::Prop(
-                PTAtom::Fireability(String::from(transition.text().unwrap()))
-            );
-        },
-        "integer-le" => {
-            let elements: Vec<roxmltree::Node> = formula_node.children().filter(|c| c.is_element()).collect();
-            if elements.len() != 2 {
-                println!("Malformed cardinality atoms");
-                exit(1);
-            }
-            let lhs = match elements[0].tag_name().name() {
-                "integer-constant" => format!("n_{}", elements[0].text().unwrap()),
-                "tokens-count" => format!("p_{}", elements[0].text().unwrap()),
-                _ => {
-                    println!("Malformed cardinality operators");
-                    exit(1);
+            let mut transitions = Vec::new();
+            for c in children(node) {
+                if c.tag_name().name() != "transition" {
+                    return Err(FormulaError::malformed(&path, &format!("unexpected element {}", c.tag_name().name())));
                 }
-            };
-            let rhs = match elements[1].tag_name().name() {
-                "integer-constant" => format!("n_{}", elements[1].text().unwrap()),
-                "tokens-count" => format!("p_{}", elements[1].text().unwrap()),
-                _ => {
-                    println!("Malformed cardinality operators");
-                    exit(1);
-                }
-            };
-            return FormulaTy::Prop(
-                PTAtom::Cardinality(lhs, rhs)
-            );
-        },
-        "negation" => {
-            let inner = formula_node.first_element_child().unwrap();
-            // println!("n: {:?}", formula_node);
-            return FormulaTy::Not(
-                Box::new(parse_formula(&inner))
-            );
-        },
-        "conjunction" => {
-            let elements: Vec<roxmltree::Node> = formula_node.children().filter(|c| c.is_element()).collect();
-            let mut ret = FormulaTy::And(
-                Box::new(parse_formula(&elements[0])),
-                Box::new(parse_formula(&elements[1]))
-            );
-            for i in 2..elements.len() {
-                ret = FormulaTy::And(
-                    Box::new(ret.clone()),
-                    Box::new(parse_formula(&elements[i]))
-                );
+                transitions.push(text(&c, &format!("{}/transition", path))?);
             }
-            return ret;
-        },
-        "disjunction" => {
-            let elements: Vec<roxmltree::Node> = formula_node.children().filter(|c| c.is_element()).collect();
-            let mut ret = FormulaTy::Or(
-                Box::new(parse_formula(&elements[0])),
-                Box::new(parse_formula(&elements[1]))
-            );
-            for i in 2..elements.len() {
-                ret = FormulaTy::Or(
-                    Box::new(ret.clone()),
-                    Box::new(parse_formula(&elements[i]))
-                );
+            if transitions.is_empty() {
+                return Err(FormulaError::malformed(&path, "no transition"));
             }
-            return ret;
+            Ok(FormulaTy::Prop(PTAtom::Fireability(transitions)))
         },
-        "finally" => {
-            let inner = formula_node.first_element_child().unwrap();
-            return FormulaTy::Finally(
-                Box::new(parse_formula(&inner))
-            );
+        "integer-le" | "integer-lt" | "integer-eq" | "integer-ne" | "integer-ge" | "integer-gt" => {
+            let op = match tag {
+                "integer-lt" => CmpOp::Lt,
+                "integer-eq" => CmpOp::Eq,
+                "integer-ne" => CmpOp::Ne,
+                "integer-ge" => CmpOp::Ge,
+                "integer-gt" => CmpOp::Gt,
+                _ => CmpOp::Le,
+            };
+            let elements = children(node);
+            if elements.len() != 2 {
+                return Err(FormulaError::malformed(&path, "expected two operands"));
+            }
+            Ok(FormulaTy::Prop(
+                PTAtom::Cardinality(parse_int_expr(&elements[0], &path)?, op, parse_int_expr(&elements[1], &path)?)
+            ))
         },
-        "globally" => {
-            let inner = formula_node.first_element_child().unwrap();
-            return FormulaTy::Global(
-                Box::new(parse_formula(&inner))
-            );
+        "negation" => unary(FormulaTy::Not),
+        "conjunction" => chain(FormulaTy::And),
+        "disjunction" => chain(FormulaTy::Or),
+        "finally" => unary(FormulaTy::Finally),
+        "globally" => unary(FormulaTy::Global),
+        "next" => unary(FormulaTy::Next),
+        "all-paths" => unary(FormulaTy::Forall),
+        "exists-path" => unary(FormulaTy::Exists),
+        // Weak until, strong release and past-time operators, as written by printer::to_mcc_xml
+        "yesterday" => unary(FormulaTy::Yesterday),
+        "once" => unary(FormulaTy::Once),
+        "historically" => unary(FormulaTy::Historically),
+        "until" | "since" | "weak-until" | "strong-release" => {
+            let elements = children(node);
+            if elements.len() != 2 || elements[0].tag_name().name() != "before" || elements[1].tag_name().name() != "reach" {
+                return Err(FormulaError::malformed(&path, "expected before and reach"));
+            }
+            let (before, reach) = (format!("{}/before", path), format!("{}/reach", path));
+            let lhs = Box::new(parse_formula(&only_child(&elements[0], &before)?, &before)?);
+            let rhs = Box::new(parse_formula(&only_child(&elements[1], &reach)?, &reach)?);
+            Ok(match tag {
+                "until" => FormulaTy::Until(lhs, rhs),
+                "weak-until" => FormulaTy::WeakUntil(lhs, rhs),
+                "strong-release" => FormulaTy::StrongRelease(lhs, rhs),
+                _ => FormulaTy::Since(lhs, rhs),
+            })
         },
-        "next" => {
-            let inner = formula_node.first_element_child().unwrap();
-            // println!("inner n: {:?}", inner);
-            return FormulaTy::Next(
-                Box::new(parse_formula(&inner))
-            );
+        // UpperBounds queries ask for a number, not a truth value
+        "place-bound" => Err(FormulaError::malformed(&path, "place-bound is not supported")),
+        _ => Err(FormulaError::malformed(&path, "unknown formula element")),
+    }
+}
+
+fn parse_int_expr(node: &Node, parent: &str) -> Result<IntExpr, FormulaError> {
+    let path = format!("{}/{}", parent, node.tag_name().name());
+    match node.tag_name().name() {
+        "integer-constant" => match text(node, &path)?.parse() {
+            Ok(n) => Ok(IntExpr::Const(n)),
+            Err(_) => Err(FormulaError::malformed(&path, "not a natural number")),
         },
-        "until" => {
-            let elements: Vec<roxmltree::Node> = formula_node.children().filter(|c| c.is_element()).collect();
-            if elements.len() != 2 || elements[0].tag_name().name() != "before" || elements[1].tag_name().name() != "reach" {
-                println!("Malformed until");
-                exit(1);
+        "tokens-count" => {
+            let mut places = Vec::new();
+            for c in children(node) {
+                if c.tag_name().name() != "place" {
+                    return Err(FormulaError::malformed(&path, &format!("unexpected element {}", c.tag_name().name())));
+                }
+                places.push(text(&c, &format!("{}/place", path))?);
+            }
+            if places.is_empty() {
+                return Err(FormulaError::malformed(&path, "no place"));
             }
-            return FormulaTy::Until(
-                Box::new(parse_formula(&elements[0].first_element_child().unwrap())),
-                Box::new(parse_formula(&elements[1].first_element_child().unwrap()))
-            );
+            Ok(IntExpr::Tokens(places, Vec::new()))
         },
-        "all-paths" => {
-            let inner = formula_node.first_element_child().unwrap();
-            return FormulaTy::Forall(
-                Box::new(parse_formula(&inner))
-            );
+        "integer-sum" | "integer-product" => {
+            let terms = children(node).iter()
+                .map(|c| parse_int_expr(c, &path))
+                .collect::<Result<Vec<IntExpr>, FormulaError>>()?;
+            if terms.is_empty() {
+                return Err(FormulaError::malformed(&path, "no operand"));
+            }
+            Ok(if node.tag_name().name() == "integer-sum" { IntExpr::Sum(terms) } else { IntExpr::Product(terms) })
         },
-        "exists-path" => {
-            let inner = formula_node.first_element_child().unwrap();
-            return FormulaTy::Exists(
-                Box::new(parse_formula(&inner))
-            );
-        }
-        _ => {
-            println!("Malformed formula: {:?}", formula_node);
-            exit(1);
-        }
+        // The bound of a place is a number over the whole state space, not a marking expression
+        "place-bound" => Err(FormulaError::malformed(&path, "place-bound is not supported")),
+        _ => Err(FormulaError::malformed(&path, "unknown integer expression")),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::petri::parser::parse_pnml;
+
+    const DIR: &str = "data/SatelliteMemory-PT-X00010Y0003";
+
+    #[test]
+    fn upper_bounds_are_rejected() {
+        let err = parse_formulas(&format!("{}/UpperBounds.xml", DIR)).unwrap_err();
+        assert!(err.to_string().contains("place-bound is not supported"), "{}", err);
+    }
+
+    // Token counts only evaluate once bind has resolved their places
+    #[test]
+    fn bind_resolves_places() {
+        let net = parse_pnml(&format!("{}/model.pnml", DIR)).unwrap().remove(0);
+        let init: Config = net.place_indices().iter().map(|p| (*p, net.places[p].get_tokens())).collect();
+        let tokens = IntExpr::Tokens(vec![String::from("p4")], Vec::new());
+        assert_eq!(tokens.eval(&init), None);
+        let mut f = Formula { name: String::from("p4"), ty: FormulaTy::Prop(PTAtom::Cardinality(tokens, CmpOp::Le, IntExpr::Const(0))) };
+        f.bind(&net).unwrap();
+        let FormulaTy::Prop(PTAtom::Cardinality(tokens, _, _)) = &f.ty else { unreachable!() };
+        let p4 = net.index_map.get_by_left("p4").unwrap();
+        assert_eq!(tokens.eval(&init), Some(net.places[p4].get_tokens()));
+    }
+}
@@ -0,0 +1,522 @@
+use crate::logics::*;
+use crate::petri::Transition;
+
+// Formula printers for other tools. All of them put parentheses by precedence
+// (| below &, below U, R, W, M and S, below the prefix operators), so nested U and the
+// right operand of a chain of & or | come out parenthesized.
+
+// Operators of an infix syntax; None for the ones it doesn't have
+struct Ops {
+    not: &'static str,
+    and: &'static str,
+    or: &'static str,
+    next: &'static str,
+    finally: &'static str,
+    globally: &'static str,
+    until: &'static str,
+    release: &'static str,
+    // Written out as a U b | G a and b U (a & b) where missing
+    weak_until: Option<&'static str>,
+    strong_release: Option<&'static str>,
+    forall: Option<&'static str>,
+    exists: Option<&'static str>,
+    // Past-time operators
+    yesterday: Option<&'static str>,
+    since: Option<&'static str>,
+    once: Option<&'static str>,
+    historically: Option<&'static str>,
+    tt: &'static str,
+    ff: &'static str,
+}
+
+// The command-line language of logics::props, also used by Display
+const PROPS: Ops = Ops {
+    not: "!", and: " & ", or: " | ", next: "X ", finally: "F ", globally: "G ",
+    until: " U ", release: " R ", weak_until: Some(" W "), strong_release: Some(" M "), forall: Some("A "), exists: Some("E "),
+    yesterday: Some("Y "), since: Some(" S "), once: Some("O "), historically: Some("H "), tt: "true", ff: "false",
+};
+
+const SPOT: Ops = Ops {
+    not: "!", and: " & ", or: " | ", next: "X ", finally: "F ", globally: "G ",
+    until: " U ", release: " R ", weak_until: Some(" W "), strong_release: Some(" M "), forall: None, exists: None,
+    yesterday: None, since: None, once: None, historically: None, tt: "true", ff: "false",
+};
+
+const LTL2BA: Ops = Ops {
+    not: "!", and: " && ", or: " || ", next: "X ", finally: "<> ", globally: "[] ",
+    until: " U ", release: " V ", weak_until: None, strong_release: None, forall: None, exists: None,
+    yesterday: None, since: None, once: None, historically: None, tt: "true", ff: "false",
+};
+
+const NUSMV: Ops = Ops {
+    not: "!", and: " & ", or: " | ", next: "X ", finally: "F ", globally: "G ",
+    until: " U ", release: " V ", weak_until: None, strong_release: None, forall: None, exists: None,
+    yesterday: Some("Y "), since: Some(" S "), once: Some("O "), historically: Some("H "), tt: "TRUE", ff: "FALSE",
+};
+
+const OR: u8 = 1;
+const AND: u8 = 2;
+const BINARY: u8 = 3;
+const UNARY: u8 = 4;
+const ATOM: u8 = 5;
+
+fn prec(f: &FormulaTy) -> u8 {
+    match f {
+        FormulaTy::Or(_, _) => OR,
+        FormulaTy::And(_, _) => AND,
+        FormulaTy::Until(_, _) | FormulaTy::Release(_, _) | FormulaTy::WeakUntil(_, _) | FormulaTy::StrongRelease(_, _)
+        | FormulaTy::Since(_, _) => BINARY,
+        FormulaTy::True | FormulaTy::False | FormulaTy::Prop(_) => ATOM,
+        _ => UNARY,
+    }
+}
+
+struct Infix<'a> {
+    ops: &'static Ops,
+    atom: &'a mut dyn FnMut(&PTAtom) -> Option<String>,
+    // Quantifiers are glued to the temporal operator below them, as in NuSMV CTLSPEC
+    ctl: bool,
+}
+
+impl Infix<'_> {
+    fn print(&mut self, f: &FormulaTy) -> Option<String> {
+        let mut out = String::new();
+        self.write(f, &mut out)?;
+        Some(out)
+    }
+
+    fn child(&mut self, f: &FormulaTy, min: u8, out: &mut String) -> Option<()> {
+        // Operators the syntax lacks are parenthesized by the precedence of their expansion
+        let expanded = match f {
+            FormulaTy::WeakUntil(_, _) if self.ops.weak_until.is_none() => Some(expand(f)),
+            FormulaTy::StrongRelease(_, _) if self.ops.strong_release.is_none() => Some(expand(f)),
+            _ => None,
+        };
+        let f = expanded.as_ref().unwrap_or(f);
+        if prec(f) < min {
+            out.push('(');
+            self.write(f, out)?;
+            out.push(')');
+            Some(())
+        }
+        else {
+            self.write(f, out)
+        }
+    }
+
+    fn unary(&mut self, op: &str, f: &FormulaTy, out: &mut String) -> Option<()> {
+        out.push_str(op);
+        self.child(f, UNARY, out)
+    }
+
+    // Chains of & and | print flat when folded to the left, as all the parsers build them
+    fn binary(&mut self, lhs: &FormulaTy, op: &str, rhs: &FormulaTy, own: u8, out: &mut String) -> Option<()> {
+        let (left, right) = if own == BINARY { (UNARY, UNARY) } else { (own, own + 1) };
+        self.child(lhs, left, out)?;
+        out.push_str(op);
+        self.child(rhs, right, out)
+    }
+
+    fn write(&mut self, f: &FormulaTy, out: &mut String) -> Option<()> {
+        let ops = self.ops;
+        match f {
+            FormulaTy::True => out.push_str(ops.tt),
+            FormulaTy::False => out.push_str(ops.ff),
+            FormulaTy::Prop(atom) => out.push_str(&(self.atom)(atom)?),
+            FormulaTy::Neg(atom) => {
+                out.push_str(ops.not);
+                self.child(&FormulaTy::Prop(atom.clone()), UNARY, out)?;
+            },
+            FormulaTy::Not(inner) => self.unary(ops.not, inner, out)?,
+            FormulaTy::And(lhs, rhs) => self.binary(lhs, ops.and, rhs, AND, out)?,
+            FormulaTy::Or(lhs, rhs) => self.binary(lhs, ops.or, rhs, OR, out)?,
+            FormulaTy::Forall(inner) | FormulaTy::Exists(inner) if self.ctl => {
+                let q = if let FormulaTy::Forall(_) = f { "A" } else { "E" };
+                self.path(q, inner, out)?;
+            },
+            _ if self.ctl => return None,
+            FormulaTy::Next(inner) => self.unary(ops.next, inner, out)?,
+            FormulaTy::Finally(inner) => self.unary(ops.finally, inner, out)?,
+            FormulaTy::Global(inner) => self.unary(ops.globally, inner, out)?,
+            FormulaTy::Until(lhs, rhs) => self.binary(lhs, ops.until, rhs, BINARY, out)?,
+            FormulaTy::Release(lhs, rhs) => self.binary(lhs, ops.release, rhs, BINARY, out)?,
+            FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) => {
+                let op = if let FormulaTy::WeakUntil(_, _) = f { ops.weak_until } else { ops.strong_release };
+                match op {
+                    Some(op) => self.binary(lhs, op, rhs, BINARY, out)?,
+                    None => self.write(&expand(f), out)?,
+                }
+            },
+            FormulaTy::Forall(inner) => self.unary(ops.forall?, inner, out)?,
+            FormulaTy::Exists(inner) => self.unary(ops.exists?, inner, out)?,
+            FormulaTy::Yesterday(inner) => self.unary(ops.yesterday?, inner, out)?,
+            FormulaTy::Since(lhs, rhs) => self.binary(lhs, ops.since?, rhs, BINARY, out)?,
+            FormulaTy::Once(inner) => self.unary(ops.once?, inner, out)?,
+            FormulaTy::Historically(inner) => self.unary(ops.historically?, inner, out)?,
+        }
+        Some(())
+    }
+
+    // CTL operator made of the quantifier q and the temporal operator of f
+    fn path(&mut self, q: &str, f: &FormulaTy, out: &mut String) -> Option<()> {
+        match f {
+            FormulaTy::Next(inner) => self.unary(&format!("{}X ", q), inner, out),
+            FormulaTy::Finally(inner) => self.unary(&format!("{}F ", q), inner, out),
+            FormulaTy::Global(inner) => self.unary(&format!("{}G ", q), inner, out),
+            FormulaTy::Until(lhs, rhs) => {
+                out.push_str(&format!("{} [", q));
+                self.write(lhs, out)?;
+                out.push_str(" U ");
+                self.write(rhs, out)?;
+                out.push(']');
+                Some(())
+            },
+            // A [a R b] is !E [!a U !b], and the other way round
+            FormulaTy::Release(lhs, rhs) => {
+                let dual = if q == "A" { "E" } else { "A" };
+                let until = FormulaTy::Until(Box::new(FormulaTy::Not(lhs.clone())), Box::new(FormulaTy::Not(rhs.clone())));
+                out.push_str(self.ops.not);
+                self.path(dual, &until, out)
+            },
+            // A [a W b] is !E [!b U (!a & !b)], and the other way round
+            FormulaTy::WeakUntil(lhs, rhs) => {
+                let dual = if q == "A" { "E" } else { "A" };
+                let not_rhs = Box::new(FormulaTy::Not(rhs.clone()));
+                let both = Box::new(FormulaTy::And(Box::new(FormulaTy::Not(lhs.clone())), not_rhs.clone()));
+                out.push_str(self.ops.not);
+                self.path(dual, &FormulaTy::Until(not_rhs, both), out)
+            },
+            FormulaTy::StrongRelease(_, _) => self.path(q, &expand(f), out),
+            _ => None,
+        }
+    }
+}
+
+// a W b as a U b | G a, a M b as b U (a & b), for the syntaxes without them
+fn expand(f: &FormulaTy) -> FormulaTy {
+    match f {
+        FormulaTy::WeakUntil(lhs, rhs) => FormulaTy::Or(
+            Box::new(FormulaTy::Until(lhs.clone(), rhs.clone())),
+            Box::new(FormulaTy::Global(lhs.clone())),
+        ),
+        FormulaTy::StrongRelease(lhs, rhs) => FormulaTy::Until(rhs.clone(), Box::new(FormulaTy::And(lhs.clone(), rhs.clone()))),
+        _ => f.clone(),
+    }
+}
+
+// Props syntax of a name: bare when the grammar reads it back as an identifier, quoted otherwise
+fn ident(name: &str) -> String {
+    let mut chars = name.chars();
+    let bare = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    let keyword = name.chars().all(|c| "AEXFGYOH".contains(c))
+        || ["U", "R", "W", "M", "S", "true", "false", "fireable", "deadlock"].contains(&name);
+    if bare && !keyword { String::from(name) } else { format!("\"{}\"", name) }
+}
+
+// NuSMV identifier for a place, other characters turned into underscores
+fn smv_ident(name: &str) -> String {
+    let res: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    if res.starts_with(|c: char| c.is_ascii_digit()) { format!("_{}", res) } else { res }
+}
+
+// Sums bind looser than products, token counts over several places are sums
+fn int_expr(e: &IntExpr, place: &dyn Fn(&str) -> String, min: u8, out: &mut String) {
+    let (own, terms, sep): (u8, Vec<IntExpr>, &str) = match e {
+        IntExpr::Const(n) => {
+            out.push_str(&n.to_string());
+            return;
+        },
+        IntExpr::Tokens(places, _) if places.len() == 1 => {
+            out.push_str(&place(&places[0]));
+            return;
+        },
+        IntExpr::Tokens(places, _) => (1, places.iter().map(|p| IntExpr::Tokens(vec![p.clone()], Vec::new())).collect(), " + "),
+        IntExpr::Sum(terms) => (1, terms.clone(), " + "),
+        IntExpr::Product(terms) => (2, terms.clone(), "*"),
+    };
+    if own < min {
+        out.push('(');
+    }
+    for (i, t) in terms.iter().enumerate() {
+        if i > 0 {
+            out.push_str(sep);
+        }
+        int_expr(t, place, own, out);
+    }
+    if own < min {
+        out.push(')');
+    }
+}
+
+fn comparison(lhs: &IntExpr, op: &CmpOp, rhs: &IntExpr, place: &dyn Fn(&str) -> String) -> String {
+    let mut out = String::new();
+    int_expr(lhs, place, 0, &mut out);
+    out.push_str(&format!(" {} ", op));
+    int_expr(rhs, place, 0, &mut out);
+    out
+}
+
+fn props_atom(atom: &PTAtom) -> String {
+    match atom {
+        PTAtom::Fireability(names) => {
+            let names: Vec<String> = names.iter().map(|t| ident(t)).collect();
+            format!("fireable({})", names.join(", "))
+        },
+        PTAtom::Deadlock => String::from("deadlock"),
+        PTAtom::Cardinality(lhs, op, rhs) => comparison(lhs, op, rhs, &ident),
+    }
+}
+
+// A LTL formula, A phi or quantifier-free
+fn ltl_body(f: &FormulaTy) -> &FormulaTy {
+    match f {
+        FormulaTy::Forall(inner) => inner,
+        _ => f,
+    }
+}
+
+pub(crate) fn props(f: &FormulaTy) -> String {
+    // Comparisons are parenthesized, so that G (p1 <= p2) U (p3 <= 1) reads as it parses
+    let mut atom = |a: &PTAtom| Some(match a {
+        PTAtom::Cardinality(_, _, _) => format!("({})", props_atom(a)),
+        _ => props_atom(a),
+    });
+    Infix { ops: &PROPS, atom: &mut atom, ctl: false }.print(f).unwrap_or_default()
+}
+
+pub(crate) fn props_int_expr(e: &IntExpr) -> String {
+    let mut out = String::new();
+    int_expr(e, &ident, 0, &mut out);
+    out
+}
+
+// Spot infix LTL; atoms become double-quoted propositions holding their props syntax.
+// None unless the formula is LTL.
+pub fn to_spot(f: &FormulaTy) -> Option<String> {
+    let mut atom = |a: &PTAtom| Some(format!("\"{}\"", props_atom(a).replace('\\', "\\\\").replace('"', "\\\"")));
+    Infix { ops: &SPOT, atom: &mut atom, ctl: false }.print(ltl_body(f))
+}
+
+// LTL2BA/LTL3BA input. Their propositions are plain identifiers, so atom i is named a<i>;
+// the atoms are returned in that order.
+pub fn to_ltl2ba(f: &FormulaTy) -> Option<(String, Vec<PTAtom>)> {
+    let mut atoms: Vec<PTAtom> = Vec::new();
+    let mut atom = |a: &PTAtom| {
+        let i = match atoms.iter().position(|b| b == a) {
+            Some(i) => i,
+            None => {
+                atoms.push(a.clone());
+                atoms.len() - 1
+            },
+        };
+        Some(format!("a{}", i))
+    };
+    let res = Infix { ops: &LTL2BA, atom: &mut atom, ctl: false }.print(ltl_body(f))?;
+    Some((res, atoms))
+}
+
+// Disjunction of the transition guards, in NuSMV syntax
+fn smv_fireable<'a>(net: &PTNet, transitions: impl Iterator<Item = &'a Transition>) -> Option<String> {
+    let mut guards = Vec::new();
+    for tran in transitions {
+        let guard: Option<Vec<String>> = tran.conditions.iter()
+            .map(|(p, w)| Some(format!("{} >= {}", smv_ident(net.index_map.get_by_right(p)?), w)))
+            .collect();
+        let guard = guard?;
+        if guard.is_empty() {
+            return Some(String::from("TRUE"));
+        }
+        guards.push(guard.join(" & "));
+    }
+    Some(match guards.len() {
+        0 => String::from("FALSE"),
+        1 => format!("({})", guards[0]),
+        _ => format!("(({}))", guards.join(") | (")),
+    })
+}
+
+// NuSMV LTLSPEC for LTL formulas, CTLSPEC for CTL ones, over one integer variable per place.
+// Fireability is spelled out as the guards of the transitions. None for other formulas.
+pub fn to_nusmv(f: &FormulaTy, net: &PTNet) -> Option<String> {
+    let place = |p: &str| smv_ident(p);
+    let mut atom = |a: &PTAtom| match a {
+        PTAtom::Fireability(names) => {
+            let transitions: Option<Vec<&Transition>> = names.iter()
+                .map(|t| net.transitions.get(net.index_map.get_by_left(t)?))
+                .collect();
+            smv_fireable(net, transitions?.into_iter())
+        },
+        PTAtom::Deadlock => Some(format!("!{}", smv_fireable(net, net.transitions.values())?)),
+        PTAtom::Cardinality(lhs, op, rhs) => {
+            let mut out = String::from("(");
+            int_expr(lhs, &place, 0, &mut out);
+            out.push_str(&format!(" {} ", op));
+            int_expr(rhs, &place, 0, &mut out);
+            out.push(')');
+            Some(out)
+        },
+    };
+    let ltl = Infix { ops: &NUSMV, atom: &mut atom, ctl: false }.print(ltl_body(f));
+    if let Some(res) = ltl {
+        return Some(format!("LTLSPEC {}", res));
+    }
+    let res = Infix { ops: &NUSMV, atom: &mut atom, ctl: true }.print(f)?;
+    Some(format!("CTLSPEC {}", res))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn xml_int_expr(e: &IntExpr, depth: usize, out: &mut String) {
+    let pad = "  ".repeat(depth);
+    match e {
+        IntExpr::Const(n) => out.push_str(&format!("{}<integer-constant>{}</integer-constant>\n", pad, n)),
+        IntExpr::Tokens(places, _) => {
+            out.push_str(&format!("{}<tokens-count>\n", pad));
+            for p in places.iter() {
+                out.push_str(&format!("{}  <place>{}</place>\n", pad, escape(p)));
+            }
+            out.push_str(&format!("{}</tokens-count>\n", pad));
+        },
+        IntExpr::Sum(terms) | IntExpr::Product(terms) => {
+            let tag = if let IntExpr::Sum(_) = e { "integer-sum" } else { "integer-product" };
+            out.push_str(&format!("{}<{}>\n", pad, tag));
+            for t in terms.iter() {
+                xml_int_expr(t, depth + 1, out);
+            }
+            out.push_str(&format!("{}</{}>\n", pad, tag));
+        },
+    }
+}
+
+fn xml_formula(f: &FormulaTy, depth: usize, out: &mut String) {
+    let pad = "  ".repeat(depth);
+    let element = |tag: &str, children: &[&FormulaTy], out: &mut String| {
+        out.push_str(&format!("{}<{}>\n", pad, tag));
+        for c in children.iter() {
+            xml_formula(c, depth + 1, out);
+        }
+        out.push_str(&format!("{}</{}>\n", pad, tag));
+    };
+    match f {
+        FormulaTy::True => out.push_str(&format!("{}<true/>\n", pad)),
+        FormulaTy::False => out.push_str(&format!("{}<false/>\n", pad)),
+        FormulaTy::Prop(PTAtom::Fireability(names)) => {
+            out.push_str(&format!("{}<is-fireable>\n", pad));
+            for t in names.iter() {
+                out.push_str(&format!("{}  <transition>{}</transition>\n", pad, escape(t)));
+            }
+            out.push_str(&format!("{}</is-fireable>\n", pad));
+        },
+        FormulaTy::Prop(PTAtom::Deadlock) => out.push_str(&format!("{}<deadlock/>\n", pad)),
+        FormulaTy::Prop(PTAtom::Cardinality(lhs, op, rhs)) => {
+            let tag = match op {
+                CmpOp::Lt => "integer-lt",
+                CmpOp::Le => "integer-le",
+                CmpOp::Eq => "integer-eq",
+                CmpOp::Ne => "integer-ne",
+                CmpOp::Ge => "integer-ge",
+                CmpOp::Gt => "integer-gt",
+            };
+            out.push_str(&format!("{}<{}>\n", pad, tag));
+            xml_int_expr(lhs, depth + 1, out);
+            xml_int_expr(rhs, depth + 1, out);
+            out.push_str(&format!("{}</{}>\n", pad, tag));
+        },
+        FormulaTy::Neg(atom) => element("negation", &[&FormulaTy::Prop(atom.clone())], out),
+        FormulaTy::Not(inner) => element("negation", &[inner], out),
+        FormulaTy::And(lhs, rhs) => element("conjunction", &[lhs, rhs], out),
+        FormulaTy::Or(lhs, rhs) => element("disjunction", &[lhs, rhs], out),
+        FormulaTy::Next(inner) => element("next", &[inner], out),
+        FormulaTy::Finally(inner) => element("finally", &[inner], out),
+        FormulaTy::Global(inner) => element("globally", &[inner], out),
+        FormulaTy::Forall(inner) => element("all-paths", &[inner], out),
+        FormulaTy::Exists(inner) => element("exists-path", &[inner], out),
+        FormulaTy::Until(lhs, rhs) => {
+            out.push_str(&format!("{}<until>\n{}  <before>\n", pad, pad));
+            xml_formula(lhs, depth + 2, out);
+            out.push_str(&format!("{}  </before>\n{}  <reach>\n", pad, pad));
+            xml_formula(rhs, depth + 2, out);
+            out.push_str(&format!("{}  </reach>\n{}</until>\n", pad, pad));
+        },
+        // Weak until, strong release and the past-time operators aren't in the MCC format, they get
+        // elements of their own
+        FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) => {
+            let tag = if let FormulaTy::WeakUntil(_, _) = f { "weak-until" } else { "strong-release" };
+            out.push_str(&format!("{}<{}>\n{}  <before>\n", pad, tag, pad));
+            xml_formula(lhs, depth + 2, out);
+            out.push_str(&format!("{}  </before>\n{}  <reach>\n", pad, pad));
+            xml_formula(rhs, depth + 2, out);
+            out.push_str(&format!("{}  </reach>\n{}</{}>\n", pad, pad, tag));
+        },
+        FormulaTy::Yesterday(inner) => element("yesterday", &[inner], out),
+        FormulaTy::Once(inner) => element("once", &[inner], out),
+        FormulaTy::Historically(inner) => element("historically", &[inner], out),
+        FormulaTy::Since(lhs, rhs) => {
+            out.push_str(&format!("{}<since>\n{}  <before>\n", pad, pad));
+            xml_formula(lhs, depth + 2, out);
+            out.push_str(&format!("{}  </before>\n{}  <reach>\n", pad, pad));
+            xml_formula(rhs, depth + 2, out);
+            out.push_str(&format!("{}  </reach>\n{}</since>\n", pad, pad));
+        },
+        // The MCC format has no release, a R b is written !(!a U !b)
+        FormulaTy::Release(lhs, rhs) => {
+            let until = FormulaTy::Until(Box::new(FormulaTy::Not(lhs.clone())), Box::new(FormulaTy::Not(rhs.clone())));
+            xml_formula(&FormulaTy::Not(Box::new(until)), depth, out);
+        },
+    }
+}
+
+// MCC property set that parser::parse_formulas reads back to the same formulas, up to
+// release and Neg atoms. Comparisons other than <= use integer-lt, integer-ge etc.
+pub fn to_mcc_xml(formulas: &[Formula]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\"?>\n<property-set xmlns=\"http://mcc.lip6.fr/\">\n");
+    for f in formulas.iter() {
+        out.push_str(&format!("  <property>\n    <id>{}</id>\n    <formula>\n", escape(&f.name)));
+        xml_formula(&f.ty, 3, &mut out);
+        out.push_str("    </formula>\n  </property>\n");
+    }
+    out.push_str("</property-set>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::logics::parser::parse_formulas;
+    use crate::logics::props::parse_property;
+
+    // Both printers read back to the formulas they were given, for every LTL and CTL property
+    // shipped with the models
+    #[test]
+    fn round_trip() {
+        let mut checked = 0;
+        for dir in fs::read_dir("data").unwrap() {
+            let dir = dir.unwrap().path();
+            for kind in ["LTLCardinality", "LTLFireability", "CTLCardinality", "CTLFireability"] {
+                let path = dir.join(format!("{}.xml", kind));
+                if !path.exists() {
+                    continue;
+                }
+                let formulas = parse_formulas(path.to_str().unwrap()).unwrap();
+                for f in formulas.iter() {
+                    let text = props(&f.ty);
+                    assert_eq!(parse_property(&text).unwrap(), f.ty, "{}: {}", f.name, text);
+                }
+                let out = std::env::temp_dir().join(format!("ptchecker-round-trip-{}-{}.xml", std::process::id(), kind));
+                fs::write(&out, to_mcc_xml(&formulas)).unwrap();
+                let parsed = parse_formulas(out.to_str().unwrap()).unwrap();
+                fs::remove_file(&out).unwrap();
+                assert_eq!(parsed.len(), formulas.len());
+                for (g, f) in parsed.iter().zip(formulas.iter()) {
+                    assert_eq!((&g.name, &g.ty), (&f.name, &f.ty), "{}", f.name);
+                }
+                checked += formulas.len();
+            }
+        }
+        assert!(checked >= 128);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::logics::*;
+use std::error::Error;
+use std::fs;
+use pest::Parser;
+use pest::error::ErrorVariant;
+use pest::iterators::Pair;
+use pest_derive::Parser;
+
+#[derive(Parser)]
+#[grammar = "logics/props.pest"]
+struct PropsParser;
+
+// A single formula, as given with --formula. Syntax errors carry their span.
+pub fn parse_property(input: &str) -> Result<FormulaTy, Box<dyn Error>> {
+    let single = PropsParser::parse(Rule::single, input)?.next().unwrap();
+    parse_formula(single.into_inner().next().unwrap())
+}
+
+// One property per line; unnamed ones are called after their line number
+pub fn parse_props(input: &str) -> Result<Vec<Formula>, Box<dyn Error>> {
+    let props = PropsParser::parse(Rule::props, input)?.next().unwrap();
+    let mut formulas = Vec::new();
+    for p in props.into_inner() {
+        if p.as_rule() != Rule::property {
+            continue;
+        }
+        let line = p.as_span().start_pos().line_col().0;
+        let mut inner: Vec<Pair<Rule>> = p.into_inner().collect();
+        let ty = parse_formula(inner.pop().unwrap())?;
+        let name = match inner.pop() {
+            Some(name) => String::from(name.as_str()),
+            None => format!("line-{}", line),
+        };
+        formulas.push(Formula { name, ty });
+    }
+    Ok(formulas)
+}
+
+pub fn parse_props_file(path: &str) -> Result<Vec<Formula>, Box<dyn Error>> {
+    let raw_string = fs::read_to_string(path)?;
+    parse_props(raw_string.as_str()).map_err(|e| match e.downcast::<pest::error::Error<Rule>>() {
+        Ok(e) => e.with_path(path).into(),
+        Err(e) => e,
+    })
+}
+
+fn parse_formula(pair: Pair<Rule>) -> Result<FormulaTy, Box<dyn Error>> {
+    match pair.as_rule() {
+        // a -> b is read as !a | b
+        Rule::formula => {
+            let mut inner = pair.into_inner();
+            let lhs = parse_formula(inner.next().unwrap())?;
+            match inner.next() {
+                Some(rhs) => Ok(FormulaTy::Or(Box::new(FormulaTy::Not(Box::new(lhs))), Box::new(parse_formula(rhs)?))),
+                None => Ok(lhs),
+            }
+        },
+        Rule::disj | Rule::conj => {
+            let rule = pair.as_rule();
+            let mut inner = pair.into_inner();
+            let mut ret = parse_formula(inner.next().unwrap())?;
+            for rhs in inner {
+                let rhs = Box::new(parse_formula(rhs)?);
+                ret = if rule == Rule::disj { FormulaTy::Or(Box::new(ret), rhs) } else { FormulaTy::And(Box::new(ret), rhs) };
+            }
+            Ok(ret)
+        },
+        // U, R, W, M and S associate to the right
+        Rule::binary => {
+            let mut inner = pair.into_inner();
+            let lhs = Box::new(parse_formula(inner.next().unwrap())?);
+            let Some(op) = inner.next() else { return Ok(*lhs) };
+            let rhs = Box::new(parse_formula(inner.next().unwrap())?);
+            Ok(match op.as_str() {
+                "U" => FormulaTy::Until(lhs, rhs),
+                "R" => FormulaTy::Release(lhs, rhs),
+                "W" => FormulaTy::WeakUntil(lhs, rhs),
+                "M" => FormulaTy::StrongRelease(lhs, rhs),
+                _ => FormulaTy::Since(lhs, rhs),
+            })
+        },
+        Rule::unary => {
+            let mut inner: Vec<Pair<Rule>> = pair.into_inner().collect();
+            let mut ret = parse_formula(inner.pop().unwrap())?;
+            let ops: String = inner.iter().map(|op| op.as_str()).collect();
+            for op in ops.chars().rev() {
+                let arg = Box::new(ret);
+                ret = match op {
+                    '!' => FormulaTy::Not(arg),
+                    'A' => FormulaTy::Forall(arg),
+                    'E' => FormulaTy::Exists(arg),
+                    'X' => FormulaTy::Next(arg),
+                    'F' => FormulaTy::Finally(arg),
+                    'Y' => FormulaTy::Yesterday(arg),
+                    'O' => FormulaTy::Once(arg),
+                    'H' => FormulaTy::Historically(arg),
+                    _ => FormulaTy::Global(arg),
+                };
+            }
+            Ok(ret)
+        },
+        Rule::true_ => Ok(FormulaTy::True),
+        Rule::false_ => Ok(FormulaTy::False),
+        // fireable(t1, t2) holds when one of the transitions is
+        Rule::fireable => Ok(FormulaTy::Prop(PTAtom::Fireability(pair.into_inner().map(ident).collect()))),
+        Rule::deadlock => Ok(FormulaTy::Prop(PTAtom::Deadlock)),
+        Rule::constraint => {
+            let mut inner = pair.into_inner();
+            let lhs = parse_linear(inner.next().unwrap())?;
+            let op = match inner.next().unwrap().as_str() {
+                "<" => CmpOp::Lt,
+                "<=" => CmpOp::Le,
+                "=" | "==" => CmpOp::Eq,
+                "!=" => CmpOp::Ne,
+                ">=" => CmpOp::Ge,
+                _ => CmpOp::Gt,
+            };
+            let rhs = parse_linear(inner.next().unwrap())?;
+            Ok(FormulaTy::Prop(PTAtom::Cardinality(lhs, op, rhs)))
+        },
+        _ => unreachable!("unexpected rule {:?}", pair.as_rule()),
+    }
+}
+
+fn parse_linear(pair: Pair<Rule>) -> Result<IntExpr, Box<dyn Error>> {
+    match pair.as_rule() {
+        Rule::linear | Rule::term => {
+            let rule = pair.as_rule();
+            let mut terms = pair.into_inner()
+                .map(parse_linear)
+                .collect::<Result<Vec<IntExpr>, Box<dyn Error>>>()?;
+            if terms.len() == 1 {
+                return Ok(terms.pop().unwrap());
+            }
+            Ok(if rule == Rule::linear { IntExpr::Sum(terms) } else { IntExpr::Product(terms) })
+        },
+        // Digits past usize are reported at their span, like syntax errors
+        Rule::constant => match pair.as_str().parse() {
+            Ok(n) => Ok(IntExpr::Const(n)),
+            Err(e) => {
+                let variant = ErrorVariant::<Rule>::CustomError { message: format!("invalid constant: {}", e) };
+                Err(Box::new(pest::error::Error::new_from_span(variant, pair.as_span())))
+            },
+        },
+        Rule::ident => Ok(IntExpr::Tokens(vec![ident(pair)], Vec::new())),
+        _ => unreachable!("unexpected rule {:?}", pair.as_rule()),
+    }
+}
+
+fn ident(pair: Pair<Rule>) -> String {
+    String::from(pair.into_inner().next().unwrap().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_constant_has_a_span() {
+        let err = parse_props("ok: p1 <= 3\nbig: p1 <= 123456789012345678901234567890\n").unwrap_err();
+        let err = err.downcast::<pest::error::Error<Rule>>().unwrap();
+        assert_eq!(err.line_col, pest::error::LineColLocation::Span((2, 12), (2, 42)));
+        assert!(err.to_string().contains("invalid constant"), "{}", err);
+    }
+}
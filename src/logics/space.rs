@@ -0,0 +1,94 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use crate::logics::{eval_atom, PTAtom};
+use crate::logics::transys::{Config, State, TranSys};
+use crate::petri::PTNet;
+
+// A state graph as seen by the model checkers: successors come with the index of the fired
+// transition, and atomic propositions are evaluated per state (None when an atom isn't supported).
+pub trait StateSpace {
+    type State: Clone + Eq + Hash + Debug;
+
+    fn initial(&self) -> Self::State;
+
+    fn successors(&self, state: &Self::State) -> Vec<(usize, Self::State)>;
+
+    fn eval(&self, state: &Self::State, atom: &PTAtom) -> Option<bool>;
+
+    fn marking(&self, state: &Self::State) -> Config;
+}
+
+// States generated on demand from the net, as token counts in place_indices() order
+pub struct NetSpace<'a> {
+    pub net: &'a PTNet,
+    places: Vec<usize>,
+    transitions: Vec<usize>,
+}
+
+impl<'a> NetSpace<'a> {
+    pub fn new(net: &'a PTNet) -> NetSpace<'a> {
+        NetSpace {
+            net,
+            places: net.place_indices(),
+            transitions: net.transition_indices(),
+        }
+    }
+}
+
+impl StateSpace for NetSpace<'_> {
+    type State = Vec<usize>;
+
+    fn initial(&self) -> Vec<usize> {
+        self.places.iter().map(|p| self.net.places[p].get_tokens()).collect()
+    }
+
+    fn successors(&self, state: &Vec<usize>) -> Vec<(usize, Vec<usize>)> {
+        let config = self.marking(state);
+        self.transitions.iter()
+            .filter(|t| self.net.is_fireable(&config, &self.net.transitions[t]))
+            .map(|t| {
+                let next = self.net.fire(&config, &self.net.transitions[t]);
+                (*t, self.places.iter().map(|p| next[p]).collect())
+            })
+            .collect()
+    }
+
+    fn eval(&self, state: &Vec<usize>, atom: &PTAtom) -> Option<bool> {
+        eval_atom(atom, self.net, &self.marking(state))
+    }
+
+    fn marking(&self, state: &Vec<usize>) -> Config {
+        self.places.iter().zip(state.iter()).map(|(p, v)| (*p, *v)).collect()
+    }
+}
+
+// A TranSys built beforehand; fireability and deadlock atoms are read off its labels, cardinality atoms off its markings
+impl StateSpace for TranSys {
+    type State = State;
+
+    fn initial(&self) -> State {
+        0
+    }
+
+    fn successors(&self, state: &State) -> Vec<(usize, State)> {
+        self.fired_from(state).to_vec()
+    }
+
+    fn eval(&self, state: &State, atom: &PTAtom) -> Option<bool> {
+        match atom {
+            PTAtom::Fireability(names) => {
+                Some(names.iter().any(|name| self.label_of(name).is_some_and(|set| set.contains(state))))
+            },
+            PTAtom::Deadlock => Some(self.is_deadlock(state)),
+            PTAtom::Cardinality(lhs, op, rhs) => {
+                let config = self.config_of(*state)?;
+                Some(op.holds(lhs.eval(config)?, rhs.eval(config)?))
+            },
+        }
+    }
+
+    fn marking(&self, state: &State) -> Config {
+        self.config_of(*state).cloned().unwrap_or_default()
+    }
+}
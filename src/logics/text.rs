@@ -0,0 +1,154 @@
+use crate::logics::*;
+use std::error::Error;
+use std::fs;
+use pest::Parser;
+use pest::error::ErrorVariant;
+use pest::iterators::Pair;
+use pest_derive::Parser;
+
+#[derive(Parser)]
+#[grammar = "logics/formula.pest"]
+struct FormulaParser;
+
+// Same formulas as parser::parse_formulas gives for the XML file of the examination
+pub fn parse_text_formulas(path: &str) -> Result<Vec<Formula>, Box<dyn Error>> {
+    let raw_string = fs::read_to_string(path)?;
+    parse_text(raw_string.as_str())
+}
+
+pub fn parse_text(input: &str) -> Result<Vec<Formula>, Box<dyn Error>> {
+    let file = FormulaParser::parse(Rule::file, input)?.next().unwrap();
+    let mut formulas = Vec::new();
+    for p in file.into_inner() {
+        if p.as_rule() != Rule::property {
+            continue;
+        }
+        let mut inner = p.into_inner();
+        let name = String::from(inner.next().unwrap().as_str());
+        let ty = parse_formula(inner.next().unwrap())?;
+        formulas.push(Formula { name, ty });
+    }
+    Ok(formulas)
+}
+
+fn parse_formula(pair: Pair<Rule>) -> Result<FormulaTy, Box<dyn Error>> {
+    match pair.as_rule() {
+        // Chains are folded to the left, like conjunctions with more than two operands in XML
+        Rule::formula | Rule::conj | Rule::until => {
+            let rule = pair.as_rule();
+            let mut inner = pair.into_inner();
+            let mut ret = parse_formula(inner.next().unwrap())?;
+            for rhs in inner {
+                let rhs = Box::new(parse_formula(rhs)?);
+                ret = match rule {
+                    Rule::formula => FormulaTy::Or(Box::new(ret), rhs),
+                    Rule::conj => FormulaTy::And(Box::new(ret), rhs),
+                    _ => FormulaTy::Until(Box::new(ret), rhs),
+                };
+            }
+            Ok(ret)
+        },
+        Rule::unary => {
+            let mut inner: Vec<Pair<Rule>> = pair.into_inner().collect();
+            let mut ret = parse_formula(inner.pop().unwrap())?;
+            for op in inner.iter().rev() {
+                let arg = Box::new(ret);
+                ret = match op.as_str() {
+                    "!" => FormulaTy::Not(arg),
+                    "A" => FormulaTy::Forall(arg),
+                    "E" => FormulaTy::Exists(arg),
+                    "X" => FormulaTy::Next(arg),
+                    "F" => FormulaTy::Finally(arg),
+                    _ => FormulaTy::Global(arg),
+                };
+            }
+            Ok(ret)
+        },
+        Rule::fireable => {
+            let transitions = pair.into_inner()
+                .map(|q| String::from(q.into_inner().next().unwrap().as_str()))
+                .collect();
+            Ok(FormulaTy::Prop(PTAtom::Fireability(transitions)))
+        },
+        Rule::deadlock => Ok(FormulaTy::Prop(PTAtom::Deadlock)),
+        Rule::comparison => {
+            let mut inner = pair.into_inner();
+            let lhs = parse_int_expr(inner.next().unwrap())?;
+            let op = match inner.next().unwrap().as_str() {
+                "<" => CmpOp::Lt,
+                "<=" => CmpOp::Le,
+                "=" => CmpOp::Eq,
+                "!=" => CmpOp::Ne,
+                ">=" => CmpOp::Ge,
+                _ => CmpOp::Gt,
+            };
+            let rhs = parse_int_expr(inner.next().unwrap())?;
+            Ok(FormulaTy::Prop(PTAtom::Cardinality(lhs, op, rhs)))
+        },
+        _ => unreachable!("unexpected rule {:?}", pair.as_rule()),
+    }
+}
+
+fn parse_int_expr(pair: Pair<Rule>) -> Result<IntExpr, Box<dyn Error>> {
+    match pair.as_rule() {
+        // A single operand stays as is, so "(5)" reads the same as "5"
+        Rule::int_expr | Rule::int_term => {
+            let rule = pair.as_rule();
+            let mut terms = pair.into_inner()
+                .map(parse_int_expr)
+                .collect::<Result<Vec<IntExpr>, Box<dyn Error>>>()?;
+            if terms.len() == 1 {
+                return Ok(terms.pop().unwrap());
+            }
+            Ok(if rule == Rule::int_expr { IntExpr::Sum(terms) } else { IntExpr::Product(terms) })
+        },
+        Rule::constant => match pair.as_str().parse() {
+            Ok(n) => Ok(IntExpr::Const(n)),
+            Err(e) => {
+                let variant = ErrorVariant::<Rule>::CustomError { message: format!("invalid constant: {}", e) };
+                Err(Box::new(pest::error::Error::new_from_span(variant, pair.as_span())))
+            },
+        },
+        Rule::tokens => {
+            let places = pair.into_inner()
+                .map(|q| String::from(q.into_inner().next().unwrap().as_str()))
+                .collect();
+            Ok(IntExpr::Tokens(places, Vec::new()))
+        },
+        _ => unreachable!("unexpected rule {:?}", pair.as_rule()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::parser::parse_formulas;
+
+    // Every .txt file of the data directories reads into the formula trees of its .xml twin
+    #[test]
+    fn text_matches_xml() {
+        let mut compared = 0;
+        for dir in fs::read_dir("data").unwrap() {
+            for file in fs::read_dir(dir.unwrap().path()).unwrap() {
+                let path = file.unwrap().path();
+                if path.extension().is_none_or(|ext| ext != "txt") {
+                    continue;
+                }
+                let xml = path.with_extension("xml");
+                let (text, xml) = (path.to_str().unwrap(), xml.to_str().unwrap());
+                match (parse_text_formulas(text), parse_formulas(xml)) {
+                    // Some XML files carry extra hand-written examples after the examination's properties
+                    (Ok(lhs), Ok(rhs)) => {
+                        assert!(!lhs.is_empty(), "{}", text);
+                        assert_eq!(lhs[..], rhs[..lhs.len()], "{}", text);
+                    },
+                    // UpperBounds queries, which neither parser takes
+                    (Err(_), Err(_)) if text.ends_with("UpperBounds.txt") => continue,
+                    (lhs, rhs) => panic!("{}: {:?} against {:?}", text, lhs.err().map(|e| e.to_string()), rhs.err().map(|e| e.to_string())),
+                }
+                compared += 1;
+            }
+        }
+        assert_eq!(compared, 12);
+    }
+}
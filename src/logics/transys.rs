@@ -1,7 +1,13 @@
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash};
+
+use bimap::BiMap;
+
 use crate::logics::{FormulaTy, PTAtom};
-use crate::petri::{Marking, Place, PTNet};
+use crate::petri::{Marking, Place, PTNet, Transition};
+use crate::petri::parallel;
+use crate::petri::stubborn::{ExploreStats, Reduction, StubbornSets};
+use crate::store::{marking_key, StateStore, StoreMode, Visit};
 
 pub(crate) type State = usize;
 
@@ -11,7 +17,12 @@ pub struct TranSys {
     states: HashSet<State>,
     state2conf: HashMap<State, Config>,
     transitions: HashMap<State, HashSet<State>>,
+    // Outgoing edges with the index of the fired transition
+    fired: HashMap<State, Vec<(usize, State)>>,
     labels: HashMap<String, HashSet<State>>,
+    // Names of the net's places, for cardinality atoms
+    names: BiMap<String, usize>,
+    stats: ExploreStats,
 }
 
 impl TranSys {
@@ -20,7 +31,10 @@ impl TranSys {
             states: HashSet::new(),
             state2conf: HashMap::new(),
             transitions: HashMap::new(),
+            fired: HashMap::new(),
             labels: HashMap::new(),
+            names: BiMap::new(),
+            stats: ExploreStats::default(),
         }
     }
 
@@ -46,6 +60,11 @@ impl TranSys {
         // }
     }
 
+    pub fn insert_fired(&mut self, s1: State, transition: usize, s2: State) {
+        self.insert_transition(s1, s2);
+        self.fired.entry(s1).or_default().push((transition, s2));
+    }
+
     pub fn insert_fireable(&mut self, state: State, names: Vec<String>) {
         for name in names {
             self.labels.entry(name).or_insert_with(|| HashSet::new()).insert(state);
@@ -56,6 +75,10 @@ impl TranSys {
         self.transitions.get(source)
     }
 
+    pub fn fired_from(&self, source: &State) -> &[(usize, State)] {
+        self.fired.get(source).map_or(&[], |v| v.as_slice())
+    }
+
     pub fn label_of(&self, f: &String) -> Option<&HashSet<State>> {
         self.labels.get(f)
     }
@@ -64,49 +87,22 @@ impl TranSys {
         self.state2conf.get(&state)
     }
 
-    fn duplicate_config(&self, new_config: &Config) -> Option<usize> {
-        for (k, v) in self.state2conf.iter() {
-            if *v == *new_config {
-                return Some(k.clone());
-            }
-        }
-        return None;
+    pub fn names(&self) -> &BiMap<String, usize> {
+        &self.names
     }
 
-    fn merge_pair(&mut self, s1: State, s2: State) {
-        self.states.remove(&s2);
-        self.state2conf.remove(&s2);
-        self.transitions.remove(&s1);
-        for set in self.transitions.values_mut() {
-            if set.contains(&s2) {
-                set.remove(&s2);
-                set.insert(s1);
-            }
-        }
-    }
-
-    fn merge_graph(&mut self) {
-        let mut tmp = vec![];
-        for i in 0..self.states.len() {
-            let ith = self.states.get(&i).unwrap();
-            for j in i + 1..self.states.len() {
-                let jth = self.states.get(&j).unwrap();
-                if let Some(&ref config1) = self.state2conf.get(ith) {
-                    if let Some(&ref config2) = self.state2conf.get(jth) {
-                        if config1 == config2 {
-                            tmp.push((*ith, *jth));
-                        }
-                    }
-                }
-            }
-        }
-        for (ith, jth) in tmp {
-            self.merge_pair(ith, jth);
-        }
+    // Full state space, as needed by the CTL engine
+    pub fn from_petri(petri: &PTNet) -> TranSys {
+        TranSys::from_petri_with(petri, Reduction::Off, StoreMode::Exact)
     }
 
-    pub fn from_petri(petri: &PTNet) -> TranSys {
+    // In bitstate mode edges into states seen before are not recorded, as their ids are unknown
+    pub fn from_petri_with(petri: &PTNet, reduction: Reduction, mode: StoreMode) -> TranSys {
+        let stubborn = StubbornSets::new(petri, reduction);
+        let places = petri.place_indices();
+        let mut store = StateStore::new(mode);
         let mut tran = TranSys::new();
+        tran.names = petri.index_map.clone();
         let mut root = Config::new();
         // tran.insert_mapping(0, root);
         for (ind, p) in petri.places.iter() {
@@ -117,69 +113,112 @@ impl TranSys {
         path_map.insert(0, 0);
 
         let mut all = Vec::new();
-        let mut index_all = 0;
-        all.push((index_all, root.clone()));
-        tran.insert_mapping(index_all, root);
-        while !all.is_empty() {
-            let (index_old, config) = all.pop().unwrap();
+        store.insert(marking_key(&root, &places));
+        all.push((0, root.clone()));
+        tran.insert_mapping(0, root);
+        while let Some((index_old, config)) = all.pop() {
             let path = find_path(&path_map, index_old);
-            if has_identical(&tran.state2conf, &path, &config) {
-                continue
-            }
 
             let mut fireable = vec![];
-            for (_, transition) in petri.transitions.iter() {
-                let mut fire = true;
-                for (ind_p, capacity) in transition.conditions.iter() {
-                    let tokens =
-                        if let Some(n) = config.get(&ind_p) { *n } else { 0 };
-                    if tokens < *capacity {
-                        fire = false;
-                        break;
-                    }
-                }
-                if fire {
+            let mut enabled = vec![];
+            for (ind_t, transition) in petri.transitions.iter() {
+                if petri.is_fireable(&config, transition) {
                     fireable.push(transition.name.clone());
-                    let mut new_config = config.clone();
-                    for (ind_p, capacity) in transition.conditions.iter() {
-                        let tokens =
-                            if let Some(n) = new_config.get(&ind_p) { *n } else { 0 };
-                        new_config.insert(*ind_p, if tokens == usize::MAX {tokens} else { tokens-*capacity });
-                    }
-                    for (ind_p, capacity) in transition.effects.iter() {
-                        let tokens =
-                            if let Some(n) = new_config.get(&ind_p) { *n } else { 0 };
-                        new_config.insert(*ind_p, if tokens == usize::MAX {tokens} else { tokens+*capacity });
-                    }
-                    if let Some(covers) = has_cover(&tran.state2conf, &path, &new_config) {
-                        if covers.is_empty() {
-                            continue;
-                        }
-                        println!("{:?}", covers);
-                        for (c) in covers {
-                            new_config.insert(c, usize::MAX);
-                        }
-                    }
-                    if let Some(index_older) = tran.duplicate_config(&new_config) {
-                        // println!("{:?} linking to previous state: {:?}", index_old, index_older);
-                        if index_old != index_older {
-                            tran.insert_transition(index_old, index_older);
-                            continue;
-                        }
+                    enabled.push(*ind_t);
+                }
+            }
+            let mut to_fire = stubborn.reduce(&config, &enabled);
+            if to_fire.len() < enabled.len() && stubborn.needs_proviso() && to_fire.iter().any(|t| {
+                store.contains(&marking_key(&petri.fire(&config, &petri.transitions[t]), &places))
+            }) {
+                // Cycle proviso: a reduced state reaching an already stored one is fully expanded
+                to_fire = enabled.clone();
+            }
+            tran.stats.record(enabled.len(), to_fire.len());
+            for ind_t in to_fire.iter() {
+                let mut new_config = petri.fire(&config, &petri.transitions[ind_t]);
+                // A marking equal to an ancestor is found in the store below, a strictly larger one
+                // is accelerated to omega on the growing places
+                if let Some(covers) = has_cover(&tran.state2conf, &path, &new_config) {
+                    for c in covers {
+                        new_config.insert(c, usize::MAX);
                     }
-                    index_all += 1;
-                    path_map.insert(index_all, index_old);
-                    all.push((index_all, new_config.clone()));
-                    tran.insert_mapping(index_all, new_config.clone());
-                    tran.insert_transition(index_old, index_all);
-                    // println!("{:?}", (index_old, index_all, new_config))
+                }
+                match store.insert(marking_key(&new_config, &places)) {
+                    Visit::New(index_new) => {
+                        path_map.insert(index_new, index_old);
+                        all.push((index_new, new_config.clone()));
+                        tran.insert_mapping(index_new, new_config);
+                        tran.insert_fired(index_old, *ind_t, index_new);
+                    },
+                    Visit::Seen(Some(index_older)) => tran.insert_fired(index_old, *ind_t, index_older),
+                    Visit::Seen(None) => {},
                 }
             }
             tran.insert_fireable(index_old, fireable);
         }
-        tran.merge_graph();
+        tran.stats.states = tran.states.len();
         tran
     }
+
+    // Same TranSys as from_petri_with on a bounded net, with the markings found by the parallel
+    // explorer. The graph is then walked in the sequential DFS order to number the states the same way.
+    // None when the reduction needs the cycle proviso.
+    pub fn from_petri_parallel(petri: &PTNet, reduction: Reduction, mode: StoreMode, threads: usize) -> Option<TranSys> {
+        let explored = parallel::explore(petri, reduction, mode, threads, true)?;
+        let places = petri.place_indices();
+        let config = |id: usize| -> Config {
+            places.iter().zip(explored.markings[id].iter()).map(|(p, v)| (*p, *v)).collect()
+        };
+        let mut tran = TranSys::new();
+        tran.names = petri.index_map.clone();
+        // Explorer id of each state, and the other way round
+        let mut found = vec![0];
+        let mut index_of: HashMap<usize, State> = HashMap::new();
+        index_of.insert(0, 0);
+        let mut all = vec![0];
+        tran.insert_mapping(0, config(0));
+        while let Some(index_old) = all.pop() {
+            let id = found[index_old];
+            let enabled = &explored.enabled[id];
+            let successors = &explored.successors[id];
+            tran.stats.record(enabled.len(), successors.len());
+            for (t, succ) in successors.iter() {
+                let Some(succ) = succ else { continue };
+                if let Some(index_older) = index_of.get(succ) {
+                    tran.insert_fired(index_old, *t, *index_older);
+                    continue;
+                }
+                let index_new = found.len();
+                found.push(*succ);
+                index_of.insert(*succ, index_new);
+                all.push(index_new);
+                tran.insert_mapping(index_new, config(*succ));
+                tran.insert_fired(index_old, *t, index_new);
+            }
+            tran.insert_fireable(index_old, enabled.iter().map(|t| petri.transitions[t].name.clone()).collect());
+        }
+        tran.stats.states = tran.states.len();
+        Some(tran)
+    }
+
+    pub fn stats(&self) -> &ExploreStats {
+        &self.stats
+    }
+
+    pub fn is_deadlock(&self, state: &State) -> bool {
+        !self.labels.values().any(|set| set.contains(state))
+    }
+
+    // States where no transition is fireable
+    pub fn deadlocks(&self) -> Vec<State> {
+        let mut res: Vec<State> = self.states.iter()
+            .filter(|s| self.is_deadlock(s))
+            .copied()
+            .collect();
+        res.sort();
+        res
+    }
 }
 
 fn find_path(path_map: &HashMap<usize, usize>, end: usize) -> Vec<usize> {
@@ -217,17 +256,6 @@ fn is_covered(m1: &Config, m2: &Config) -> Option<Vec<usize>> {
     Some(res)
 }
 
-fn has_identical(state2conf: &HashMap<usize, Config>, path: &Vec<usize>, config_end: &Config) -> bool {
-    for (state) in path.iter().skip(1) {
-        if let Some(&ref config)  = state2conf.get(&state) {
-            if *config == *config_end {
-                return true
-            }
-        }
-    }
-    false
-}
-
 fn has_cover(state2conf: &HashMap<usize, Config>, path: &Vec<usize>, config_end: &Config) -> Option<Vec<usize>> {
     for (state) in path {
         if let Some(&ref config)  = state2conf.get(&state) {
@@ -261,4 +289,45 @@ impl PTNet {
     // pub fn get_by_index(&self, ind: usize) {
     //     self.index_map.get_by_left(ind)
     // }
-}
\ No newline at end of file
+
+    pub fn is_fireable(&self, config: &Config, tran: &Transition) -> bool {
+        tran.conditions.iter().all(|(ind_p, capacity)| config.get(ind_p).copied().unwrap_or(0) >= *capacity)
+    }
+
+    // Successor config, keeping omega (usize::MAX) places unbounded
+    pub fn fire(&self, config: &Config, tran: &Transition) -> Config {
+        let mut new_config = config.clone();
+        for (ind_p, capacity) in tran.conditions.iter() {
+            let tokens = new_config.get(ind_p).copied().unwrap_or(0);
+            new_config.insert(*ind_p, if tokens == usize::MAX { tokens } else { tokens - *capacity });
+        }
+        for (ind_p, capacity) in tran.effects.iter() {
+            let tokens = new_config.get(ind_p).copied().unwrap_or(0);
+            new_config.insert(*ind_p, if tokens == usize::MAX { tokens } else { tokens + *capacity });
+        }
+        new_config
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::petri::parser::parse_pnml;
+
+    // The parallel explorer finds the same graph, numbered the same way, as the sequential DFS
+    #[test]
+    fn parallel_matches_sequential() {
+        let net = parse_pnml("data/SatelliteMemory-PT-X00010Y0003/model.pnml").unwrap().remove(0);
+        for reduction in [Reduction::Off, Reduction::Deadlock] {
+            let sequential = TranSys::from_petri_with(&net, reduction.clone(), StoreMode::Exact);
+            let parallel = TranSys::from_petri_parallel(&net, reduction, StoreMode::Exact, 4).unwrap();
+            assert_eq!(sequential.stats().states, parallel.stats().states);
+            assert_eq!(sequential.stats().fired, parallel.stats().fired);
+            for s in 0..sequential.stats().states {
+                assert_eq!(sequential.config_of(s), parallel.config_of(s));
+                assert_eq!(sequential.fired_from(&s), parallel.fired_from(&s));
+            }
+        }
+        let full = TranSys::from_petri(&net);
+        assert_eq!((full.stats().states, full.stats().fired), (398, 1044));
+    }
+}
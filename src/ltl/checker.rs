@@ -6,128 +6,125 @@ use petgraph::graph::*;
 use petgraph::dot::{Dot};
 
 use crate::logics::*;
-use crate::logics::transys::*;
+use crate::logics::intern::{FormulaStore, Node};
+use crate::logics::space::StateSpace;
 use crate::ltl::*;
 use crate::ltl::vwaa::*;
-use crate::ltl::gba::*;
+use crate::ltl::fairness::*;
+use crate::ltl::history::*;
 use crate::ltl::translator::*;
 use crate::petri::*;
+use crate::petri::stubborn::*;
+use crate::store::*;
 use crate::utils::*;
 
-pub struct LTLChecker {
-    pub auto: Automaton::<(FormulaSet, usize), FormulaSet>,
+// Product state: automaton state and state of the explored space
+pub type ProductKey<T> = ((FormulaSet, usize), T);
+
+// Nested DFS over the product of the automaton with any StateSpace, generated on the fly from the
+// net (NetSpace) or read from a TranSys built beforehand. States carry the history of the
+// past-time subformulas, empty for formulas without them. Under fairness assumptions the product
+// is explored in full and its strongly connected components searched for a fair accepting cycle.
+pub struct LTLChecker<S: StateSpace> {
+    pub auto: Buchi,
     pub fin_size: usize,
-    pub net: PTNet,
-    pub init: Config,
-    pub visited_1: Vec<((FormulaSet, usize), Config)>,
-    pub visited_2: Vec<((FormulaSet, usize), Config)>,
-    pub stack_1: Vec<((FormulaSet, usize), Config)>,
-    pub stack_2: Vec<((FormulaSet, usize), Config)>,
+    // Formulas the automaton actions refer to
+    pub formulas: FormulaStore,
+    // Assumptions the counterexample cycle must meet, none unless set after new
+    pub fairness: Vec<Fairness>,
+    // How the counterexample cycle meets each assumption, one line per assumption
+    pub fairness_met: Vec<String>,
+    pub space: HistorySpace<S>,
+    pub init: HistoryState<S::State>,
+    pub visited_1: StateStore<ProductKey<HistoryState<S::State>>>,
+    pub visited_2: StateStore<ProductKey<HistoryState<S::State>>>,
+    // States of visited_1, for the cycle proviso
+    pub markings: StateStore<HistoryState<S::State>>,
+    pub stack_1: Vec<((FormulaSet, usize), HistoryState<S::State>)>,
+    pub stack_2: Vec<((FormulaSet, usize), HistoryState<S::State>)>,
+    // Entries of stack_1, the inner search closes a cycle when it reaches one of them
+    on_stack_1: HashSet<ProductKey<HistoryState<S::State>>>,
+    // Transitions fired from each entry of visited_1 by id, so that dfs2 replays the same reduction
+    pub reduced_1: HashMap<usize, Vec<usize>>,
+    pub stubborn: StubbornSets,
+    pub stats: ExploreStats,
 }
 
-impl LTLChecker {
-    pub fn new(net: &PTNet, auto: Automaton::<(FormulaSet, usize), FormulaSet>, fin: usize) -> Self {
-        let mut root = Config::new();
-        for (ind, p) in net.places.iter() {
-            root.insert(*ind, p.get_tokens());
-        }
+impl<S: StateSpace> LTLChecker<S> {
+    // The net is needed for the stubborn sets, even when the space is a prebuilt TranSys
+    pub fn new(space: S, net: &PTNet, auto: Buchi, fin: usize, formulas: FormulaStore,
+               reduction: Reduction, mode: StoreMode) -> Self {
+        let space = HistorySpace::new(space, formulas.clone());
         LTLChecker {
-            auto: auto.clone(),
+            auto,
             fin_size: fin,
-            net: net.clone(),
-            init: root,
-            visited_1: Vec::new(),
-            visited_2: Vec::new(),
+            formulas,
+            fairness: Vec::new(),
+            fairness_met: Vec::new(),
+            init: space.initial(),
+            space,
+            visited_1: StateStore::new(mode),
+            visited_2: StateStore::new(mode),
+            markings: StateStore::new(mode),
             stack_1: Vec::new(),
             stack_2: Vec::new(),
+            on_stack_1: HashSet::new(),
+            reduced_1: HashMap::new(),
+            stubborn: StubbornSets::new(net, reduction),
+            stats: ExploreStats::default(),
         }
     }
 
-    fn fireable_set(&self, config: Config) -> Vec<Config> {
-        let mut fireable = vec![];
-        for (_, transition) in self.net.transitions.iter() {
-            let mut fire = true;
-            for (ind_p, capacity) in transition.conditions.iter() {
-                let tokens =
-                    if let Some(n) = config.get(&ind_p) { *n } else { 0 };
-                if tokens < *capacity {
-                    fire = false;
-                    break;
-                }
-            }
-            if fire {
-                let mut new_config = config.clone();
-                for (ind_p, capacity) in transition.conditions.iter() {
-                    let tokens =
-                        if let Some(n) = new_config.get(&ind_p) { *n } else { 0 };
-                    new_config.insert(ind_p.clone(), if tokens == usize::MAX {tokens} else { tokens-*capacity });
-                }
-                for (ind_p, capacity) in transition.effects.iter() {
-                    let tokens =
-                        if let Some(n) = new_config.get(&ind_p) { *n } else { 0 };
-                            new_config.insert(ind_p.clone(), if tokens == usize::MAX {tokens} else { tokens+*capacity });
-                }
-                fireable.push(new_config.clone());
-            }
+    // Stubborn subset of the successors, fully expanded when it would close a cycle
+    fn reduced(&mut self, state: &HistoryState<S::State>) -> Vec<(usize, HistoryState<S::State>)> {
+        let successors = self.space.successors(state);
+        if self.stubborn.reduction == Reduction::Off {
+            self.stats.record(successors.len(), successors.len());
+            return successors;
+        }
+        let enabled: Vec<usize> = successors.iter().map(|(t, _)| *t).collect();
+        let to_fire = self.stubborn.reduce(&self.space.marking(state), &enabled);
+        let mut reduced: Vec<(usize, HistoryState<S::State>)> = successors.iter()
+            .filter(|(t, _)| to_fire.contains(t))
+            .cloned()
+            .collect();
+        if reduced.len() < successors.len() && self.stubborn.needs_proviso()
+            && reduced.iter().any(|(_, m)| self.markings.contains(m)) {
+            reduced = successors;
         }
-        fireable
+        self.stats.record(enabled.len(), reduced.len());
+        reduced
     }
 
-    fn is_fireable(&self, config: Config, tran: &Transition) -> bool {
-        let mut fire = true;
-        for (ind_p, capacity) in tran.conditions.iter() {
-            let tokens =
-                if let Some(n) = config.get(&ind_p) { *n } else { 0 };
-            if tokens < *capacity {
-                fire = false;
-                break;
-            }
+    // Whether the state satisfies all literals of the action; literals that can't be evaluated pass
+    fn reads(&self, action: &FormulaSet, m: &HistoryState<S::State>) -> bool {
+        if action.len() == 1 && action.contains(&FormulaStore::TRUE) {
+            return true;
         }
-        fire
+        action.iter().all(|a| {
+            let holds = match self.formulas.node(a) {
+                Node::Prop(ap) => self.space.eval(m, ap),
+                Node::Neg(ap) => self.space.eval(m, ap).map(|b| !b),
+                _ => self.space.holds(m, a),
+            };
+            holds != Some(false)
+        })
     }
 
-    pub fn filter_marks(&self, action: &FormulaSet, dest: &(FormulaSet, usize), marks: &Vec<Config>) -> Vec<Config> {
-        let mut filtered = marks.clone();
-        if *action == FormulaSet::from_iter(vec![FormulaTy::True]) {
-            return marks.clone();
-        }
-        for m in marks.iter() {
-            let mut flag = true;
-            for a in action.set.iter() {
-                if let FormulaTy::Prop(ap) = a.clone() {
-                    if let PTAtom::Fireability(name) = ap.clone() {
-                        let tran_idx = self.net.index_map.get_by_left(&name).unwrap();
-                        let tran = self.net.transitions.get(&tran_idx).unwrap();
-                        // println!("tran: {:?}", tran);
-                        if !self.is_fireable(m.clone(), &tran) {
-                            flag = false;
-                            break;
-                        }
-                    }
-                }
-                else if let FormulaTy::Neg(ap) = a.clone() {
-                    if let PTAtom::Fireability(name) = ap.clone() {
-                        let tran_idx = self.net.index_map.get_by_left(&name).unwrap();
-                        let tran = self.net.transitions.get(&tran_idx).unwrap();
-                        // println!("tran: {:?}", tran);
-                        if self.is_fireable(m.clone(), &tran) {
-                            flag = false;
-                            break;
-                        }
-                    }
-                }
-            }
-            if flag {
-                filtered.push(m.clone());
-            }
-        }
-        filtered
+    pub fn filter_marks(&self, action: &FormulaSet, dest: &(FormulaSet, usize), marks: &Vec<HistoryState<S::State>>) -> Vec<HistoryState<S::State>> {
+        marks.iter().filter(|m| self.reads(action, m)).cloned().collect()
     }
 
-    pub fn dfs1(&mut self, spec: &(FormulaSet, usize), marking: Config) -> bool {
-        self.visited_1.push((spec.clone(), marking.clone()));
+    pub fn dfs1(&mut self, spec: &(FormulaSet, usize), marking: HistoryState<S::State>) -> bool {
+        // Recorded first, so that a stubborn set only leading back to this state counts as a cycle
+        self.markings.insert(marking.clone());
+        let successors = self.reduced(&marking);
+        if let Visit::New(id) = self.visited_1.insert((spec.clone(), marking.clone())) {
+            self.reduced_1.insert(id, successors.iter().map(|(t, _)| *t).collect());
+        }
         self.stack_1.push((spec.clone(), marking.clone()));
-        let markings = self.fireable_set(marking.clone());
+        self.on_stack_1.insert((spec.clone(), marking.clone()));
+        let markings: Vec<HistoryState<S::State>> = successors.into_iter().map(|(_, m)| m).collect();
         // println!("markings: {:?}", markings);
         // if !self.auto.transitions.contains_key(spec) {
         //     // println!("invalid key: {:?}", spec);
@@ -152,20 +149,29 @@ impl LTLChecker {
                 return true;
             }
         }
+        self.stack_1.pop();
+        self.on_stack_1.remove(&(spec.clone(), marking));
         false
     }
 
-    pub fn dfs2(&mut self, spec: &(FormulaSet, usize), marking: Config, spec_start: &(FormulaSet, usize), marking_start: Config) -> bool {
-        self.visited_2.push((spec.clone(), marking.clone()));
+    pub fn dfs2(&mut self, spec: &(FormulaSet, usize), marking: HistoryState<S::State>, spec_start: &(FormulaSet, usize), marking_start: HistoryState<S::State>) -> bool {
+        let key = (spec.clone(), marking.clone());
+        let to_fire = self.visited_1.get(&key).and_then(|i| self.reduced_1.get(&i)).cloned();
+        self.visited_2.insert(key);
         self.stack_2.push((spec.clone(), marking.clone()));
-        let markings = self.fireable_set(marking.clone());
+        let markings: Vec<HistoryState<S::State>> = self.space.successors(&marking).into_iter()
+            .filter(|(t, _)| to_fire.as_ref().is_none_or(|to_fire| to_fire.contains(t)))
+            .map(|(_, m)| m)
+            .collect();
         for (a, d) in self.auto.transitions[spec].clone().iter() {
             let filtered = self.filter_marks(a, d, &markings);
             for m in filtered.iter() {
-                if self.visited_1.contains(&(d.clone(), m.clone())) {
+                let key = (d.clone(), m.clone());
+                // The state reaches the accepting one along stack_1, so this closes an accepting cycle
+                if self.on_stack_1.contains(&key) {
                     return true;
                 }
-                else if !self.visited_2.contains(&(d.clone(), m.clone())) && self.auto.transitions.contains_key(d) {
+                else if !self.visited_2.contains(&key) && self.auto.transitions.contains_key(d) {
                     let dest = d.clone();
                     if self.dfs2(&dest, m.clone(), spec_start, marking_start.clone()) {
                         return true;
@@ -173,16 +179,113 @@ impl LTLChecker {
                 }
             }
         }
+        self.stack_2.pop();
         return false;
     }
 
     pub fn check(&mut self) -> bool {
+        if !self.fairness.is_empty() {
+            return self.fair_check();
+        }
+        // The initial state is the first letter of the run: the product starts in the automaton
+        // states it leads to, as dfs1 reads the letters of the successors
+        let first = vec![self.init.clone()];
         for init in self.auto.init_states.clone().iter() {
-            println!("init state: {:?}", init);
-            if self.dfs1(init, self.init.clone()) {
-                return false;
+            println!("init state: ({}, {})", self.formulas.show(&init.0), init.1);
+            for (a, d) in self.auto.transitions[init].clone().iter() {
+                if self.filter_marks(a, d, &first).is_empty() || !self.auto.transitions.contains_key(d)
+                    || self.visited_1.contains(&(d.clone(), self.init.clone())) {
+                    continue;
+                }
+                if self.dfs1(d, self.init.clone()) {
+                    self.stats.states = self.visited_1.len();
+                    return false;
+                }
             }
         }
+        self.stats.states = self.visited_1.len();
         true
     }
-}
\ No newline at end of file
+
+    // Builds the whole product, then looks for a fair accepting lasso in it. The counterexample
+    // goes to stack_1 (prefix) and stack_2 (cycle), like the one of the nested search.
+    fn fair_check(&mut self) -> bool {
+        let mut graph = FairGraph::default();
+        let mut ids: HashMap<ProductKey<HistoryState<S::State>>, usize> = HashMap::new();
+        let mut keys: Vec<ProductKey<HistoryState<S::State>>> = Vec::new();
+        let mut queue = Vec::new();
+        let fin_size = self.fin_size;
+        let mut node = |key: ProductKey<HistoryState<S::State>>, graph: &mut FairGraph, queue: &mut Vec<usize>, keys: &mut Vec<_>| {
+            if let Some(id) = ids.get(&key) {
+                return *id;
+            }
+            let id = graph.add_node(key.0.1 == fin_size);
+            ids.insert(key.clone(), id);
+            keys.push(key);
+            queue.push(id);
+            id
+        };
+        let init = self.init.clone();
+        for spec in self.auto.init_states.iter() {
+            for (a, d) in self.auto.transitions[spec].iter() {
+                if self.reads(a, &init) && self.auto.transitions.contains_key(d) {
+                    let id = node((d.clone(), init.clone()), &mut graph, &mut queue, &mut keys);
+                    graph.inits.push(id);
+                }
+            }
+        }
+        while let Some(id) = queue.pop() {
+            let (spec, marking) = keys[id].clone();
+            let successors = self.space.successors(&marking);
+            self.stats.record(successors.len(), successors.len());
+            graph.enabled[id] = successors.iter().map(|(t, _)| *t).collect();
+            for (a, d) in self.auto.transitions[&spec].iter() {
+                if !self.auto.transitions.contains_key(d) {
+                    continue;
+                }
+                for (t, m) in successors.iter() {
+                    if self.reads(a, m) {
+                        let next = node((d.clone(), m.clone()), &mut graph, &mut queue, &mut keys);
+                        graph.edges[id].push((*t, next));
+                    }
+                }
+            }
+        }
+        self.stats.states = keys.len();
+        let Some(lasso) = graph.fair_lasso(&self.fairness) else { return true };
+        self.stack_1 = lasso.prefix.iter().map(|n| keys[*n].clone()).collect();
+        self.stack_2 = lasso.cycle.iter().map(|(_, n)| keys[*n].clone()).collect();
+        let enabled: Vec<&[usize]> = lasso.cycle.iter().map(|(_, n)| graph.enabled[*n].as_slice()).collect();
+        let fired: Vec<usize> = lasso.cycle.iter().map(|(t, _)| *t).collect();
+        self.fairness_met = self.fairness.iter()
+            .map(|f| format!("{}: {}", f, f.met_by(&enabled, &fired).unwrap_or_else(|| String::from("not met"))))
+            .collect();
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::props::parse_property;
+    use crate::logics::space::NetSpace;
+
+    fn check(net: &PTNet, text: &str, por: bool) -> bool {
+        let mut f = Formula { name: String::from(text), ty: parse_property(text).unwrap() };
+        f.bind(net).unwrap();
+        let (auto, fin, formulas) = build_automaton_cav01(&f).unwrap();
+        let reduction = if por { Reduction::for_formula(net, &f.ty) } else { Reduction::Off };
+        LTLChecker::new(NetSpace::new(net), net, auto, fin, formulas, reduction, StoreMode::Exact).check()
+    }
+
+    // The invisible self-loop on d is tried first and is a stubborn set of its own in every
+    // marking; unless the proviso sees the loop it is fired forever and the ring never explored
+    #[test]
+    fn proviso_expands_self_loops() {
+        let net = test_net(&[("a", 1), ("b", 0), ("c", 0), ("d", 1)], &[("dd", &["d"], &["d"]), ("ab", &["a"], &["b"]), ("bc", &["b"], &["c"]), ("ca", &["c"], &["a"])]);
+        for (text, expected) in [("A (G (c < 1) | F (b >= 2))", false), ("A (G (c < 1))", false), ("A (F (b >= 1) | G (c < 1))", true), ("A (G (a + b + c = 1))", true)] {
+            assert_eq!(check(&net, text, false), expected, "{}", text);
+            assert_eq!(check(&net, text, true), expected, "{}", text);
+        }
+    }
+}
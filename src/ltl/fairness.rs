@@ -0,0 +1,359 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use crate::petri::PTNet;
+use crate::utils::sccs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FairKind {
+    // Transitions of the group that stay enabled from some point on must fire
+    Weak,
+    // Transitions of the group that are enabled infinitely often must fire
+    Strong,
+}
+
+// Fairness assumption on a group of transitions, taken as one: the group is enabled when one of
+// its transitions is, and fires when one of them does
+#[derive(Debug, Clone)]
+pub struct Fairness {
+    pub kind: FairKind,
+    pub names: Vec<String>,
+    pub transitions: Vec<usize>,
+}
+
+impl Fairness {
+    // The assumption over the named transitions, or the first name that isn't one of the net
+    pub fn resolve(kind: FairKind, names: Vec<String>, net: &PTNet) -> Result<Fairness, String> {
+        let mut transitions = Vec::new();
+        for name in names.iter() {
+            match net.index_map.get_by_left(name) {
+                Some(t) if net.transitions.contains_key(t) => transitions.push(*t),
+                _ => return Err(name.clone()),
+            }
+        }
+        Ok(Fairness { kind, names, transitions })
+    }
+
+    fn name_of(&self, t: usize) -> &str {
+        let i = self.transitions.iter().position(|u| *u == t).unwrap();
+        &self.names[i]
+    }
+
+    fn enabled(&self, enabled: &[usize]) -> bool {
+        self.transitions.iter().any(|t| enabled.contains(t))
+    }
+
+    // Why a cycle with these enabled sets per state and fired transitions meets the assumption,
+    // None if it doesn't
+    pub fn met_by(&self, enabled: &[&[usize]], fired: &[usize]) -> Option<String> {
+        if let Some(t) = fired.iter().find(|t| self.transitions.contains(t)) {
+            return Some(format!("{} fires on the cycle", self.name_of(*t)));
+        }
+        match self.kind {
+            FairKind::Weak if enabled.iter().any(|e| !self.enabled(e)) => Some(String::from("disabled on the cycle")),
+            FairKind::Strong if !enabled.iter().any(|e| self.enabled(e)) => Some(String::from("never enabled on the cycle")),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Fairness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.kind == FairKind::Weak { "weak" } else { "strong" };
+        write!(f, "{} fairness of {{{}}}", kind, self.names.join(", "))
+    }
+}
+
+// Product explored in full for the fair emptiness check: per node whether it is accepting, the
+// transitions enabled in its marking and the (transition, node) edges leaving it
+#[derive(Debug, Default)]
+pub struct FairGraph {
+    pub inits: Vec<usize>,
+    pub accepting: Vec<bool>,
+    pub enabled: Vec<Vec<usize>>,
+    pub edges: Vec<Vec<(usize, usize)>>,
+}
+
+// Lasso of the graph: nodes from an initial one to the cycle entry, then the (transition, node)
+// steps of the cycle, ending back at the entry
+#[derive(Debug, Default)]
+pub struct Lasso {
+    pub prefix: Vec<usize>,
+    pub cycle: Vec<(usize, usize)>,
+}
+
+impl FairGraph {
+    pub fn add_node(&mut self, accepting: bool) -> usize {
+        self.accepting.push(accepting);
+        self.enabled.push(Vec::new());
+        self.edges.push(Vec::new());
+        self.accepting.len() - 1
+    }
+
+    // Accepting lasso whose cycle meets all the assumptions, None if there is none
+    pub fn fair_lasso(&self, fairness: &[Fairness]) -> Option<Lasso> {
+        let all = vec![true; self.accepting.len()];
+        let scc = self.fair_scc(&all, fairness)?;
+        let prefix = self.path(&self.inits, &all, |n| scc[n])?;
+        let entry = *prefix.last().unwrap();
+        let mut lasso = Lasso { prefix, cycle: Vec::new() };
+        // Stops on the cycle: an accepting node, then a witness for each assumption
+        let mut at = entry;
+        let visit = |lasso: &mut Lasso, at: &mut usize, goal: &dyn Fn(usize) -> bool| {
+            let steps = self.steps(*at, &scc, goal);
+            if let Some((_, last)) = steps.last() {
+                *at = *last;
+            }
+            lasso.cycle.extend(steps);
+        };
+        visit(&mut lasso, &mut at, &|n| self.accepting[n]);
+        for f in fairness.iter() {
+            // An edge of the component firing the group, else (weak only) a node where it is disabled
+            let source = (0..self.edges.len())
+                .find(|n| scc[*n] && self.edges[*n].iter().any(|(t, m)| scc[*m] && f.transitions.contains(t)));
+            if let Some(source) = source {
+                visit(&mut lasso, &mut at, &|n| n == source);
+                let (t, m) = *self.edges[at].iter().find(|(t, m)| scc[*m] && f.transitions.contains(t)).unwrap();
+                lasso.cycle.push((t, m));
+                at = m;
+            }
+            else if f.kind == FairKind::Weak {
+                visit(&mut lasso, &mut at, &|n| !f.enabled(&self.enabled[n]));
+            }
+        }
+        // Back to the entry, over at least one edge
+        if lasso.cycle.is_empty() {
+            let (t, m) = *self.edges[at].iter().find(|(_, m)| scc[*m]).unwrap();
+            lasso.cycle.push((t, m));
+            at = m;
+        }
+        visit(&mut lasso, &mut at, &|n| n == entry);
+        Some(lasso)
+    }
+
+    // Nodes of a strongly connected component, within the nodes of mask, that has an accepting
+    // cycle meeting the assumptions (Streett emptiness: nodes where a strong group is enabled but
+    // never fired in the component are removed and its sub-components searched again)
+    fn fair_scc(&self, mask: &[bool], fairness: &[Fairness]) -> Option<Vec<bool>> {
+        let sccs = sccs(mask, |v, i| self.edges[v].get(i).map(|(_, w)| *w));
+        let mut comp = vec![usize::MAX; mask.len()];
+        for (i, scc) in sccs.iter().enumerate() {
+            for n in scc.iter() {
+                comp[*n] = i;
+            }
+        }
+        // Components are disjoint, the nodes kept of each one share a mask
+        let mut sub = vec![false; mask.len()];
+        for (i, scc) in sccs.iter().enumerate() {
+            let inside = |n: usize| comp[n] == i;
+            let fired: HashSet<usize> = scc.iter()
+                .flat_map(|n| self.edges[*n].iter().filter(|(_, m)| inside(*m)).map(|(t, _)| *t))
+                .collect();
+            let looping = scc.len() > 1 || self.edges[scc[0]].iter().any(|(_, m)| *m == scc[0]);
+            if !looping || !scc.iter().any(|n| self.accepting[*n]) {
+                continue;
+            }
+            let fires = |f: &Fairness| f.transitions.iter().any(|t| fired.contains(t));
+            let weak_met = fairness.iter()
+                .filter(|f| f.kind == FairKind::Weak)
+                .all(|f| fires(f) || scc.iter().any(|n| !f.enabled(&self.enabled[*n])));
+            if !weak_met {
+                continue;
+            }
+            let unfair: Vec<&Fairness> = fairness.iter()
+                .filter(|f| f.kind == FairKind::Strong && !fires(f))
+                .collect();
+            let mut removed = false;
+            for n in scc.iter() {
+                if unfair.iter().any(|f| f.enabled(&self.enabled[*n])) {
+                    removed = true;
+                }
+                else {
+                    sub[*n] = true;
+                }
+            }
+            if !removed {
+                let mut res = vec![false; mask.len()];
+                for n in scc.iter() {
+                    res[*n] = true;
+                }
+                return Some(res);
+            }
+            if let Some(res) = self.fair_scc(&sub, fairness) {
+                return Some(res);
+            }
+            for n in scc.iter() {
+                sub[*n] = false;
+            }
+        }
+        None
+    }
+
+    // Shortest path, as nodes, from one of the sources to a goal node, within mask
+    fn path(&self, sources: &[usize], mask: &[bool], goal: impl Fn(usize) -> bool) -> Option<Vec<usize>> {
+        let mut parent = vec![None; mask.len()];
+        let mut queue = VecDeque::new();
+        for s in sources.iter() {
+            if parent[*s].is_none() {
+                parent[*s] = Some(*s);
+                queue.push_back(*s);
+            }
+        }
+        while let Some(v) = queue.pop_front() {
+            if goal(v) {
+                let mut res = vec![v];
+                let mut at = v;
+                while parent[at] != Some(at) {
+                    at = parent[at].unwrap();
+                    res.push(at);
+                }
+                res.reverse();
+                return Some(res);
+            }
+            for (_, w) in self.edges[v].iter() {
+                if mask[*w] && parent[*w].is_none() {
+                    parent[*w] = Some(v);
+                    queue.push_back(*w);
+                }
+            }
+        }
+        None
+    }
+
+    // Shortest (transition, node) steps from a node to a goal node within mask, none if the
+    // node is one; mask is strongly connected, so the goal is reached
+    fn steps(&self, from: usize, mask: &[bool], goal: &dyn Fn(usize) -> bool) -> Vec<(usize, usize)> {
+        let nodes = self.path(&[from], mask, goal).unwrap();
+        nodes.windows(2)
+            .map(|w| (self.edges[w[0]].iter().find(|(_, m)| *m == w[1]).unwrap().0, w[1]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::Formula;
+    use crate::logics::props::parse_property;
+    use crate::logics::space::NetSpace;
+    use crate::ltl::checker::LTLChecker;
+    use crate::ltl::translator::build_automaton_cav01;
+    use crate::petri::stubborn::Reduction;
+    use crate::petri::test_net;
+    use crate::store::StoreMode;
+
+    fn group(kind: FairKind, transitions: &[usize]) -> Fairness {
+        Fairness { kind, names: transitions.iter().map(|t| format!("t{}", t)).collect(), transitions: transitions.to_vec() }
+    }
+
+    // Graph of (accepting, enabled, edges) nodes, the first one initial
+    fn graph(nodes: &[(bool, &[usize], &[(usize, usize)])]) -> FairGraph {
+        let mut g = FairGraph { inits: vec![0], ..FairGraph::default() };
+        for (accepting, enabled, edges) in nodes.iter() {
+            let n = g.add_node(*accepting);
+            g.enabled[n] = enabled.to_vec();
+            g.edges[n] = edges.to_vec();
+        }
+        g
+    }
+
+    // The lasso follows the edges back to its entry, visits an accepting node and fires every
+    // group on the cycle or disables it, somewhere for a weak group and all along for a strong one
+    fn check_lasso(g: &FairGraph, lasso: &Lasso, fairness: &[Fairness]) {
+        assert!(g.inits.contains(&lasso.prefix[0]));
+        for w in lasso.prefix.windows(2) {
+            assert!(g.edges[w[0]].iter().any(|(_, m)| *m == w[1]));
+        }
+        let entry = *lasso.prefix.last().unwrap();
+        let mut at = entry;
+        for (t, m) in lasso.cycle.iter() {
+            assert!(g.edges[at].contains(&(*t, *m)));
+            at = *m;
+        }
+        assert_eq!(at, entry);
+        assert!(lasso.cycle.iter().any(|(_, m)| g.accepting[*m]));
+        let enabled: Vec<&[usize]> = lasso.cycle.iter().map(|(_, m)| g.enabled[*m].as_slice()).collect();
+        let fired: Vec<usize> = lasso.cycle.iter().map(|(t, _)| *t).collect();
+        for f in fairness.iter() {
+            let fires = fired.iter().any(|t| f.transitions.contains(t));
+            let disabled = match f.kind {
+                FairKind::Weak => enabled.iter().any(|e| !f.enabled(e)),
+                FairKind::Strong => enabled.iter().all(|e| !f.enabled(e)),
+            };
+            assert!(fires || disabled, "{}", f);
+            assert!(f.met_by(&enabled, &fired).is_some(), "{}", f);
+        }
+    }
+
+    // Node 0 loops on t0 with t1 enabled all along; t1 leads to node 1, where only t2 is enabled,
+    // and t2 back. Node 2 is reached by t3 and only loops on t4 with t1 enabled.
+    #[test]
+    fn lasso_meets_assumptions() {
+        let g = graph(&[
+            (true, &[0, 1, 3], &[(0, 0), (1, 1), (3, 2)]),
+            (false, &[2], &[(2, 0)]),
+            (true, &[1, 4], &[(4, 2)]),
+        ]);
+        let all = vec![true; 3];
+        let cases = [
+            (vec![], true),
+            (vec![group(FairKind::Weak, &[1])], true),
+            (vec![group(FairKind::Strong, &[1])], true),
+            (vec![group(FairKind::Weak, &[0]), group(FairKind::Strong, &[2])], true),
+            // t2 is disabled on node 0, so weak fairness is met by t0 looping there
+            (vec![group(FairKind::Weak, &[2]), group(FairKind::Weak, &[4])], true),
+            // Node 2 never fires t3, whose only edge leaves the cycle of nodes 0 and 1, and
+            // keeps t1 enabled without firing it
+            (vec![group(FairKind::Strong, &[1]), group(FairKind::Strong, &[3])], false),
+        ];
+        for (fairness, exists) in cases {
+            assert_eq!(g.fair_scc(&all, &fairness).is_some(), exists, "{:?}", fairness);
+            match g.fair_lasso(&fairness) {
+                Some(lasso) => check_lasso(&g, &lasso, &fairness),
+                None => assert!(!exists, "{:?}", fairness),
+            }
+        }
+        // The unfair self-loop alone is no lasso once t1 has to fire
+        let lasso = g.fair_lasso(&[group(FairKind::Weak, &[1])]).unwrap();
+        assert!(lasso.cycle.iter().any(|(t, _)| *t == 1));
+    }
+
+    fn check(text: &str, fairness: &[(FairKind, &str)]) -> (bool, Vec<String>) {
+        // A token on p may loop on stay or go to q; from r it moves to s, where go2 is enabled,
+        // and back again
+        let net = test_net(&[("p", 1), ("q", 0), ("r", 1), ("s", 0)],
+            &[("stay", &["p"], &["p"]), ("go", &["p"], &["q"]), ("done", &["q"], &["q"]),
+              ("rs", &["r"], &["s"]), ("sr", &["s"], &["r"]), ("go2", &["s", "p"], &["q"])]);
+        let mut f = Formula { name: String::from(text), ty: parse_property(text).unwrap() };
+        f.bind(&net).unwrap();
+        let (auto, fin, formulas) = build_automaton_cav01(&f).unwrap();
+        let mut checker = LTLChecker::new(NetSpace::new(&net), &net, auto, fin, formulas, Reduction::Off, StoreMode::Exact);
+        checker.fairness = fairness.iter()
+            .map(|(kind, names)| Fairness::resolve(*kind, names.split(',').map(String::from).collect(), &net).unwrap())
+            .collect();
+        let res = checker.check();
+        (res, checker.fairness_met)
+    }
+
+    // Looping on stay forever falsifies F q unless fairness makes go fire; go2 is enabled only
+    // every other step of the shuttle between r and s, so only strong fairness forces it
+    #[test]
+    fn fairness_rules_out_unfair_cycles() {
+        let text = "A F q >= 1";
+        assert!(!check(text, &[]).0);
+        assert!(check(text, &[(FairKind::Weak, "go")]).0);
+        assert!(check(text, &[(FairKind::Strong, "go")]).0);
+        assert!(check(text, &[(FairKind::Strong, "go,go2")]).0);
+        assert!(check(text, &[(FairKind::Strong, "go2"), (FairKind::Weak, "rs,sr")]).0);
+        // The token on r may also stay put, and go2 is then never enabled
+        let (holds, met) = check(text, &[(FairKind::Strong, "go2")]);
+        assert!(!holds);
+        assert_eq!(met, vec![String::from("strong fairness of {go2}: never enabled on the cycle")]);
+        let (holds, met) = check(text, &[(FairKind::Weak, "go2"), (FairKind::Weak, "rs,sr")]);
+        assert!(!holds);
+        assert_eq!(met[0], "weak fairness of {go2}: disabled on the cycle");
+        let (holds, met) = check(text, &[(FairKind::Weak, "stay"), (FairKind::Strong, "rs")]);
+        assert!(!holds);
+        assert!(met.iter().all(|m| m.contains("fires on the cycle")), "{:?}", met);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::logics::*;
+use crate::logics::intern::{FormulaId, FormulaStore, Node};
+use crate::logics::space::StateSpace;
+use crate::logics::transys::Config;
+
+// A state of the underlying space with the past-time subformulas that hold at this point of the run
+pub type HistoryState<T> = (T, FormulaSet);
+
+// History variables for past-time LTL: each state carries the set of past subformulas true at
+// its position, computed from the set of its predecessor on the run. States reached with
+// different histories are different states of the product.
+pub struct HistorySpace<S: StateSpace> {
+    pub space: S,
+    store: FormulaStore,
+    // Past-time subformulas in id order, so operands are updated before the operators over them
+    past: Vec<FormulaId>,
+}
+
+impl<S: StateSpace> HistorySpace<S> {
+    pub fn new(space: S, store: FormulaStore) -> Self {
+        let past = (0..store.len() as u32)
+            .map(FormulaId)
+            .filter(|id| matches!(store.node(*id), Node::Yesterday(_) | Node::Since(_, _) | Node::Once(_) | Node::Historically(_)))
+            .collect();
+        HistorySpace { space, store, past }
+    }
+
+    // Truth of a pure past formula at the state, None when one of its atoms can't be evaluated
+    pub fn holds(&self, state: &HistoryState<S::State>, f: FormulaId) -> Option<bool> {
+        match self.store.node(f) {
+            Node::True => Some(true),
+            Node::False => Some(false),
+            Node::Prop(atom) => self.space.eval(&state.0, atom),
+            Node::Neg(atom) => self.space.eval(&state.0, atom).map(|b| !b),
+            Node::Not(inner) => self.holds(state, *inner).map(|b| !b),
+            Node::And(lhs, rhs) => Some(self.holds(state, *lhs)? && self.holds(state, *rhs)?),
+            Node::Or(lhs, rhs) => Some(self.holds(state, *lhs)? || self.holds(state, *rhs)?),
+            Node::Yesterday(_) | Node::Since(_, _) | Node::Once(_) | Node::Historically(_) => Some(state.1.contains(&f)),
+            _ => None,
+        }
+    }
+
+    // History of s, entered from prev (None at the start of the run)
+    fn step(&self, s: S::State, prev: Option<&HistoryState<S::State>>) -> HistoryState<S::State> {
+        let mut state = (s, FormulaSet::new());
+        for f in self.past.iter() {
+            let now = |state: &HistoryState<S::State>, g: &FormulaId| self.holds(state, *g).unwrap_or(false);
+            let before = prev.is_some_and(|p| p.1.contains(f));
+            let holds = match self.store.node(*f) {
+                Node::Yesterday(inner) => prev.is_some_and(|p| now(p, inner)),
+                Node::Since(lhs, rhs) => now(&state, rhs) || now(&state, lhs) && before,
+                Node::Once(inner) => now(&state, inner) || before,
+                Node::Historically(inner) => now(&state, inner) && (prev.is_none() || before),
+                _ => false,
+            };
+            if holds {
+                state.1.insert(*f);
+            }
+        }
+        state
+    }
+}
+
+impl<S: StateSpace> StateSpace for HistorySpace<S> {
+    type State = HistoryState<S::State>;
+
+    fn initial(&self) -> Self::State {
+        self.step(self.space.initial(), None)
+    }
+
+    fn successors(&self, state: &Self::State) -> Vec<(usize, Self::State)> {
+        self.space.successors(&state.0).into_iter()
+            .map(|(t, s)| (t, self.step(s, Some(state))))
+            .collect()
+    }
+
+    fn eval(&self, state: &Self::State, atom: &PTAtom) -> Option<bool> {
+        self.space.eval(&state.0, atom)
+    }
+
+    fn marking(&self, state: &Self::State) -> Config {
+        self.space.marking(&state.0)
+    }
+}
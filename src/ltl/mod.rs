@@ -9,8 +9,10 @@ use std::fs::File;
 use std::io::prelude::*;
 
 pub mod checker;
+pub mod fairness;
+pub mod history;
 pub mod vwaa;
-pub mod gba;
+pub mod rewrite;
 pub mod translator;
 
 use crate::ltl::translator::*;
@@ -43,9 +45,20 @@ pub fn ltl_subf_recur(set: &mut HashSet<FormulaTy>, input: FormulaTy) {
             ltl_subf_recur(set, *lhs.clone());
             ltl_subf_recur(set, *rhs.clone());
         },
+        FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) => {
+            ltl_subf_recur(set, *lhs.clone());
+            ltl_subf_recur(set, *rhs.clone());
+        },
         FormulaTy::Forall(inner) => {
             ltl_subf_recur(set, *inner.clone());
         },
+        FormulaTy::Yesterday(inner) | FormulaTy::Once(inner) | FormulaTy::Historically(inner) => {
+            ltl_subf_recur(set, *inner.clone());
+        },
+        FormulaTy::Since(lhs, rhs) => {
+            ltl_subf_recur(set, *lhs.clone());
+            ltl_subf_recur(set, *rhs.clone());
+        },
         _ => {},
     }
 }
@@ -94,7 +107,19 @@ pub fn ltl_negate(input: FormulaTy) -> FormulaTy {
             Box::new(ltl_negate(*lhs.clone())),
             Box::new(ltl_negate(*rhs.clone())),
         ),
+        FormulaTy::WeakUntil(lhs, rhs) => FormulaTy::StrongRelease(
+            Box::new(ltl_negate(*lhs.clone())),
+            Box::new(ltl_negate(*rhs.clone())),
+        ),
+        FormulaTy::StrongRelease(lhs, rhs) => FormulaTy::WeakUntil(
+            Box::new(ltl_negate(*lhs.clone())),
+            Box::new(ltl_negate(*rhs.clone())),
+        ),
         FormulaTy::Forall(inner) => FormulaTy::Forall(Box::new(ltl_negate(*inner.clone()))),
+        FormulaTy::Once(inner) => FormulaTy::Historically(Box::new(ltl_negate(*inner.clone()))),
+        FormulaTy::Historically(inner) => FormulaTy::Once(Box::new(ltl_negate(*inner.clone()))),
+        // The weak yesterday and back-to duals aren't in the language, these stay negated
+        FormulaTy::Yesterday(_) | FormulaTy::Since(_, _) => FormulaTy::Not(Box::new(input)),
         _ => {
             println!("Strange formular: {:?}", input.clone());
             input.clone()
@@ -130,6 +155,14 @@ pub fn ltl_simplify(input: FormulaTy) -> FormulaTy {
             Box::new(ltl_simplify(*lhs.clone())),
             Box::new(ltl_simplify(*rhs.clone())),
         ),
+        FormulaTy::WeakUntil(lhs, rhs) => FormulaTy::WeakUntil(
+            Box::new(ltl_simplify(*lhs.clone())),
+            Box::new(ltl_simplify(*rhs.clone())),
+        ),
+        FormulaTy::StrongRelease(lhs, rhs) => FormulaTy::StrongRelease(
+            Box::new(ltl_simplify(*lhs.clone())),
+            Box::new(ltl_simplify(*rhs.clone())),
+        ),
         FormulaTy::Forall(inner) => FormulaTy::Forall(Box::new(ltl_simplify(*inner.clone()))),
         _ => input.clone(),
     }
@@ -0,0 +1,414 @@
+use crate::logics::*;
+
+// Passes over the whole formula; each one either shrinks it or moves X outwards, so a fixpoint is
+// reached long before this in practice
+const MAX_PASSES: usize = 64;
+
+// Rewrites the (negated) formula before translation: negations are pushed to the atoms, then the
+// rules below are applied bottom-up until nothing changes. All of them preserve the language of
+// infinite words, and each removed subformula saves VWAA states and, through them, BA states.
+pub fn ltl_rewrite(input: FormulaTy) -> FormulaTy {
+    if let FormulaTy::Forall(inner) = input {
+        return FormulaTy::Forall(Box::new(ltl_rewrite(*inner)));
+    }
+    let mut f = nnf(input, true);
+    for _ in 0..MAX_PASSES {
+        let next = rewrite(&f);
+        if next == f {
+            break;
+        }
+        f = next;
+    }
+    f
+}
+
+// Negation normal form: Not only survives above path quantifiers and past-time operators, which
+// the checker evaluates on the run like atoms; atoms are negated with Neg
+pub fn nnf(f: FormulaTy, positive: bool) -> FormulaTy {
+    let dual = |f: fn(Box<FormulaTy>) -> FormulaTy, g: fn(Box<FormulaTy>) -> FormulaTy, inner: Box<FormulaTy>| {
+        (if positive { f } else { g })(Box::new(nnf(*inner, positive)))
+    };
+    let dual2 = |f: fn(Box<FormulaTy>, Box<FormulaTy>) -> FormulaTy, g: fn(Box<FormulaTy>, Box<FormulaTy>) -> FormulaTy,
+                 lhs: Box<FormulaTy>, rhs: Box<FormulaTy>| {
+        (if positive { f } else { g })(Box::new(nnf(*lhs, positive)), Box::new(nnf(*rhs, positive)))
+    };
+    match f {
+        FormulaTy::True | FormulaTy::False => if (f == FormulaTy::True) == positive { FormulaTy::True } else { FormulaTy::False },
+        FormulaTy::Prop(atom) => if positive { FormulaTy::Prop(atom) } else { FormulaTy::Neg(atom) },
+        FormulaTy::Neg(atom) => if positive { FormulaTy::Neg(atom) } else { FormulaTy::Prop(atom) },
+        FormulaTy::Not(inner) => nnf(*inner, !positive),
+        FormulaTy::Or(lhs, rhs) => dual2(FormulaTy::Or, FormulaTy::And, lhs, rhs),
+        FormulaTy::And(lhs, rhs) => dual2(FormulaTy::And, FormulaTy::Or, lhs, rhs),
+        FormulaTy::Next(inner) => FormulaTy::Next(Box::new(nnf(*inner, positive))),
+        FormulaTy::Global(inner) => dual(FormulaTy::Global, FormulaTy::Finally, inner),
+        FormulaTy::Finally(inner) => dual(FormulaTy::Finally, FormulaTy::Global, inner),
+        FormulaTy::Until(lhs, rhs) => dual2(FormulaTy::Until, FormulaTy::Release, lhs, rhs),
+        FormulaTy::Release(lhs, rhs) => dual2(FormulaTy::Release, FormulaTy::Until, lhs, rhs),
+        FormulaTy::WeakUntil(lhs, rhs) => dual2(FormulaTy::WeakUntil, FormulaTy::StrongRelease, lhs, rhs),
+        FormulaTy::StrongRelease(lhs, rhs) => dual2(FormulaTy::StrongRelease, FormulaTy::WeakUntil, lhs, rhs),
+        FormulaTy::Forall(_) | FormulaTy::Exists(_)
+        | FormulaTy::Yesterday(_) | FormulaTy::Since(_, _) | FormulaTy::Once(_) | FormulaTy::Historically(_) => {
+            if positive { f } else { FormulaTy::Not(Box::new(f)) }
+        },
+    }
+}
+
+// Pure eventualities: f holds on a word iff it holds on every word with that suffix, i.e. f = F f
+pub fn is_eventual(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::True | FormulaTy::False | FormulaTy::Finally(_) => true,
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs) => is_eventual(lhs) && is_eventual(rhs),
+        FormulaTy::Next(inner) | FormulaTy::Global(inner) => is_eventual(inner),
+        FormulaTy::Until(_, rhs) | FormulaTy::Release(_, rhs) => is_eventual(rhs),
+        _ => false,
+    }
+}
+
+// Purely universal formulas: f holds on a word iff it holds on all of its suffixes, i.e. f = G f
+pub fn is_universal(f: &FormulaTy) -> bool {
+    match f {
+        FormulaTy::True | FormulaTy::False | FormulaTy::Global(_) => true,
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs) => is_universal(lhs) && is_universal(rhs),
+        FormulaTy::Next(inner) | FormulaTy::Finally(inner) => is_universal(inner),
+        FormulaTy::Until(_, rhs) | FormulaTy::Release(_, rhs) => is_universal(rhs),
+        // a W u = u | G a
+        FormulaTy::WeakUntil(_, rhs) => is_universal(rhs),
+        _ => false,
+    }
+}
+
+// Suspendable formulas don't depend on any finite prefix, e.g. G F p
+fn is_suspendable(f: &FormulaTy) -> bool {
+    is_eventual(f) && is_universal(f)
+}
+
+fn rewrite(f: &FormulaTy) -> FormulaTy {
+    let b = |f: &FormulaTy| Box::new(rewrite(f));
+    match f {
+        FormulaTy::Next(inner) => next(rewrite(inner)),
+        FormulaTy::Finally(inner) => finally(rewrite(inner)),
+        FormulaTy::Global(inner) => global(rewrite(inner)),
+        FormulaTy::Until(lhs, rhs) => until(rewrite(lhs), rewrite(rhs)),
+        FormulaTy::Release(lhs, rhs) => release(rewrite(lhs), rewrite(rhs)),
+        FormulaTy::WeakUntil(lhs, rhs) => weak_until(rewrite(lhs), rewrite(rhs)),
+        FormulaTy::StrongRelease(lhs, rhs) => strong_release(rewrite(lhs), rewrite(rhs)),
+        FormulaTy::And(..) | FormulaTy::Or(..) => {
+            let conj = matches!(f, FormulaTy::And(..));
+            let mut operands = Vec::new();
+            flatten(f, conj, &mut operands);
+            junction(operands.iter().map(rewrite).collect(), conj)
+        },
+        FormulaTy::Not(inner) => FormulaTy::Not(b(inner)),
+        _ => f.clone(),
+    }
+}
+
+fn next(inner: FormulaTy) -> FormulaTy {
+    // X true = true, X false = false, and X s = s for suspendable s
+    if is_suspendable(&inner) {
+        return inner;
+    }
+    FormulaTy::Next(Box::new(inner))
+}
+
+fn finally(inner: FormulaTy) -> FormulaTy {
+    match inner {
+        // F e = e, which covers F F a and F G F a = G F a
+        _ if is_eventual(&inner) => inner,
+        // F (a U b) = F b
+        FormulaTy::Until(_, rhs) => finally(*rhs),
+        // F X a = X F a, so that X can be merged in conjunctions and disjunctions
+        FormulaTy::Next(inner) => next(finally(*inner)),
+        _ => FormulaTy::Finally(Box::new(inner)),
+    }
+}
+
+fn global(inner: FormulaTy) -> FormulaTy {
+    match inner {
+        // G u = u, which covers G G a and G F G a = F G a
+        _ if is_universal(&inner) => inner,
+        // G (a R b) = G b
+        FormulaTy::Release(_, rhs) => global(*rhs),
+        FormulaTy::Next(inner) => next(global(*inner)),
+        _ => FormulaTy::Global(Box::new(inner)),
+    }
+}
+
+fn until(lhs: FormulaTy, rhs: FormulaTy) -> FormulaTy {
+    match (lhs, rhs) {
+        (_, FormulaTy::True) => FormulaTy::True,
+        (_, FormulaTy::False) => FormulaTy::False,
+        (FormulaTy::False, rhs) => rhs,
+        (FormulaTy::True, rhs) => finally(rhs),
+        (lhs, rhs) if lhs == rhs => lhs,
+        // a U e = e
+        (_, rhs) if is_eventual(&rhs) => rhs,
+        // X a U X b = X (a U b)
+        (FormulaTy::Next(lhs), FormulaTy::Next(rhs)) => next(until(*lhs, *rhs)),
+        (lhs, rhs) => FormulaTy::Until(Box::new(lhs), Box::new(rhs)),
+    }
+}
+
+fn release(lhs: FormulaTy, rhs: FormulaTy) -> FormulaTy {
+    match (lhs, rhs) {
+        (_, FormulaTy::True) => FormulaTy::True,
+        (_, FormulaTy::False) => FormulaTy::False,
+        (FormulaTy::True, rhs) => rhs,
+        (FormulaTy::False, rhs) => global(rhs),
+        (lhs, rhs) if lhs == rhs => lhs,
+        // a R u = u
+        (_, rhs) if is_universal(&rhs) => rhs,
+        (FormulaTy::Next(lhs), FormulaTy::Next(rhs)) => next(release(*lhs, *rhs)),
+        (lhs, rhs) => FormulaTy::Release(Box::new(lhs), Box::new(rhs)),
+    }
+}
+
+fn weak_until(lhs: FormulaTy, rhs: FormulaTy) -> FormulaTy {
+    match (lhs, rhs) {
+        (_, FormulaTy::True) | (FormulaTy::True, _) => FormulaTy::True,
+        (FormulaTy::False, rhs) => rhs,
+        (lhs, FormulaTy::False) => global(lhs),
+        (lhs, rhs) if lhs == rhs => lhs,
+        (FormulaTy::Next(lhs), FormulaTy::Next(rhs)) => next(weak_until(*lhs, *rhs)),
+        (lhs, rhs) => FormulaTy::WeakUntil(Box::new(lhs), Box::new(rhs)),
+    }
+}
+
+fn strong_release(lhs: FormulaTy, rhs: FormulaTy) -> FormulaTy {
+    match (lhs, rhs) {
+        (_, FormulaTy::False) | (FormulaTy::False, _) => FormulaTy::False,
+        (FormulaTy::True, rhs) => rhs,
+        (lhs, FormulaTy::True) => finally(lhs),
+        (lhs, rhs) if lhs == rhs => lhs,
+        (FormulaTy::Next(lhs), FormulaTy::Next(rhs)) => next(strong_release(*lhs, *rhs)),
+        (lhs, rhs) => FormulaTy::StrongRelease(Box::new(lhs), Box::new(rhs)),
+    }
+}
+
+fn flatten(f: &FormulaTy, conj: bool, operands: &mut Vec<FormulaTy>) {
+    match f {
+        FormulaTy::And(lhs, rhs) if conj => {
+            flatten(lhs, conj, operands);
+            flatten(rhs, conj, operands);
+        },
+        FormulaTy::Or(lhs, rhs) if !conj => {
+            flatten(lhs, conj, operands);
+            flatten(rhs, conj, operands);
+        },
+        _ => operands.push(f.clone()),
+    }
+}
+
+// A conjunction (conj) or disjunction of rewritten operands, folded to the left again
+fn junction(operands: Vec<FormulaTy>, conj: bool) -> FormulaTy {
+    let (unit, zero) = if conj { (FormulaTy::True, FormulaTy::False) } else { (FormulaTy::False, FormulaTy::True) };
+    let mut res: Vec<FormulaTy> = Vec::new();
+    for op in operands {
+        let mut inner = Vec::new();
+        flatten(&op, conj, &mut inner);
+        for op in inner {
+            if op == zero || res.contains(&nnf(op.clone(), false)) {
+                return zero;
+            }
+            if op == unit || res.contains(&op) {
+                continue;
+            }
+            match res.iter().position(|other| merge(other, &op, conj).is_some()) {
+                Some(i) => res[i] = merge(&res[i], &op, conj).unwrap(),
+                None => res.push(op),
+            }
+        }
+    }
+    // Absorption: a & (a | b) = a and a | (a & b) = a
+    let absorbed: Vec<bool> = res.iter().map(|op| {
+        let mut inner = Vec::new();
+        flatten(op, !conj, &mut inner);
+        inner.len() > 1 && res.iter().any(|other| other != op && inner.contains(other))
+    }).collect();
+    let mut res: Vec<FormulaTy> = res.into_iter().zip(absorbed).filter(|(_, a)| !a).map(|(op, _)| op).collect();
+    if res.is_empty() {
+        return unit;
+    }
+    let first = res.remove(0);
+    res.into_iter().fold(first, |acc, op| {
+        if conj { FormulaTy::And(Box::new(acc), Box::new(op)) } else { FormulaTy::Or(Box::new(acc), Box::new(op)) }
+    })
+}
+
+// Two operands of a conjunction or disjunction that combine into one
+fn merge(lhs: &FormulaTy, rhs: &FormulaTy, conj: bool) -> Option<FormulaTy> {
+    let join = |a: &FormulaTy, b: &FormulaTy| junction(vec![a.clone(), b.clone()], conj);
+    match (lhs, rhs) {
+        // X a & X b = X (a & b), X a | X b = X (a | b)
+        (FormulaTy::Next(a), FormulaTy::Next(b)) => Some(next(join(a, b))),
+        // G a & G b = G (a & b), F a | F b = F (a | b)
+        (FormulaTy::Global(a), FormulaTy::Global(b)) if conj => Some(global(join(a, b))),
+        (FormulaTy::Finally(a), FormulaTy::Finally(b)) if !conj => Some(finally(join(a, b))),
+        // F G a & F G b = F G (a & b), G F a | G F b = G F (a | b)
+        (FormulaTy::Finally(a), FormulaTy::Finally(b)) if conj => match (&**a, &**b) {
+            (FormulaTy::Global(a), FormulaTy::Global(b)) => Some(finally(global(join(a, b)))),
+            _ => None,
+        },
+        (FormulaTy::Global(a), FormulaTy::Global(b)) if !conj => match (&**a, &**b) {
+            (FormulaTy::Finally(a), FormulaTy::Finally(b)) => Some(global(finally(join(a, b)))),
+            _ => None,
+        },
+        // (a U c) & (b U c) = (a & b) U c, (a U b) | (a U c) = a U (b | c)
+        (FormulaTy::Until(a, c), FormulaTy::Until(b, d)) if conj && c == d => Some(until(join(a, b), *c.clone())),
+        (FormulaTy::Until(a, c), FormulaTy::Until(b, d)) if !conj && a == b => Some(until(*a.clone(), join(c, d))),
+        // (a R b) & (a R c) = a R (b & c), (a R c) | (b R c) = (a | b) R c
+        (FormulaTy::Release(a, c), FormulaTy::Release(b, d)) if conj && a == b => Some(release(*a.clone(), join(c, d))),
+        (FormulaTy::Release(a, c), FormulaTy::Release(b, d)) if !conj && c == d => Some(release(join(a, b), *c.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::props::parse_property;
+    use crate::logics::space::{NetSpace, StateSpace};
+    use crate::logics::transys::Config;
+    use crate::ltl::checker::LTLChecker;
+    use crate::ltl::translator::build_automaton_cav01;
+    use crate::petri::stubborn::Reduction;
+    use crate::petri::{test_net, PTNet};
+    use crate::store::StoreMode;
+
+    // Two tokens on the ring a -> b -> c -> a, with a shortcut from a to c
+    fn ring() -> PTNet {
+        test_net(&[("a", 2), ("b", 0), ("c", 0)], &[("ab", &["a"], &["b"]), ("bc", &["b"], &["c"]), ("ca", &["c"], &["a"]), ("ac", &["a"], &["c"])])
+    }
+
+    fn formula(net: &PTNet, text: &str) -> Formula {
+        let mut f = Formula { name: String::from(text), ty: parse_property(text).unwrap() };
+        f.bind(net).unwrap();
+        f
+    }
+
+    // Truth of f at each position of the lasso run[0..] with run.last() followed by run[back]
+    fn eval(f: &FormulaTy, net: &PTNet, run: &[Config], back: usize) -> Vec<bool> {
+        let n = run.len();
+        let succ = |i: usize| if i + 1 < n { i + 1 } else { back };
+        // Least (init false) or greatest (init true) fixpoint of v[i] = step(i, v[succ(i)])
+        let fix = |init: bool, step: &dyn Fn(usize, bool) -> bool| {
+            let mut v = vec![init; n];
+            for _ in 0..2 * n {
+                for i in (0..n).rev() {
+                    v[i] = step(i, v[succ(i)]);
+                }
+            }
+            v
+        };
+        match f {
+            FormulaTy::Forall(inner) => eval(inner, net, run, back),
+            FormulaTy::Not(inner) => eval(inner, net, run, back).into_iter().map(|b| !b).collect(),
+            FormulaTy::And(a, b) | FormulaTy::Or(a, b) => {
+                let (a, b) = (eval(a, net, run, back), eval(b, net, run, back));
+                let conj = matches!(f, FormulaTy::And(..));
+                (0..n).map(|i| if conj { a[i] && b[i] } else { a[i] || b[i] }).collect()
+            },
+            FormulaTy::Next(inner) => {
+                let v = eval(inner, net, run, back);
+                (0..n).map(|i| v[succ(i)]).collect()
+            },
+            FormulaTy::Finally(inner) => {
+                let v = eval(inner, net, run, back);
+                fix(false, &|i, next| v[i] || next)
+            },
+            FormulaTy::Global(inner) => {
+                let v = eval(inner, net, run, back);
+                fix(true, &|i, next| v[i] && next)
+            },
+            FormulaTy::Until(a, b) | FormulaTy::WeakUntil(a, b) => {
+                let (a, b) = (eval(a, net, run, back), eval(b, net, run, back));
+                fix(matches!(f, FormulaTy::WeakUntil(..)), &|i, next| b[i] || (a[i] && next))
+            },
+            FormulaTy::Release(a, b) | FormulaTy::StrongRelease(a, b) => {
+                let (a, b) = (eval(a, net, run, back), eval(b, net, run, back));
+                fix(matches!(f, FormulaTy::Release(..)), &|i, next| b[i] && (a[i] || next))
+            },
+            _ => run.iter().map(|config| eval_state(f, net, config).unwrap()).collect(),
+        }
+    }
+
+    // Every lasso of the net's state graph with at most len states before closing the loop
+    fn lassos(net: &PTNet, len: usize) -> Vec<(Vec<Config>, usize)> {
+        let space = NetSpace::new(net);
+        let mut res = Vec::new();
+        let mut paths = vec![vec![space.initial()]];
+        while let Some(path) = paths.pop() {
+            let succs: Vec<_> = space.successors(path.last().unwrap()).into_iter().map(|(_, s)| s).collect();
+            let run: Vec<Config> = path.iter().map(|s| space.marking(s)).collect();
+            for (back, s) in path.iter().enumerate() {
+                if succs.contains(s) {
+                    res.push((run.clone(), back));
+                }
+            }
+            if path.len() < len {
+                for s in succs {
+                    let mut next = path.clone();
+                    next.push(s);
+                    paths.push(next);
+                }
+            }
+        }
+        res
+    }
+
+    fn temporal(f: &FormulaTy) -> usize {
+        match f {
+            FormulaTy::Until(a, b) | FormulaTy::Release(a, b) => 1 + temporal(a) + temporal(b),
+            FormulaTy::And(a, b) | FormulaTy::Or(a, b) => temporal(a) + temporal(b),
+            FormulaTy::Forall(inner) => temporal(inner),
+            _ => 0,
+        }
+    }
+
+    // The sound merges turn two Until/Release operands into one, the unsound shapes are left
+    // alone, and either way the rewritten formula holds on exactly the same runs
+    #[test]
+    fn merges_preserve_runs() {
+        let net = ring();
+        let runs = lassos(&net, 7);
+        assert!(runs.len() > 100);
+        let cases = [
+            ("A((a >= 1 U c >= 1) & (b >= 1 U c >= 1))", 1),
+            ("A((a >= 1 U b >= 1) | (a >= 1 U c >= 1))", 1),
+            ("A((a >= 1 R b >= 1) & (a >= 1 R c >= 1))", 1),
+            ("A((a >= 1 R c >= 1) | (b >= 1 R c >= 1))", 1),
+            ("A((a >= 1 U c >= 1) | (b >= 1 U c >= 1))", 2),
+            ("A((a >= 1 U b >= 1) & (a >= 1 U c >= 1))", 2),
+            ("A((a >= 1 R b >= 1) | (a >= 1 R c >= 1))", 2),
+            ("A((a >= 1 R c >= 1) & (b >= 1 R c >= 1))", 2),
+        ];
+        for (text, merged) in cases {
+            let f = formula(&net, text).ty;
+            let rewritten = ltl_rewrite(f.clone());
+            assert_eq!(temporal(&rewritten), merged, "{}: {:?}", text, rewritten);
+            for (run, back) in runs.iter() {
+                assert_eq!(eval(&f, &net, run, *back)[0], eval(&rewritten, &net, run, *back)[0], "{}", text);
+            }
+        }
+    }
+
+    // Neither token can reach c without leaving a and b empty on the way, so the disjunction of
+    // Untils fails while (a | b) U c would hold
+    #[test]
+    fn checker_agrees_with_runs() {
+        let net = test_net(&[("a", 1), ("b", 0), ("c", 0)], &[("ab", &["a"], &["b"]), ("bc", &["b"], &["c"]), ("ca", &["c"], &["a"])]);
+        let runs = lassos(&net, 7);
+        let cases = [
+            ("A((a >= 1 U c >= 1) | (b >= 1 U c >= 1))", false),
+            ("A((a >= 1 | b >= 1) U c >= 1)", true),
+            ("A((a >= 1 R c < 1) & (b >= 1 R c < 1))", true),
+        ];
+        for (text, expected) in cases {
+            let f = formula(&net, text);
+            assert_eq!(runs.iter().all(|(run, back)| eval(&f.ty, &net, run, *back)[0]), expected, "{}", text);
+            let (auto, fin, formulas) = build_automaton_cav01(&f).unwrap();
+            let mut checker = LTLChecker::new(NetSpace::new(&net), &net, auto, fin, formulas, Reduction::Off, StoreMode::Exact);
+            assert_eq!(checker.check(), expected, "{}", text);
+        }
+    }
+}
@@ -1,50 +1,40 @@
-use std::collections::{HashMap, HashSet};
-use std::default::Default;
-
-use bimap::BiMap;
-use petgraph::graph::*;
-use petgraph::dot::{Dot, Config};
+use std::collections::BTreeSet;
 
 use crate::logics::*;
-use crate::ltl::*;
-use crate::utils::*;
+use crate::logics::intern::{FormulaId, FormulaStore, Node};
+
+// VWAA transitions as (action, destination): the action is the set of literals read, {true} for
+// any letter, and the destination the conjunction of states moved to, {true} for none
+pub type VwaaTrans = BTreeSet<(FormulaSet, FormulaSet)>;
 
-pub fn vwaa_bar(f: FormulaTy) -> FormulaSet {
-    let mut res = FormulaSet::new();
-    match f {
-        FormulaTy::Or(lhs, rhs) => {
-            let mut lset = vwaa_bar(*lhs.clone());
-            let mut rset = vwaa_bar(*rhs.clone());
-            res = lset.union(&rset);
+fn singleton(id: FormulaId) -> FormulaSet {
+    FormulaSet::from_iter(vec![id])
+}
+
+// Disjunctive normal form of f over VWAA states, one set per conjunction
+pub fn vwaa_bar(store: &FormulaStore, f: FormulaId) -> BTreeSet<FormulaSet> {
+    match store.node(f) {
+        Node::Or(lhs, rhs) => vwaa_bar(store, *lhs).union(&vwaa_bar(store, *rhs)).cloned().collect(),
+        Node::And(lhs, rhs) => {
+            let rset = vwaa_bar(store, *rhs);
+            vwaa_bar(store, *lhs).iter().flat_map(|l| rset.iter().map(move |r| l.union(r))).collect()
         },
-        FormulaTy::And(lhs, rhs) => {
-            let lset = vwaa_bar(*lhs.clone());
-            let rset = vwaa_bar(*rhs.clone());
-            for l in lset.set.iter() {
-                for r in rset.set.iter() {
-                    res.insert(FormulaTy::And(Box::new(l.clone()), Box::new(r.clone())));
-                }
-            }
-        }
-        _ => {
-            res.insert(f.clone());
-        }
+        _ => BTreeSet::from([singleton(f)]),
     }
-    res
 }
 
-pub fn vwaa_product(lhs: HashSet<(FormulaSet, FormulaTy)>,
-    rhs: HashSet<(FormulaSet, FormulaTy)>) -> HashSet<(FormulaSet, FormulaTy)> {
-    let mut res = HashSet::new();
+pub fn vwaa_product(lhs: &VwaaTrans, rhs: &VwaaTrans) -> VwaaTrans {
+    let tt = singleton(FormulaStore::TRUE);
+    let mut res = BTreeSet::new();
     for (l_key, l_val) in lhs.iter() {
         for (r_key, r_val) in rhs.iter() {
-            let key = l_key.union(&r_key);
-            let val = if *l_val == FormulaTy::True {
+            let key = l_key.union(r_key);
+            let val = if *l_val == tt {
                 r_val.clone()
-            } else if *r_val == FormulaTy::True {
+            } else if *r_val == tt {
                 l_val.clone()
             } else {
-                FormulaTy::And(Box::new(l_val.clone()), Box::new(r_val.clone()))
+                l_val.union(r_val)
             };
             res.insert((key, val));
         }
@@ -52,67 +42,46 @@ pub fn vwaa_product(lhs: HashSet<(FormulaSet, FormulaTy)>,
     res
 }
 
-pub fn vwaa_cap_delta(f: FormulaTy) -> HashSet<(FormulaSet, FormulaTy)> {
-    match f {
-        FormulaTy::Or(lhs, rhs) => {
-            return vwaa_cap_delta(*lhs.clone()).union(&vwaa_cap_delta(*rhs.clone())).map(|i| i.clone()).collect();
-        }
-        FormulaTy::And(lhs, rhs) => {
-            return vwaa_product(vwaa_cap_delta(*lhs.clone()), vwaa_cap_delta(*rhs.clone()));
-        }
-        _ => {
-            return vwaa_delta(f);
-        }
+pub fn vwaa_cap_delta(store: &FormulaStore, f: FormulaId) -> VwaaTrans {
+    match store.node(f) {
+        Node::Or(lhs, rhs) => vwaa_cap_delta(store, *lhs).union(&vwaa_cap_delta(store, *rhs)).cloned().collect(),
+        Node::And(lhs, rhs) => vwaa_product(&vwaa_cap_delta(store, *lhs), &vwaa_cap_delta(store, *rhs)),
+        _ => vwaa_delta(store, f),
     }
 }
 
-pub fn vwaa_delta(f: FormulaTy) -> HashSet<(FormulaSet, FormulaTy)> {
-    // Returns transition set where element (sigma, q), sigma is actually set of letters that include formula sigma
-    let mut res = HashSet::new();
-    let tt = FormulaSet::from_iter(vec![FormulaTy::True]);
-    let p = FormulaSet::from_iter(vec![f.clone()]);
-
-    match f.clone() {
-        FormulaTy::True => {
-            res.insert((tt.clone(), FormulaTy::True));
-            return res;
-        },
-        FormulaTy::False => {
-            return res;
+pub fn vwaa_delta(store: &FormulaStore, f: FormulaId) -> VwaaTrans {
+    let tt = singleton(FormulaStore::TRUE);
+    let mut res = BTreeSet::new();
+    match store.node(f) {
+        Node::True => {
+            res.insert((tt.clone(), tt));
         },
-        FormulaTy::Prop(atom) => {
-            res.insert((p.clone(), FormulaTy::True));
-            return res;
+        // Past-time formulas are literals too, read off the history carried with the state
+        Node::Prop(_) | Node::Neg(_) | Node::Not(_)
+        | Node::Yesterday(_) | Node::Since(_, _) | Node::Once(_) | Node::Historically(_) => {
+            res.insert((singleton(f), tt));
         },
-        FormulaTy::Neg(atom) => {
-            res.insert((p.clone(), FormulaTy::True));
-            return res;
-        },
-        FormulaTy::Next(inner) => {
-            let bar = vwaa_bar(*inner.clone());
-            for b in bar.set.iter() {
-                res.insert((tt.clone(), b.clone()));
+        Node::Next(inner) => {
+            for b in vwaa_bar(store, *inner) {
+                res.insert((tt.clone(), b));
             }
-            return res;
         },
-        FormulaTy::Until(lhs, rhs) => {
-            res.insert((tt.clone(), f.clone()));
-            let res_left = vwaa_cap_delta(*rhs.clone());
-            let res_right = vwaa_product(vwaa_cap_delta(*lhs.clone()), res);
-            return res_left.union(&res_right).map(|i| i.clone()).collect();
+        // a W b moves like a U b but isn't final, a M b like a R b but is: it must leave its loop
+        Node::Until(lhs, rhs) | Node::WeakUntil(lhs, rhs) => {
+            res.insert((tt, singleton(f)));
+            let res_left = vwaa_cap_delta(store, *rhs);
+            let res_right = vwaa_product(&vwaa_cap_delta(store, *lhs), &res);
+            return res_left.union(&res_right).cloned().collect();
         },
-        FormulaTy::Release(lhs, rhs) => {
-            res.insert((tt.clone(), f.clone()));
-            let res_left = vwaa_cap_delta(*rhs.clone());
-            let res_right = vwaa_cap_delta(*lhs.clone()).union(&res).map(|i| i.clone()).collect();
-            return vwaa_product(res_left, res_right);
+        Node::Release(lhs, rhs) | Node::StrongRelease(lhs, rhs) => {
+            res.insert((tt, singleton(f)));
+            let res_left = vwaa_cap_delta(store, *rhs);
+            let res_right = vwaa_cap_delta(store, *lhs).union(&res).cloned().collect();
+            return vwaa_product(&res_left, &res_right);
         },
-        FormulaTy::Or(lhs, rhs) => {
-            return vwaa_cap_delta(f.clone());
-        }
-        FormulaTy::And(lhs, rhs) => {
-            return vwaa_cap_delta(f.clone());
-        } 
-        _ => {return res;},
+        Node::Or(_, _) | Node::And(_, _) => return vwaa_cap_delta(store, f),
+        _ => {},
     }
-}
\ No newline at end of file
+    res
+}
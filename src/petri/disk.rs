@@ -0,0 +1,308 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::logics::transys::Config;
+use crate::petri::*;
+
+type Arcs = Vec<(usize, usize)>;
+
+// Visited runs merged into one once there are more of them
+const MAX_VISITED_RUNS: usize = 16;
+
+#[derive(Debug, Clone, Default)]
+pub struct DiskReport {
+    pub states: u64,
+    // Number of BFS layers, i.e. the largest distance from the initial marking plus one
+    pub layers: usize,
+    pub deadlock: Option<Config>,
+    // False when the visitor stopped the search early
+    pub complete: bool,
+}
+
+// Breadth-first exploration with delayed duplicate detection: the successors of a layer are
+// buffered up to the memory budget, then sorted and written out as runs. After the layer the runs
+// are merged and the visited runs subtracted, giving the next layer, which becomes a visited run
+// itself. Only the buffer and one record per open run are held in memory.
+pub struct DiskExplorer<'a> {
+    pub net: &'a PTNet,
+    pub dir: PathBuf,
+    // Bytes of successor markings buffered before a run is written
+    pub budget: usize,
+    places: Vec<usize>,
+    // (consumed, produced) arcs over positions in place_indices()
+    transitions: Vec<(Arcs, Arcs)>,
+    files: usize,
+}
+
+impl<'a> DiskExplorer<'a> {
+    pub fn new(net: &'a PTNet, dir: &Path, budget: usize) -> DiskExplorer<'a> {
+        let places = net.place_indices();
+        let position: HashMap<usize, usize> = places.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+        let transitions = net.transition_indices().iter().map(|t| {
+            let tran = &net.transitions[t];
+            (tran.conditions.iter().map(|(p, w)| (position[p], *w)).collect(),
+             tran.effects.iter().map(|(p, w)| (position[p], *w)).collect())
+        }).collect();
+        DiskExplorer {
+            net,
+            dir: dir.to_path_buf(),
+            budget,
+            places,
+            transitions,
+            files: 0,
+        }
+    }
+
+    fn config(&self, marking: &[u64]) -> Config {
+        self.places.iter().zip(marking.iter()).map(|(p, v)| (*p, *v as usize)).collect()
+    }
+
+    fn next_file(&mut self) -> PathBuf {
+        self.files += 1;
+        self.dir.join(format!("ptchecker-{}-{}.run", std::process::id(), self.files))
+    }
+
+    // Visit every reachable marking once, layer by layer; the visitor returns true to stop
+    pub fn explore<F: FnMut(&Config) -> bool>(&mut self, mut visit: F) -> io::Result<DiskReport> {
+        fs::create_dir_all(&self.dir)?;
+        let width = self.places.len();
+        let init: Vec<u64> = self.places.iter().map(|p| self.net.places[p].get_tokens() as u64).collect();
+        let mut report = DiskReport { states: 1, layers: 1, ..DiskReport::default() };
+        let frontier_path = self.next_file();
+        write_run(&frontier_path, std::slice::from_ref(&init))?;
+        let mut frontier = frontier_path.clone();
+        let mut visited = vec![frontier_path];
+        let mut stopped = visit(&self.config(&init));
+        let per_state = (8 * width + 24).max(1);
+        while !stopped {
+            // Expand the frontier into sorted candidate runs
+            let mut runs = Vec::new();
+            let mut buffer: Vec<Vec<u64>> = Vec::new();
+            let mut reader = RunReader::open(&frontier, width)?;
+            while let Some(marking) = reader.head.take() {
+                reader.advance()?;
+                let mut enabled = false;
+                for (pre, post) in self.transitions.iter() {
+                    if pre.iter().all(|(p, w)| marking[*p] >= *w as u64) {
+                        enabled = true;
+                        let mut next = marking.clone();
+                        for (p, w) in pre.iter() {
+                            next[*p] -= *w as u64;
+                        }
+                        for (p, w) in post.iter() {
+                            next[*p] += *w as u64;
+                        }
+                        buffer.push(next);
+                    }
+                }
+                if !enabled && report.deadlock.is_none() {
+                    report.deadlock = Some(self.config(&marking));
+                }
+                if buffer.len() * per_state >= self.budget {
+                    runs.push(self.spill(&mut buffer)?);
+                }
+            }
+            if !buffer.is_empty() {
+                runs.push(self.spill(&mut buffer)?);
+            }
+            // Merge the candidates and drop everything already visited
+            let next_path = self.next_file();
+            let mut writer = BufWriter::new(File::create(&next_path)?);
+            let mut candidates = Merger::open(&runs, width)?;
+            let mut seen = Merger::open(&visited, width)?;
+            let mut found = 0;
+            while let Some(marking) = candidates.next()? {
+                while seen.peek().is_some_and(|m| *m < marking) {
+                    seen.next()?;
+                }
+                if seen.peek() == Some(&marking) {
+                    continue;
+                }
+                write_record(&mut writer, &marking)?;
+                found += 1;
+                if !stopped && visit(&self.config(&marking)) {
+                    stopped = true;
+                }
+            }
+            writer.flush()?;
+            for run in runs.iter() {
+                fs::remove_file(run)?;
+            }
+            if found == 0 {
+                fs::remove_file(&next_path)?;
+                break;
+            }
+            report.states += found;
+            report.layers += 1;
+            frontier = next_path.clone();
+            visited.push(next_path);
+            if visited.len() > MAX_VISITED_RUNS {
+                let merged = self.next_file();
+                merge_runs(&visited, &merged, width)?;
+                for run in visited.iter() {
+                    // The frontier is read once more for the next layer
+                    if *run != frontier {
+                        fs::remove_file(run)?;
+                    }
+                }
+                visited = vec![merged, frontier.clone()];
+            }
+        }
+        for run in visited.iter() {
+            fs::remove_file(run)?;
+        }
+        report.complete = !stopped;
+        Ok(report)
+    }
+
+    fn spill(&mut self, buffer: &mut Vec<Vec<u64>>) -> io::Result<PathBuf> {
+        buffer.sort_unstable();
+        buffer.dedup();
+        let path = self.next_file();
+        write_run(&path, buffer)?;
+        buffer.clear();
+        Ok(path)
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, marking: &[u64]) -> io::Result<()> {
+    for v in marking.iter() {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_run(path: &Path, markings: &[Vec<u64>]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for m in markings.iter() {
+        write_record(&mut writer, m)?;
+    }
+    writer.flush()
+}
+
+fn merge_runs(runs: &[PathBuf], path: &Path, width: usize) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut merger = Merger::open(runs, width)?;
+    while let Some(marking) = merger.next()? {
+        write_record(&mut writer, &marking)?;
+    }
+    writer.flush()
+}
+
+// Sequential reader over a run of fixed-width records
+struct RunReader {
+    reader: BufReader<File>,
+    width: usize,
+    head: Option<Vec<u64>>,
+}
+
+impl RunReader {
+    fn open(path: &Path, width: usize) -> io::Result<RunReader> {
+        let mut run = RunReader { reader: BufReader::new(File::open(path)?), width, head: None };
+        run.advance()?;
+        Ok(run)
+    }
+
+    fn advance(&mut self) -> io::Result<()> {
+        let mut bytes = vec![0u8; 8 * self.width];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => {
+                self.head = Some(bytes.chunks(8).map(|c| u64::from_le_bytes(c.try_into().unwrap())).collect());
+                Ok(())
+            },
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.head = None;
+                Ok(())
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// K-way merge of sorted runs, yielding each marking once
+struct Merger {
+    runs: Vec<RunReader>,
+    heap: BinaryHeap<Reverse<(Vec<u64>, usize)>>,
+}
+
+impl Merger {
+    fn open(paths: &[PathBuf], width: usize) -> io::Result<Merger> {
+        let mut merger = Merger { runs: Vec::new(), heap: BinaryHeap::new() };
+        for path in paths.iter() {
+            let mut run = RunReader::open(path, width)?;
+            if let Some(head) = run.head.take() {
+                merger.heap.push(Reverse((head, merger.runs.len())));
+            }
+            merger.runs.push(run);
+        }
+        Ok(merger)
+    }
+
+    fn peek(&self) -> Option<&Vec<u64>> {
+        self.heap.peek().map(|Reverse((m, _))| m)
+    }
+
+    fn next(&mut self) -> io::Result<Option<Vec<u64>>> {
+        let Some(Reverse((marking, i))) = self.heap.pop() else { return Ok(None) };
+        self.refill(i)?;
+        // Skip copies of the same marking in other runs
+        while self.peek() == Some(&marking) {
+            let Reverse((_, j)) = self.heap.pop().unwrap();
+            self.refill(j)?;
+        }
+        Ok(Some(marking))
+    }
+
+    fn refill(&mut self, i: usize) -> io::Result<()> {
+        self.runs[i].advance()?;
+        if let Some(head) = self.runs[i].head.take() {
+            self.heap.push(Reverse((head, i)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::logics::transys::TranSys;
+    use crate::petri::parser::parse_pnml;
+
+    fn sorted(config: &Config) -> Vec<(usize, usize)> {
+        let mut m: Vec<(usize, usize)> = config.iter().map(|(p, v)| (*p, *v)).collect();
+        m.sort();
+        m
+    }
+
+    // A budget of a few markings spills several runs per layer, and the layers outnumber
+    // MAX_VISITED_RUNS so the visited runs get merged; the markings are still those of the
+    // explicit graph, each visited once, and no run is left behind
+    #[test]
+    fn explores_with_small_budget() {
+        let net = parse_pnml("data/SatelliteMemory-PT-X00010Y0003/model.pnml").unwrap().remove(0);
+        let dir = std::env::temp_dir().join(format!("ptchecker-disk-test-{}", std::process::id()));
+        let per_state = 8 * net.places.len() + 24;
+        let mut explorer = DiskExplorer::new(&net, &dir, 4 * per_state);
+        let mut visited = Vec::new();
+        let report = explorer.explore(|config| {
+            visited.push(config.clone());
+            false
+        }).unwrap();
+        assert!(report.complete);
+        assert_eq!(report.states, 398);
+        assert!(report.layers > MAX_VISITED_RUNS, "{} layers", report.layers);
+        assert!(explorer.files > 2 * report.layers);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+
+        let tran = TranSys::from_petri(&net);
+        let expected: HashSet<Vec<(usize, usize)>> = (0..tran.stats().states).map(|s| sorted(tran.config_of(s).unwrap())).collect();
+        let found: HashSet<Vec<(usize, usize)>> = visited.iter().map(sorted).collect();
+        assert_eq!(visited.len(), 398);
+        assert_eq!(found, expected);
+    }
+}
@@ -1,9 +1,16 @@
 use bimap::BiMap;
 use petgraph::Graph;
 use petgraph::graph::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
+use crate::store::{marking_key, StateStore, StoreMode, Visit};
+
+pub mod disk;
+pub mod parallel;
 pub mod parser;
+pub mod reach;
+pub mod stubborn;
+pub mod unfolding;
 
 #[derive(Debug, Clone)]
 pub struct Arc {
@@ -95,10 +102,22 @@ impl PTNet {
         self.places.contains_key(idx)
     }
 
+    // Place indices in ascending order, used as the dense numbering of marking vectors
+    pub fn place_indices(&self) -> Vec<usize> {
+        let mut res: Vec<usize> = self.places.keys().copied().collect();
+        res.sort();
+        res
+    }
+
+    pub fn transition_indices(&self) -> Vec<usize> {
+        let mut res: Vec<usize> = self.transitions.keys().copied().collect();
+        res.sort();
+        res
+    }
+
     pub fn reachability_graph(&self) -> Graph::<HashMap<usize, usize>, String> {
         // Construct reachability graph, nodes for markings and edges for transition name
         let mut res = Graph::<HashMap<usize, usize>, String>::new();
-        let mut node_map = HashMap::<NodeIndex, HashMap<usize, usize>>::new();
         // Fetch initial marking
         let mut init_marking = HashMap::new();
         for (k, p) in self.places.iter() {
@@ -109,13 +128,14 @@ impl PTNet {
             init_marking.insert(k.clone(), val.clone());
         }
         // println!("init: {:?}", init_marking);
-        let mut queue = Vec::new();
+        let places = self.place_indices();
+        let mut store = StateStore::new(StoreMode::Exact);
+        let mut queue = VecDeque::new();
+        store.insert(marking_key(&init_marking, &places));
         let idx = res.add_node(init_marking.clone());
-        queue.push(idx.clone());
-        node_map.insert(idx, init_marking);
-        while queue.len() != 0 {
-            let src_idx = queue.remove(0);
-            let marking = node_map.get(&src_idx).unwrap().clone();
+        queue.push_back(idx);
+        while let Some(src_idx) = queue.pop_front() {
+            let marking = res[src_idx].clone();
             for (k, t) in self.transitions.iter() {
                 let mut fireable = true;
                 for (place, size) in t.conditions.iter() {
@@ -137,23 +157,18 @@ impl PTNet {
                         *place += num;
                     }
                     println!("new marking: {:?}", new_marking);
-                    let mut added = false;
-                    for (k, m) in node_map.iter() {
-                        if *m == new_marking {
-                            // println!("repeated marking: {:?}", new_marking);
-                            added = true;
-                            res.add_edge(src_idx.clone(), k.clone(), format!("Fireability({:?})", t.name));
+                    // Store ids follow node insertion, so they double as node indices
+                    match store.insert(marking_key(&new_marking, &places)) {
+                        Visit::New(_) => {
+                            let dst_idx = res.add_node(new_marking.clone());
+                            println!("new node: {:?} {:?}", dst_idx, new_marking);
+                            res.add_edge(src_idx, dst_idx, format!("Fireability({:?})", t.name));
+                            queue.push_back(dst_idx);
                             break;
-                        }
-                    }
-                    if !added {
-                        // println!("new marking: {:?}", new_marking);
-                        let dst_idx = res.add_node(new_marking.clone());
-                        println!("new node: {:?} {:?}", dst_idx, new_marking);
-                        node_map.insert(dst_idx.clone(), new_marking.clone());
-                        res.add_edge(src_idx.clone(), dst_idx.clone(), format!("Fireability({:?})", t.name));
-                        queue.push(dst_idx);
-                        break;
+                        },
+                        Visit::Seen(id) => {
+                            res.add_edge(src_idx, NodeIndex::new(id.unwrap()), format!("Fireability({:?})", t.name));
+                        },
                     }
                 }
             }
@@ -161,4 +176,32 @@ impl PTNet {
         println!("res: {:?}", res);
         res
     }
-}
\ No newline at end of file
+}
+// Net built from (name, initial tokens) places and (name, inputs, outputs) transitions, a place
+// listed twice on one side weighing 2
+#[cfg(test)]
+pub(crate) fn test_net(places: &[(&str, usize)], transitions: &[(&str, &[&str], &[&str])]) -> PTNet {
+    let mut net = PTNet::default();
+    for (name, tokens) in places.iter() {
+        let place = Place { name: name.to_string(), page: String::new(), init: Marking::Plain(*tokens), producers: Vec::new(), consumers: Vec::new() };
+        net.insert_place(place);
+    }
+    for (name, inputs, outputs) in transitions.iter() {
+        let transition = Transition { name: name.to_string(), page: String::new(), conditions: Vec::new(), effects: Vec::new() };
+        net.insert_transition(transition);
+        for (side, arcs) in [("in", inputs), ("out", outputs)] {
+            let mut weights: Vec<(&str, usize)> = Vec::new();
+            for p in arcs.iter() {
+                match weights.iter_mut().find(|(q, _)| q == p) {
+                    Some((_, w)) => *w += 1,
+                    None => weights.push((p, 1)),
+                }
+            }
+            for (p, w) in weights {
+                let arc = Arc { id: format!("{}-{}-{}", name, side, p), ty: ArcTy::Plain(w) };
+                if side == "in" { net.insert_arc(arc, p.to_string(), name.to_string()) } else { net.insert_arc(arc, name.to_string(), p.to_string()) }
+            }
+        }
+    }
+    net
+}
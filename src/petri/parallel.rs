@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::logics::transys::Config;
+use crate::petri::*;
+use crate::petri::stubborn::{Reduction, StubbornSets};
+use crate::store::{ConcurrentStore, StoreMode, Visit};
+
+// State graph found by the parallel explorer. Ids follow discovery, which depends on scheduling,
+// so callers renumber it (see TranSys::from_petri_parallel) when they need a canonical order.
+#[derive(Debug, Clone, Default)]
+pub struct Explored {
+    pub states: usize,
+    // Left empty when the graph isn't kept. Token counts in place_indices() order
+    pub markings: Vec<Vec<usize>>,
+    // Enabled transitions, in the iteration order of PTNet::transitions
+    pub enabled: Vec<Vec<usize>>,
+    // Fired transitions in the same order with the successor each one reaches, None when the store can't tell
+    pub successors: Vec<Vec<(usize, Option<usize>)>>,
+}
+
+impl Explored {
+    pub fn len(&self) -> usize {
+        self.states
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states == 0
+    }
+}
+
+// (id, marking, enabled, successors) of one expanded state
+type Expanded = (usize, Vec<usize>, Vec<usize>, Vec<(usize, Option<usize>)>);
+
+type WorkQueue = VecDeque<(usize, Vec<usize>)>;
+
+// Transition with its arcs over positions in place_indices()
+struct Dense {
+    index: usize,
+    pre: Vec<(usize, usize)>,
+    post: Vec<(usize, usize)>,
+}
+
+struct Explorer<'a> {
+    places: Vec<usize>,
+    transitions: Vec<Dense>,
+    stubborn: StubbornSets,
+    store: &'a ConcurrentStore<Vec<usize>>,
+    queues: Vec<Mutex<WorkQueue>>,
+    // States queued or being expanded; the search is over once it drops to zero
+    pending: AtomicUsize,
+}
+
+// Explore the reachable markings on the given number of threads, each with its own deque and
+// stealing from the others when it runs dry. Markings are not accelerated to omega, so the net
+// must be bounded. Reductions needing a cycle proviso depend on the visiting order and are refused.
+pub fn explore(net: &PTNet, reduction: Reduction, mode: StoreMode, threads: usize, keep_graph: bool) -> Option<Explored> {
+    let stubborn = StubbornSets::new(net, reduction);
+    if stubborn.needs_proviso() {
+        return None;
+    }
+    let places = net.place_indices();
+    let position: HashMap<usize, usize> = places.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+    let transitions = net.transitions.iter().map(|(idx, t)| Dense {
+        index: *idx,
+        pre: t.conditions.iter().map(|(p, w)| (position[p], *w)).collect(),
+        post: t.effects.iter().map(|(p, w)| (position[p], *w)).collect(),
+    }).collect();
+    let threads = threads.max(1);
+    let store = ConcurrentStore::new(mode, threads * 16);
+    let explorer = Explorer {
+        places: places.clone(),
+        transitions,
+        stubborn,
+        store: &store,
+        queues: (0..threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+        pending: AtomicUsize::new(1),
+    };
+    let init: Vec<usize> = places.iter().map(|p| net.places[p].get_tokens()).collect();
+    if let Visit::New(id) = store.insert(init.clone()) {
+        explorer.queues[0].lock().unwrap().push_back((id, init));
+    }
+    let found: Vec<Vec<Expanded>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads).map(|i| {
+            let explorer = &explorer;
+            scope.spawn(move || explorer.work(i, keep_graph))
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mut res = Explored { states: store.len(), ..Explored::default() };
+    if keep_graph {
+        let len = store.len();
+        res.markings = vec![Vec::new(); len];
+        res.enabled = vec![Vec::new(); len];
+        res.successors = vec![Vec::new(); len];
+        for (id, marking, enabled, successors) in found.into_iter().flatten() {
+            res.markings[id] = marking;
+            res.enabled[id] = enabled;
+            res.successors[id] = successors;
+        }
+    }
+    Some(res)
+}
+
+impl Explorer<'_> {
+    fn next(&self, me: usize) -> Option<(usize, Vec<usize>)> {
+        if let Some(item) = self.queues[me].lock().unwrap().pop_back() {
+            return Some(item);
+        }
+        let n = self.queues.len();
+        (1..n).find_map(|k| self.queues[(me + k) % n].lock().unwrap().pop_front())
+    }
+
+    fn work(&self, me: usize, keep_graph: bool) -> Vec<Expanded> {
+        let mut found = Vec::new();
+        while self.pending.load(Ordering::SeqCst) > 0 {
+            let Some((id, marking)) = self.next(me) else {
+                thread::yield_now();
+                continue;
+            };
+            let enabled: Vec<usize> = self.transitions.iter()
+                .filter(|t| t.pre.iter().all(|(p, w)| marking[*p] >= *w))
+                .map(|t| t.index)
+                .collect();
+            let to_fire = if self.stubborn.reduction == Reduction::Off {
+                enabled.clone()
+            }
+            else {
+                let config: Config = self.places.iter().zip(marking.iter()).map(|(p, v)| (*p, *v)).collect();
+                self.stubborn.reduce(&config, &enabled)
+            };
+            let mut successors = Vec::with_capacity(to_fire.len());
+            for t in self.transitions.iter().filter(|t| to_fire.contains(&t.index)) {
+                let mut next = marking.clone();
+                for (p, w) in t.pre.iter() {
+                    next[*p] -= w;
+                }
+                for (p, w) in t.post.iter() {
+                    next[*p] += w;
+                }
+                match self.store.insert(next.clone()) {
+                    Visit::New(succ) => {
+                        self.pending.fetch_add(1, Ordering::SeqCst);
+                        self.queues[me].lock().unwrap().push_back((succ, next));
+                        successors.push((t.index, Some(succ)));
+                    },
+                    Visit::Seen(succ) => successors.push((t.index, succ)),
+                }
+            }
+            if keep_graph {
+                found.push((id, marking, enabled, successors));
+            }
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+        found
+    }
+}
@@ -0,0 +1,278 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::logics::{eval_atom, eval_state, CmpOp, FormulaTy, PTAtom};
+use crate::logics::space::{NetSpace, StateSpace};
+use crate::logics::transys::Config;
+use crate::petri::*;
+use crate::store::{StateStore, StoreMode, Visit};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchOrder {
+    Bfs,
+    Dfs,
+    // Markings with the smallest distance estimate to a target go first, ties in discovery order
+    BestFirst,
+}
+
+impl SearchOrder {
+    pub fn from_name(name: &str) -> Option<SearchOrder> {
+        match name {
+            "bfs" => Some(SearchOrder::Bfs),
+            "dfs" => Some(SearchOrder::Dfs),
+            "best" => Some(SearchOrder::BestFirst),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReachReport {
+    pub holds: bool,
+    // Shortest firing sequence to a marking satisfying phi for EF phi, or violating it for AG phi
+    pub witness: Option<Vec<usize>>,
+    // Markings stored before the search stopped, including those of the shortening pass
+    pub states: usize,
+}
+
+// Split EF phi / AG phi into phi and whether it is the AG form
+pub fn reachability_query(f: &FormulaTy) -> Option<(FormulaTy, bool)> {
+    match f {
+        FormulaTy::Exists(inner) => match &**inner {
+            FormulaTy::Finally(phi) => Some((*phi.clone(), false)),
+            _ => None,
+        },
+        FormulaTy::Forall(inner) => match &**inner {
+            FormulaTy::Global(phi) => Some((*phi.clone(), true)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+enum Frontier {
+    Queue(VecDeque<(usize, Vec<usize>)>),
+    Stack(Vec<(usize, Vec<usize>)>),
+    Heap(BinaryHeap<Reverse<(usize, usize, Vec<usize>)>>),
+}
+
+impl Frontier {
+    fn pop(&mut self) -> Option<(usize, Vec<usize>)> {
+        match self {
+            Frontier::Queue(queue) => queue.pop_front(),
+            Frontier::Stack(stack) => stack.pop(),
+            Frontier::Heap(heap) => heap.pop().map(|Reverse((_, id, state))| (id, state)),
+        }
+    }
+}
+
+// EF/AG queries decided while the markings are generated, stopping at the first marking where phi
+// takes the searched value. Only breadth-first order finds a nearest one; the others follow it with
+// a breadth-first pass bounded by the length of their witness, so the reported one is always shortest.
+pub struct ReachChecker<'a> {
+    pub net: &'a PTNet,
+    pub order: SearchOrder,
+    pub store: StoreMode,
+    space: NetSpace<'a>,
+}
+
+impl<'a> ReachChecker<'a> {
+    pub fn new(net: &'a PTNet, order: SearchOrder, store: StoreMode) -> ReachChecker<'a> {
+        ReachChecker {
+            net,
+            order,
+            store,
+            space: NetSpace::new(net),
+        }
+    }
+
+    // None when the formula isn't EF/AG of a state formula, or an atom can't be decided on a marking
+    pub fn check(&self, f: &FormulaTy) -> Option<ReachReport> {
+        let (phi, negated) = reachability_query(f)?;
+        // AG phi fails exactly when EF !phi holds
+        let target = !negated;
+        let (mut witness, mut states) = self.search(&phi, target, self.order, None)?;
+        if let Some(seq) = witness.as_ref().filter(|seq| self.order != SearchOrder::Bfs && !seq.is_empty()) {
+            let (shortest, more) = self.search(&phi, target, SearchOrder::Bfs, Some(seq.len()))?;
+            witness = shortest;
+            states += more;
+        }
+        Some(ReachReport {
+            holds: witness.is_some() != negated,
+            witness,
+            states,
+        })
+    }
+
+    // First marking found where phi evaluates to target, as the firing sequence leading to it.
+    // Markings at depth bound are checked but not expanded.
+    fn search(&self, phi: &FormulaTy, target: bool, order: SearchOrder, bound: Option<usize>)
+              -> Option<(Option<Vec<usize>>, usize)> {
+        let mut store = StateStore::new(self.store);
+        // Parent id and fired transition of every stored marking, with its depth
+        let mut parents: Vec<Option<(usize, usize)>> = Vec::new();
+        let mut depths: Vec<usize> = Vec::new();
+        let mut frontier = match order {
+            SearchOrder::Bfs => Frontier::Queue(VecDeque::new()),
+            SearchOrder::Dfs => Frontier::Stack(Vec::new()),
+            SearchOrder::BestFirst => Frontier::Heap(BinaryHeap::new()),
+        };
+
+        let init = self.space.initial();
+        store.insert(init.clone());
+        parents.push(None);
+        depths.push(0);
+        let mut pending = vec![(0, init)];
+        loop {
+            // Markings are checked when discovered, so the search stops one layer early
+            for (id, state) in pending.drain(..) {
+                let config = self.space.marking(&state);
+                if eval_state(phi, self.net, &config)? == target {
+                    return Some((Some(path(&parents, id)), store.len()));
+                }
+                match &mut frontier {
+                    Frontier::Queue(queue) => queue.push_back((id, state)),
+                    Frontier::Stack(stack) => stack.push((id, state)),
+                    Frontier::Heap(heap) => heap.push(Reverse((self.distance(phi, target, &config), id, state))),
+                }
+            }
+            let Some((id, state)) = frontier.pop() else { break };
+            if bound.is_some_and(|bound| depths[id] >= bound) {
+                continue;
+            }
+            for (tran, next) in self.space.successors(&state) {
+                if let Visit::New(next_id) = store.insert(next.clone()) {
+                    parents.push(Some((id, tran)));
+                    depths.push(depths[id] + 1);
+                    pending.push((next_id, next));
+                }
+            }
+        }
+        Some((None, store.len()))
+    }
+
+    // Estimated number of firings before phi evaluates to target, zero on the markings where it does
+    fn distance(&self, phi: &FormulaTy, target: bool, config: &Config) -> usize {
+        match phi {
+            FormulaTy::True | FormulaTy::False => if matches!(phi, FormulaTy::True) == target { 0 } else { usize::MAX },
+            FormulaTy::Prop(atom) => self.atom_distance(atom, target, config),
+            FormulaTy::Neg(atom) => self.atom_distance(atom, !target, config),
+            FormulaTy::Not(inner) => self.distance(inner, !target, config),
+            // Both sides have to change for a true conjunction or a false disjunction, one for the others
+            FormulaTy::And(lhs, rhs) | FormulaTy::Or(lhs, rhs) => {
+                let (l, r) = (self.distance(lhs, target, config), self.distance(rhs, target, config));
+                if matches!(phi, FormulaTy::And(..)) == target { l.saturating_add(r) } else { l.min(r) }
+            },
+            _ => 0,
+        }
+    }
+
+    fn atom_distance(&self, atom: &PTAtom, target: bool, config: &Config) -> usize {
+        if eval_atom(atom, self.net, config) == Some(target) {
+            return 0;
+        }
+        match (atom, target) {
+            // Tokens missing from the preset of the closest transition
+            (PTAtom::Fireability(names), true) => names.iter()
+                .filter_map(|name| self.net.transitions.get(self.net.index_map.get_by_left(name)?))
+                .map(|tran| tran.conditions.iter()
+                    .map(|(p, w)| w.saturating_sub(config.get(p).copied().unwrap_or(0)))
+                    .sum())
+                .min()
+                .unwrap_or(usize::MAX),
+            // Every fireable transition has to be disabled
+            (PTAtom::Deadlock, true) => self.net.transitions.values()
+                .filter(|tran| self.net.is_fireable(config, tran))
+                .count(),
+            (PTAtom::Cardinality(lhs, op, rhs), _) => {
+                let (Some(l), Some(r)) = (lhs.eval(config), rhs.eval(config)) else {
+                    return usize::MAX;
+                };
+                let op = if target { *op } else { negate(*op) };
+                match op {
+                    CmpOp::Lt => l.saturating_add(1).saturating_sub(r),
+                    CmpOp::Le => l.saturating_sub(r),
+                    CmpOp::Eq => l.abs_diff(r),
+                    CmpOp::Ne => 1,
+                    CmpOp::Ge => r.saturating_sub(l),
+                    CmpOp::Gt => r.saturating_add(1).saturating_sub(l),
+                }
+            },
+            _ => 1,
+        }
+    }
+}
+
+fn negate(op: CmpOp) -> CmpOp {
+    match op {
+        CmpOp::Lt => CmpOp::Ge,
+        CmpOp::Le => CmpOp::Gt,
+        CmpOp::Eq => CmpOp::Ne,
+        CmpOp::Ne => CmpOp::Eq,
+        CmpOp::Ge => CmpOp::Lt,
+        CmpOp::Gt => CmpOp::Le,
+    }
+}
+
+fn path(parents: &[Option<(usize, usize)>], mut id: usize) -> Vec<usize> {
+    let mut seq = Vec::new();
+    while let Some((parent, tran)) = parents[id] {
+        seq.push(tran);
+        id = parent;
+    }
+    seq.reverse();
+    seq
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::Formula;
+    use crate::logics::parser::parse_formulas;
+    use crate::logics::props::parse_property;
+    use crate::petri::parser::parse_pnml;
+
+    // Report of every search order, all of them with a witness of the same length that leads
+    // to a marking where phi takes the value the query asks for
+    fn check_orders(net: &PTNet, f: &FormulaTy) -> Option<usize> {
+        let (phi, negated) = reachability_query(f).unwrap();
+        let mut lengths = Vec::new();
+        for order in [SearchOrder::Bfs, SearchOrder::Dfs, SearchOrder::BestFirst] {
+            let report = ReachChecker::new(net, order, StoreMode::Exact).check(f).unwrap();
+            let Some(seq) = report.witness else {
+                lengths.push(None);
+                continue;
+            };
+            let space = NetSpace::new(net);
+            let mut config = space.marking(&space.initial());
+            for t in seq.iter() {
+                assert!(net.is_fireable(&config, &net.transitions[t]));
+                config = net.fire(&config, &net.transitions[t]);
+            }
+            assert_eq!(eval_state(&phi, net, &config), Some(!negated));
+            lengths.push(Some(seq.len()));
+        }
+        assert!(lengths.iter().all(|l| *l == lengths[0]), "{:?}", lengths);
+        lengths[0]
+    }
+
+    // The long way round is tried first by DFS, the shortcut is two firings
+    #[test]
+    fn orders_find_shortest_witness() {
+        let net = test_net(&[("s", 1), ("x", 0), ("y", 0), ("z", 0), ("u", 0), ("goal", 0)],
+            &[("sx", &["s"], &["x"]), ("xy", &["x"], &["y"]), ("yz", &["y"], &["z"]), ("zg", &["z"], &["goal"]), ("su", &["s"], &["u"]), ("ug", &["u"], &["goal"])]);
+        for (text, expected) in [("E F goal >= 1", Some(2)), ("A G goal < 1", Some(2)), ("E F z >= 1", Some(3)), ("E F goal >= 2", None)] {
+            let mut f = Formula { name: String::from(text), ty: parse_property(text).unwrap() };
+            f.bind(&net).unwrap();
+            assert_eq!(check_orders(&net, &f.ty), expected, "{}", text);
+        }
+        let dir = "data/SatelliteMemory-PT-X00010Y0003";
+        let net = parse_pnml(&format!("{}/model.pnml", dir)).unwrap().remove(0);
+        for file in ["ReachabilityCardinality.xml", "ReachabilityFireability.xml"] {
+            for mut f in parse_formulas(&format!("{}/{}", dir, file)).unwrap() {
+                f.bind(&net).unwrap();
+                check_orders(&net, &f.ty);
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::logics::{FormulaTy, PTAtom};
+use crate::logics::classify::is_stutter_insensitive;
+use crate::logics::transys::Config;
+use crate::petri::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reduction {
+    Off,
+    // Preserves all reachable deadlocks
+    Deadlock,
+    // Preserves stutter-invariant (next-free) LTL; holds the visible transitions,
+    // i.e. the ones whose firing may change the value of an atomic proposition
+    Stutter(HashSet<usize>),
+}
+
+impl Reduction {
+    // Stuttering reduction for formulas without Next or Yesterday, no reduction otherwise
+    pub fn for_formula(net: &PTNet, f: &FormulaTy) -> Reduction {
+        if !is_stutter_insensitive(f) {
+            return Reduction::Off;
+        }
+        let mut places = HashSet::new();
+        if !atom_places(net, f, &mut places) {
+            // An atom we can't attribute to places, every transition may be visible
+            return Reduction::Stutter(net.transitions.keys().copied().collect());
+        }
+        let visible = net.transitions.iter()
+            .filter(|(_, t)| places.iter().any(|p| changes(t, *p)))
+            .map(|(idx, _)| *idx)
+            .collect();
+        Reduction::Stutter(visible)
+    }
+}
+
+// Collect the places an atomic proposition reads, false if some atom can't be resolved
+fn atom_places(net: &PTNet, f: &FormulaTy, places: &mut HashSet<usize>) -> bool {
+    match f {
+        FormulaTy::Prop(atom) | FormulaTy::Neg(atom) => match atom {
+            PTAtom::Fireability(names) => {
+                for name in names.iter() {
+                    match net.index_map.get_by_left(name).and_then(|idx| net.transitions.get(idx)) {
+                        Some(t) => places.extend(t.conditions.iter().map(|(p, _)| *p)),
+                        None => return false,
+                    }
+                }
+                true
+            },
+            // Depends on the preset of every transition
+            PTAtom::Deadlock => {
+                for t in net.transitions.values() {
+                    places.extend(t.conditions.iter().map(|(p, _)| *p));
+                }
+                true
+            },
+            PTAtom::Cardinality(lhs, _, rhs) => {
+                for side in [lhs, rhs] {
+                    match side.places(net) {
+                        Some(read) => places.extend(read),
+                        None => return false,
+                    }
+                }
+                true
+            },
+        },
+        FormulaTy::Not(inner) | FormulaTy::Next(inner) | FormulaTy::Global(inner) | FormulaTy::Finally(inner)
+        | FormulaTy::Forall(inner) | FormulaTy::Exists(inner)
+        | FormulaTy::Yesterday(inner) | FormulaTy::Once(inner) | FormulaTy::Historically(inner) => atom_places(net, inner, places),
+        FormulaTy::Or(lhs, rhs) | FormulaTy::And(lhs, rhs)
+        | FormulaTy::Until(lhs, rhs) | FormulaTy::Release(lhs, rhs)
+        | FormulaTy::WeakUntil(lhs, rhs) | FormulaTy::StrongRelease(lhs, rhs) | FormulaTy::Since(lhs, rhs) => {
+            atom_places(net, lhs, places) && atom_places(net, rhs, places)
+        },
+        FormulaTy::True | FormulaTy::False => true,
+    }
+}
+
+fn weight(arcs: &[(usize, usize)], place: usize) -> usize {
+    arcs.iter().filter(|(p, _)| *p == place).map(|(_, w)| *w).sum()
+}
+
+fn changes(t: &Transition, place: usize) -> bool {
+    weight(&t.conditions, place) != weight(&t.effects, place)
+}
+
+// Stubborn set computation for a PTNet (Valmari's D1/D2 rules, plus V for the stuttering variant)
+#[derive(Debug, Clone)]
+pub struct StubbornSets {
+    pub reduction: Reduction,
+    conditions: HashMap<usize, Vec<(usize, usize)>>,
+    // Transitions taking tokens from a place
+    consumers: HashMap<usize, Vec<usize>>,
+    // Transitions adding tokens to a place
+    increasers: HashMap<usize, Vec<usize>>,
+}
+
+impl StubbornSets {
+    pub fn new(net: &PTNet, reduction: Reduction) -> StubbornSets {
+        let mut consumers: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut increasers: HashMap<usize, Vec<usize>> = HashMap::new();
+        for idx in net.transition_indices() {
+            let t = &net.transitions[&idx];
+            for (p, _) in t.conditions.iter() {
+                let list = consumers.entry(*p).or_default();
+                if !list.contains(&idx) {
+                    list.push(idx);
+                }
+            }
+            for (p, _) in t.effects.iter() {
+                let list = increasers.entry(*p).or_default();
+                if weight(&t.effects, *p) > weight(&t.conditions, *p) && !list.contains(&idx) {
+                    list.push(idx);
+                }
+            }
+        }
+        StubbornSets {
+            reduction,
+            conditions: net.transitions.iter().map(|(idx, t)| (*idx, t.conditions.clone())).collect(),
+            consumers,
+            increasers,
+        }
+    }
+
+    // Whether the caller must also apply a cycle proviso (fully expand states closing a cycle)
+    pub fn needs_proviso(&self) -> bool {
+        matches!(self.reduction, Reduction::Stutter(_))
+    }
+
+    // Subset of the enabled transitions to fire at config. Every enabled transition is tried
+    // as the seed and the smallest resulting set wins.
+    pub fn reduce(&self, config: &Config, enabled: &[usize]) -> Vec<usize> {
+        if self.reduction == Reduction::Off || enabled.len() <= 1 {
+            return enabled.to_vec();
+        }
+        let mut best: Option<Vec<usize>> = None;
+        for seed in enabled.iter() {
+            let set = self.closure(config, *seed);
+            let reduced: Vec<usize> = enabled.iter().filter(|t| set.contains(*t)).copied().collect();
+            if best.as_ref().is_none_or(|b| reduced.len() < b.len()) {
+                let done = reduced.len() == 1;
+                best = Some(reduced);
+                if done {
+                    break;
+                }
+            }
+        }
+        best.unwrap()
+    }
+
+    fn closure(&self, config: &Config, seed: usize) -> HashSet<usize> {
+        let mut set = HashSet::new();
+        let mut work = vec![seed];
+        let mut all_visible = false;
+        let empty = Vec::new();
+        set.insert(seed);
+        while let Some(t) = work.pop() {
+            let conditions = &self.conditions[&t];
+            let scapegoat = conditions.iter()
+                .filter(|(p, w)| config.get(p).copied().unwrap_or(0) < *w)
+                .min_by_key(|(p, _)| self.increasers.get(p).map_or(0, |v| v.len()));
+            let next: Vec<usize> = match scapegoat {
+                // D1: a disabled transition needs the producers of one of its missing places
+                Some((p, _)) => self.increasers.get(p).unwrap_or(&empty).clone(),
+                // D2: an enabled transition needs everything competing for its input places
+                None => {
+                    let mut res = Vec::new();
+                    for (p, _) in conditions.iter() {
+                        res.extend(self.consumers.get(p).unwrap_or(&empty).iter().copied());
+                    }
+                    if let Reduction::Stutter(visible) = &self.reduction {
+                        if !all_visible && visible.contains(&t) {
+                            // V: an enabled visible transition drags in all visible ones
+                            all_visible = true;
+                            res.extend(visible.iter().copied());
+                        }
+                    }
+                    res
+                },
+            };
+            for n in next {
+                if set.insert(n) {
+                    work.push(n);
+                }
+            }
+        }
+        set
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExploreStats {
+    pub states: usize,
+    pub enabled: usize,
+    pub fired: usize,
+    pub full_expansions: usize,
+}
+
+impl ExploreStats {
+    pub fn record(&mut self, enabled: usize, fired: usize) {
+        self.enabled += enabled;
+        self.fired += fired;
+        if enabled == fired {
+            self.full_expansions += 1;
+        }
+    }
+}
+
+impl fmt::Display for ExploreStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} states, fired {} of {} enabled transitions ({} pruned), {} states fully expanded",
+               self.states, self.fired, self.enabled, self.enabled - self.fired, self.full_expansions)
+    }
+}
@@ -0,0 +1,499 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+
+use crate::logics::transys::Config;
+use crate::petri::*;
+use crate::utils::BitSet;
+
+// Finite complete prefix of the unfolding of a bounded PTNet, built with the
+// Esparza-Romer-Vogler adequate order. Places with several tokens are unfolded
+// with one condition per token, so arc weights become sets of co-related conditions.
+
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub place: usize,
+    pub pre: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub transition: usize,
+    pub preset: Vec<usize>,
+    pub postset: Vec<usize>,
+    pub cutoff: bool,
+    pub local: BitSet,
+    pub marking: Vec<usize>,
+}
+
+// Work allowed per event of the prefix on average, in steps of the search for co-related presets
+// and history events replayed to compute the markings of possible extensions
+const WORK_PER_EVENT: usize = 100;
+
+// Ordering on local configurations: size, Parikh vector, then Foata normal form.
+// Derived Ord compares the fields lexicographically in declaration order.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OrderKey {
+    size: usize,
+    parikh: Vec<usize>,
+    foata: Vec<Vec<usize>>,
+}
+
+struct Extension {
+    transition: usize,
+    preset: Vec<usize>,
+    history: BitSet,
+    depth: usize,
+    marking: Vec<usize>,
+}
+
+pub struct Prefix {
+    // Dense numbering: position i of a marking vector is place places[i]
+    pub places: Vec<usize>,
+    pub transitions: Vec<usize>,
+    pub init_marking: Vec<usize>,
+    pub conditions: Vec<Condition>,
+    pub events: Vec<Event>,
+    pub initial: Vec<usize>,
+    pre: Vec<Vec<(usize, usize)>>,
+    post: Vec<Vec<(usize, usize)>>,
+    rank: HashMap<usize, usize>,
+    co: Vec<BitSet>,
+    depth: Vec<usize>,
+    by_place: Vec<Vec<usize>>,
+    consumers: Vec<Vec<usize>>,
+}
+
+impl Prefix {
+    pub fn build(net: &PTNet) -> Prefix {
+        Prefix::build_bounded(net, usize::MAX).unwrap()
+    }
+
+    // Returns None when the prefix would need more than max_events events, or more work than
+    // WORK_PER_EVENT per event to find its possible extensions
+    pub fn build_bounded(net: &PTNet, max_events: usize) -> Option<Prefix> {
+        let places = net.place_indices();
+        let transitions = net.transition_indices();
+        let pos: HashMap<usize, usize> = places.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+        let dense = |arcs: &Vec<(usize, usize)>| {
+            let mut res: HashMap<usize, usize> = HashMap::new();
+            for (p, w) in arcs.iter() {
+                *res.entry(pos[p]).or_insert(0) += w;
+            }
+            let mut res: Vec<(usize, usize)> = res.into_iter().collect();
+            res.sort();
+            res
+        };
+        let pre = transitions.iter().map(|t| dense(&net.transitions[t].conditions)).collect();
+        let post = transitions.iter().map(|t| dense(&net.transitions[t].effects)).collect();
+        let init_marking: Vec<usize> = places.iter().map(|p| net.places[p].get_tokens()).collect();
+        let mut prefix = Prefix {
+            rank: transitions.iter().enumerate().map(|(i, t)| (*t, i)).collect(),
+            by_place: vec![Vec::new(); places.len()],
+            places,
+            transitions,
+            init_marking,
+            conditions: Vec::new(),
+            events: Vec::new(),
+            initial: Vec::new(),
+            pre,
+            post,
+            co: Vec::new(),
+            depth: Vec::new(),
+            consumers: Vec::new(),
+        };
+        prefix.unfold(max_events)?;
+        Some(prefix)
+    }
+
+    fn add_condition(&mut self, place: usize, pre: Option<usize>) -> usize {
+        let id = self.conditions.len();
+        self.conditions.push(Condition { place, pre });
+        self.co.push(BitSet::new());
+        self.consumers.push(Vec::new());
+        self.by_place[place].push(id);
+        id
+    }
+
+    fn unfold(&mut self, max_events: usize) -> Option<()> {
+        for p in 0..self.places.len() {
+            for _ in 0..self.init_marking[p] {
+                let c = self.add_condition(p, None);
+                self.initial.push(c);
+            }
+        }
+        let initial = self.initial.clone();
+        for &c in initial.iter() {
+            self.co[c] = initial.iter().filter(|d| **d != c).copied().collect();
+        }
+
+        // Best (smallest) configuration seen for each marking; the empty one for the initial marking
+        let mut best: HashMap<Vec<usize>, OrderKey> = HashMap::new();
+        best.insert(self.init_marking.clone(), OrderKey { size: 0, parikh: Vec::new(), foata: Vec::new() });
+
+        let mut pending: Vec<Option<Extension>> = Vec::new();
+        let mut queue = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        let mut budget = max_events.saturating_mul(WORK_PER_EVENT);
+        for ext in self.extensions(&initial, &mut seen, &mut budget)? {
+            queue.push(Reverse((self.order_key(&ext), pending.len())));
+            pending.push(Some(ext));
+        }
+
+        while let Some(Reverse((key, idx))) = queue.pop() {
+            if self.events.len() >= max_events {
+                return None;
+            }
+            let ext = pending[idx].take().unwrap();
+            // Keys only count transitions, so token copies of a place give distinct configurations
+            // with equal keys: the first one popped is kept, the others are cut off
+            let cutoff = match best.get(&ext.marking) {
+                Some(older) => *older <= key,
+                None => {
+                    best.insert(ext.marking.clone(), key);
+                    false
+                }
+            };
+            let postset = self.insert_event(ext, cutoff);
+            if !cutoff {
+                for ext in self.extensions(&postset, &mut seen, &mut budget)? {
+                    queue.push(Reverse((self.order_key(&ext), pending.len())));
+                    pending.push(Some(ext));
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn insert_event(&mut self, ext: Extension, cutoff: bool) -> Vec<usize> {
+        let id = self.events.len();
+        let mut local = ext.history;
+        local.insert(id);
+        for &b in ext.preset.iter() {
+            self.consumers[b].push(id);
+        }
+        let mut postset = Vec::new();
+        for &(p, w) in self.post[ext.transition].clone().iter() {
+            for _ in 0..w {
+                postset.push(self.add_condition(p, Some(id)));
+            }
+        }
+        if !cutoff {
+            // co(c) for a fresh condition: conditions co with the whole preset, plus its siblings
+            let mut common = self.co[ext.preset[0]].clone();
+            for &b in ext.preset.iter().skip(1) {
+                common.intersect_with(&self.co[b]);
+            }
+            for &c in postset.iter() {
+                let mut set = common.clone();
+                for &d in postset.iter().filter(|d| **d != c) {
+                    set.insert(d);
+                }
+                self.co[c] = set;
+            }
+            for d in common.iter().collect::<Vec<usize>>() {
+                for &c in postset.iter() {
+                    self.co[d].insert(c);
+                }
+            }
+        }
+        self.depth.push(ext.depth);
+        self.events.push(Event {
+            transition: self.transitions[ext.transition],
+            preset: ext.preset,
+            postset: postset.clone(),
+            cutoff,
+            local,
+            marking: ext.marking,
+        });
+        postset
+    }
+
+    // Possible extensions whose preset contains at least one of the fresh conditions, None when
+    // finding them exhausts the budget
+    fn extensions(&self, fresh: &[usize], seen: &mut HashSet<(usize, Vec<usize>)>, budget: &mut usize) -> Option<Vec<Extension>> {
+        let mut res = Vec::new();
+        for t in 0..self.transitions.len() {
+            for &c in fresh.iter() {
+                let place = self.conditions[c].place;
+                if !self.pre[t].iter().any(|(p, _)| *p == place) {
+                    continue;
+                }
+                let mut chosen = vec![c];
+                let allowed = self.co[c].clone();
+                let complete = self.choose(t, (0, 0), &allowed, &mut chosen, budget, &mut |preset| {
+                    let mut preset = preset.to_vec();
+                    preset.sort();
+                    if seen.insert((t, preset.clone())) {
+                        res.push((t, preset));
+                    }
+                });
+                if !complete {
+                    return None;
+                }
+            }
+        }
+        let mut exts = Vec::new();
+        for (t, preset) in res {
+            let ext = self.extension(t, preset);
+            *budget = budget.checked_sub(ext.history.len())?;
+            exts.push(ext);
+        }
+        Some(exts)
+    }
+
+    // Backtrack over the input places of t, picking co-related conditions in increasing order,
+    // from the arc and the count already picked on it, one unit of budget per step; returns false
+    // when the budget runs out
+    fn choose<F: FnMut(&[usize])>(&self, t: usize, (arc, picked): (usize, usize), allowed: &BitSet,
+                                  chosen: &mut Vec<usize>, budget: &mut usize, found: &mut F) -> bool {
+        if *budget == 0 {
+            return false;
+        }
+        *budget -= 1;
+        if arc == self.pre[t].len() {
+            found(chosen);
+            return true;
+        }
+        let (place, weight) = self.pre[t][arc];
+        let fixed = usize::from(self.conditions[chosen[0]].place == place);
+        if picked + fixed == weight {
+            return self.choose(t, (arc + 1, 0), allowed, chosen, budget, found);
+        }
+        let last = if picked == 0 { None } else { chosen.last().copied() };
+        for &c in self.by_place[place].iter() {
+            if last.is_some_and(|l| c <= l) || !allowed.contains(c) {
+                continue;
+            }
+            let mut narrowed = allowed.clone();
+            narrowed.intersect_with(&self.co[c]);
+            chosen.push(c);
+            let complete = self.choose(t, (arc, picked + 1), &narrowed, chosen, budget, found);
+            chosen.pop();
+            if !complete {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn extension(&self, transition: usize, preset: Vec<usize>) -> Extension {
+        let mut history = BitSet::new();
+        let mut depth = 1;
+        for &b in preset.iter() {
+            if let Some(e) = self.conditions[b].pre {
+                history.union_with(&self.events[e].local);
+                depth = depth.max(self.depth[e] + 1);
+            }
+        }
+        let mut marking = self.init_marking.clone();
+        for e in history.iter() {
+            self.apply(&mut marking, self.rank[&self.events[e].transition]);
+        }
+        self.apply(&mut marking, transition);
+        Extension { transition, preset, history, depth, marking }
+    }
+
+    fn apply(&self, marking: &mut [usize], t: usize) {
+        for &(p, w) in self.pre[t].iter() {
+            marking[p] -= w;
+        }
+        for &(p, w) in self.post[t].iter() {
+            marking[p] += w;
+        }
+    }
+
+    fn order_key(&self, ext: &Extension) -> OrderKey {
+        let mut parikh = vec![0; self.transitions.len()];
+        let mut foata = vec![vec![0; self.transitions.len()]; ext.depth];
+        for e in ext.history.iter() {
+            let t = self.rank[&self.events[e].transition];
+            parikh[t] += 1;
+            foata[self.depth[e] - 1][t] += 1;
+        }
+        parikh[ext.transition] += 1;
+        foata[ext.depth - 1][ext.transition] += 1;
+        OrderKey { size: ext.history.len() + 1, parikh, foata }
+    }
+
+    pub fn cutoffs(&self) -> usize {
+        self.events.iter().filter(|e| e.cutoff).count()
+    }
+
+    pub fn to_config(&self, marking: &[usize]) -> Config {
+        self.places.iter().zip(marking.iter()).map(|(p, n)| (*p, *n)).collect()
+    }
+
+    // Firing sequence (transition indices) of a configuration, in causal order
+    pub fn firing_sequence(&self, config: &[usize]) -> Vec<usize> {
+        let mut events = config.to_vec();
+        events.sort();
+        events.iter().map(|e| self.events[*e].transition).collect()
+    }
+
+    // Search a cut-off free configuration whose marking is dead.
+    // Returns the firing sequence leading to it.
+    pub fn deadlock(&self) -> Option<Vec<usize>> {
+        let mut search = ConfigSearch::new(self);
+        let mut target = |s: &ConfigSearch| s.enabled.is_empty();
+        let found = search.run(&mut target, true);
+        found.map(|c| self.firing_sequence(&c))
+    }
+
+    // Search a cut-off free configuration whose marking satisfies pred.
+    // Complete for the prefix: every reachable marking is the marking of such a configuration.
+    pub fn reachable<F: FnMut(&[usize]) -> bool>(&self, mut pred: F) -> Option<Vec<usize>> {
+        let mut search = ConfigSearch::new(self);
+        let mut target = |s: &ConfigSearch| pred(&s.marking);
+        let found = search.run(&mut target, false);
+        found.map(|c| self.firing_sequence(&c))
+    }
+
+    pub fn marking_reachable(&self, marking: &Config) -> Option<Vec<usize>> {
+        let target: Vec<usize> = self.places.iter().map(|p| marking.get(p).copied().unwrap_or(0)).collect();
+        self.reachable(|m| *m == target[..])
+    }
+}
+
+// Enumerates the cut-off free configurations of a prefix, adding events in increasing id order
+// so that each configuration is visited exactly once.
+struct ConfigSearch<'a> {
+    prefix: &'a Prefix,
+    present: Vec<bool>,
+    marking: Vec<usize>,
+    avail: Vec<usize>,
+    enabled: BTreeSet<usize>,
+    chosen: Vec<usize>,
+    last_consumer: Vec<Option<usize>>,
+}
+
+impl<'a> ConfigSearch<'a> {
+    fn new(prefix: &'a Prefix) -> Self {
+        let mut search = ConfigSearch {
+            prefix,
+            present: vec![false; prefix.conditions.len()],
+            marking: prefix.init_marking.clone(),
+            avail: vec![0; prefix.events.len()],
+            enabled: BTreeSet::new(),
+            chosen: Vec::new(),
+            last_consumer: prefix.consumers.iter().map(|c| c.iter().max().copied()).collect(),
+        };
+        for &c in prefix.initial.iter() {
+            search.produce(c);
+        }
+        search
+    }
+
+    fn produce(&mut self, c: usize) {
+        self.present[c] = true;
+        for &e in self.prefix.consumers[c].iter() {
+            self.avail[e] += 1;
+            if self.avail[e] == self.prefix.events[e].preset.len() {
+                self.enabled.insert(e);
+            }
+        }
+    }
+
+    fn consume(&mut self, c: usize) {
+        self.present[c] = false;
+        for &e in self.prefix.consumers[c].iter() {
+            if self.avail[e] == self.prefix.events[e].preset.len() {
+                self.enabled.remove(&e);
+            }
+            self.avail[e] -= 1;
+        }
+    }
+
+    fn fire(&mut self, e: usize) {
+        let event = &self.prefix.events[e];
+        for &b in event.preset.iter() {
+            self.consume(b);
+            self.marking[self.prefix.conditions[b].place] -= 1;
+        }
+        for &c in event.postset.iter() {
+            self.produce(c);
+            self.marking[self.prefix.conditions[c].place] += 1;
+        }
+        self.chosen.push(e);
+    }
+
+    fn unfire(&mut self, e: usize) {
+        let event = &self.prefix.events[e];
+        for &c in event.postset.iter() {
+            self.consume(c);
+            self.marking[self.prefix.conditions[c].place] -= 1;
+        }
+        for &b in event.preset.iter() {
+            self.produce(b);
+            self.marking[self.prefix.conditions[b].place] += 1;
+        }
+        self.chosen.pop();
+    }
+
+    // An event that can no longer be added stays enabled unless a later event consumes its preset
+    fn stuck_enabled(&self, last: usize) -> bool {
+        self.enabled.range(..=last).any(|e| {
+            self.prefix.events[*e].preset.iter().all(|b| self.last_consumer[*b].is_none_or(|l| l <= last))
+        })
+    }
+
+    fn run<F: FnMut(&ConfigSearch) -> bool>(&mut self, target: &mut F, dead: bool) -> Option<Vec<usize>> {
+        if self.visit(target, dead, None) {
+            Some(self.chosen.clone())
+        } else {
+            None
+        }
+    }
+
+    fn visit<F: FnMut(&ConfigSearch) -> bool>(&mut self, target: &mut F, dead: bool, last: Option<usize>) -> bool {
+        if target(self) {
+            return true;
+        }
+        if dead && last.is_some_and(|l| self.stuck_enabled(l)) {
+            return false;
+        }
+        let from = last.map_or(0, |l| l + 1);
+        let candidates: Vec<usize> = self.enabled.range(from..)
+            .filter(|e| !self.prefix.events[**e].cutoff)
+            .copied()
+            .collect();
+        for e in candidates {
+            self.fire(e);
+            if self.visit(target, dead, Some(e)) {
+                return true;
+            }
+            self.unfire(e);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::transys::TranSys;
+    use crate::petri::parser::parse_pnml;
+
+    fn net(file: &str) -> PTNet {
+        parse_pnml(&format!("data/SatelliteMemory-PT-X00010Y0003/{}", file)).unwrap().remove(0)
+    }
+
+    // Token copies of p4 and p9 give configurations with equal keys, the prefix must still be
+    // finite and complete: every marking of the state graph is the marking of a configuration
+    #[test]
+    fn satellite_memory_prefix_is_complete() {
+        let net = net("model.pnml");
+        let prefix = Prefix::build_bounded(&net, 200000).unwrap();
+        assert!(prefix.deadlock().is_none());
+        let tran = TranSys::from_petri(&net);
+        assert_eq!(tran.stats().states, 398);
+        for s in 0..tran.stats().states {
+            assert!(prefix.marking_reachable(tran.config_of(s).unwrap()).is_some());
+        }
+    }
+
+    // The net of model1.pnml is unbounded, so is its unfolding: the bound stops it
+    #[test]
+    fn unbounded_net_gives_up() {
+        assert!(Prefix::build_bounded(&net("model1.pnml"), 10000).is_none());
+    }
+}
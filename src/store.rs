@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::logics::transys::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreMode {
+    // Full states, membership is exact
+    Exact,
+    // Hash compaction: only a 64-bit fingerprint per state, colliding states are taken as seen
+    Compact,
+    // Supertrace: a table of 2^n bits set by two hash functions, states seen before get no id
+    Bitstate(u32),
+}
+
+impl StoreMode {
+    pub fn from_name(name: &str) -> Option<StoreMode> {
+        match name {
+            "exact" => Some(StoreMode::Exact),
+            "compact" => Some(StoreMode::Compact),
+            "bitstate" => Some(StoreMode::Bitstate(28)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    New(usize),
+    // The id of the earlier state, unknown in bitstate mode
+    Seen(Option<usize>),
+}
+
+impl Visit {
+    pub fn is_new(&self) -> bool {
+        matches!(self, Visit::New(_))
+    }
+}
+
+// Visited-state table handing out consecutive ids in insertion order
+#[derive(Debug, Clone)]
+pub struct StateStore<K> {
+    pub mode: StoreMode,
+    exact: HashMap<K, usize>,
+    compact: HashMap<u64, usize>,
+    bits: Vec<u64>,
+    len: usize,
+}
+
+impl<K: Hash + Eq> StateStore<K> {
+    pub fn new(mode: StoreMode) -> StateStore<K> {
+        let words = match mode {
+            StoreMode::Bitstate(n) => (1usize << n).div_ceil(64),
+            _ => 0,
+        };
+        StateStore {
+            mode,
+            exact: HashMap::new(),
+            compact: HashMap::new(),
+            bits: vec![0; words],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, key: K) -> Visit {
+        let id = self.len;
+        match self.mode {
+            StoreMode::Exact => {
+                if let Some(old) = self.exact.get(&key) {
+                    return Visit::Seen(Some(*old));
+                }
+                self.exact.insert(key, id);
+            },
+            StoreMode::Compact => {
+                let old = *self.compact.entry(fingerprint(&key)).or_insert(id);
+                if old != id {
+                    return Visit::Seen(Some(old));
+                }
+            },
+            StoreMode::Bitstate(n) => {
+                let mut seen = true;
+                for pos in bit_positions(fingerprint(&key), n) {
+                    let (word, bit) = (pos / 64, 1u64 << (pos % 64));
+                    if self.bits[word] & bit == 0 {
+                        seen = false;
+                        self.bits[word] |= bit;
+                    }
+                }
+                if seen {
+                    return Visit::Seen(None);
+                }
+            },
+        }
+        self.len += 1;
+        Visit::New(id)
+    }
+
+    // Id of a stored state, always None in bitstate mode
+    pub fn get(&self, key: &K) -> Option<usize> {
+        match self.mode {
+            StoreMode::Exact => self.exact.get(key).copied(),
+            StoreMode::Compact => self.compact.get(&fingerprint(key)).copied(),
+            StoreMode::Bitstate(_) => None,
+        }
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        match self.mode {
+            StoreMode::Bitstate(n) => bit_positions(fingerprint(key), n).iter()
+                .all(|pos| self.bits[pos / 64] & (1u64 << (pos % 64)) != 0),
+            _ => self.get(key).is_some(),
+        }
+    }
+}
+
+fn fingerprint<K: Hash>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn bit_positions(hash: u64, n: u32) -> [usize; 2] {
+    let mask = (1u64 << n) - 1;
+    [(hash & mask) as usize, (hash.rotate_left(32) & mask) as usize]
+}
+
+// Hashable form of a marking: token counts in the given place order
+pub fn marking_key(config: &Config, places: &[usize]) -> Vec<usize> {
+    places.iter().map(|p| config.get(p).copied().unwrap_or(0)).collect()
+}
+
+// Thread-safe variant of StateStore: sharded tables and an atomic bit table.
+// Ids are consecutive but their order depends on thread scheduling.
+#[derive(Debug)]
+pub struct ConcurrentStore<K> {
+    pub mode: StoreMode,
+    exact: Vec<Mutex<HashMap<K, usize>>>,
+    compact: Vec<Mutex<HashMap<u64, usize>>>,
+    bits: Vec<AtomicU64>,
+    len: AtomicUsize,
+}
+
+impl<K: Hash + Eq> ConcurrentStore<K> {
+    pub fn new(mode: StoreMode, shards: usize) -> ConcurrentStore<K> {
+        let words = match mode {
+            StoreMode::Bitstate(n) => (1usize << n).div_ceil(64),
+            _ => 0,
+        };
+        ConcurrentStore {
+            mode,
+            exact: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+            compact: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+            bits: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn insert(&self, key: K) -> Visit {
+        let hash = fingerprint(&key);
+        match self.mode {
+            StoreMode::Exact => {
+                let mut shard = self.exact[hash as usize % self.exact.len()].lock().unwrap();
+                if let Some(old) = shard.get(&key) {
+                    return Visit::Seen(Some(*old));
+                }
+                let id = self.len.fetch_add(1, Ordering::SeqCst);
+                shard.insert(key, id);
+                Visit::New(id)
+            },
+            StoreMode::Compact => {
+                let mut shard = self.compact[hash as usize % self.compact.len()].lock().unwrap();
+                if let Some(old) = shard.get(&hash) {
+                    return Visit::Seen(Some(*old));
+                }
+                let id = self.len.fetch_add(1, Ordering::SeqCst);
+                shard.insert(hash, id);
+                Visit::New(id)
+            },
+            StoreMode::Bitstate(n) => {
+                let mut seen = true;
+                for pos in bit_positions(hash, n) {
+                    let bit = 1u64 << (pos % 64);
+                    if self.bits[pos / 64].fetch_or(bit, Ordering::SeqCst) & bit == 0 {
+                        seen = false;
+                    }
+                }
+                if seen {
+                    return Visit::Seen(None);
+                }
+                Visit::New(self.len.fetch_add(1, Ordering::SeqCst))
+            },
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{HashMap, HashSet};
+
+// Reduced ordered binary decision diagrams, variable 0 being the topmost one
+pub type BddId = usize;
+
+pub const FALSE: BddId = 0;
+pub const TRUE: BddId = 1;
+
+// Variable of the two terminals, below every real variable
+const TERMINAL: usize = usize::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BddNode {
+    pub var: usize,
+    pub low: BddId,
+    pub high: BddId,
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bdd {
+    nodes: Vec<BddNode>,
+    unique: HashMap<BddNode, BddId>,
+    and_cache: HashMap<(BddId, BddId), BddId>,
+    or_cache: HashMap<(BddId, BddId), BddId>,
+    not_cache: HashMap<BddId, BddId>,
+}
+
+impl Bdd {
+    pub fn new() -> Bdd {
+        let mut bdd = Bdd::default();
+        bdd.nodes.push(BddNode { var: TERMINAL, low: FALSE, high: FALSE });
+        bdd.nodes.push(BddNode { var: TERMINAL, low: TRUE, high: TRUE });
+        bdd
+    }
+
+    pub fn node(&self, id: BddId) -> &BddNode {
+        &self.nodes[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 2
+    }
+
+    // Forget the results of past operations; the nodes themselves stay valid
+    pub fn clear_caches(&mut self) {
+        self.and_cache.clear();
+        self.or_cache.clear();
+        self.not_cache.clear();
+    }
+
+    fn var(&self, id: BddId) -> usize {
+        self.nodes[id].var
+    }
+
+    // Cofactors of a with respect to var, a itself when it doesn't test var at its root
+    fn cofactors(&self, a: BddId, var: usize) -> (BddId, BddId) {
+        let node = &self.nodes[a];
+        if node.var == var { (node.low, node.high) } else { (a, a) }
+    }
+
+    pub fn mk(&mut self, var: usize, low: BddId, high: BddId) -> BddId {
+        if low == high {
+            return low;
+        }
+        let node = BddNode { var, low, high };
+        if let Some(id) = self.unique.get(&node) {
+            return *id;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(node.clone());
+        self.unique.insert(node, id);
+        id
+    }
+
+    // The function holding iff var has the given value
+    pub fn literal(&mut self, var: usize, value: bool) -> BddId {
+        if value { self.mk(var, FALSE, TRUE) } else { self.mk(var, TRUE, FALSE) }
+    }
+
+    // Conjunction of literals, given as (var, value) pairs in any order
+    pub fn cube(&mut self, literals: &[(usize, bool)]) -> BddId {
+        let mut sorted = literals.to_vec();
+        sorted.sort();
+        let mut res = TRUE;
+        for (var, value) in sorted.iter().rev() {
+            res = if *value { self.mk(*var, FALSE, res) } else { self.mk(*var, res, FALSE) };
+        }
+        res
+    }
+
+    pub fn not(&mut self, a: BddId) -> BddId {
+        if a == FALSE {
+            return TRUE;
+        }
+        if a == TRUE {
+            return FALSE;
+        }
+        if let Some(r) = self.not_cache.get(&a) {
+            return *r;
+        }
+        let BddNode { var, low, high } = self.nodes[a].clone();
+        let (low, high) = (self.not(low), self.not(high));
+        let r = self.mk(var, low, high);
+        self.not_cache.insert(a, r);
+        self.not_cache.insert(r, a);
+        r
+    }
+
+    pub fn and(&mut self, a: BddId, b: BddId) -> BddId {
+        self.apply(Op::And, a, b)
+    }
+
+    pub fn or(&mut self, a: BddId, b: BddId) -> BddId {
+        self.apply(Op::Or, a, b)
+    }
+
+    pub fn difference(&mut self, a: BddId, b: BddId) -> BddId {
+        let nb = self.not(b);
+        self.and(a, nb)
+    }
+
+    fn apply(&mut self, op: Op, a: BddId, b: BddId) -> BddId {
+        match op {
+            Op::And => {
+                if a == FALSE || b == FALSE {
+                    return FALSE;
+                }
+                if a == TRUE || a == b {
+                    return b;
+                }
+                if b == TRUE {
+                    return a;
+                }
+            },
+            Op::Or => {
+                if a == TRUE || b == TRUE {
+                    return TRUE;
+                }
+                if a == FALSE || a == b {
+                    return b;
+                }
+                if b == FALSE {
+                    return a;
+                }
+            },
+        }
+        let key = (a.min(b), a.max(b));
+        let cached = match op {
+            Op::And => self.and_cache.get(&key),
+            Op::Or => self.or_cache.get(&key),
+        };
+        if let Some(r) = cached {
+            return *r;
+        }
+        let var = self.var(a).min(self.var(b));
+        let ((a0, a1), (b0, b1)) = (self.cofactors(a, var), self.cofactors(b, var));
+        let low = self.apply(op.clone(), a0, b0);
+        let high = self.apply(op.clone(), a1, b1);
+        let r = self.mk(var, low, high);
+        match op {
+            Op::And => self.and_cache.insert(key, r),
+            Op::Or => self.or_cache.insert(key, r),
+        };
+        r
+    }
+
+    pub fn exists(&mut self, a: BddId, vars: &HashSet<usize>) -> BddId {
+        self.and_exists(a, TRUE, vars)
+    }
+
+    // Relational product: exists vars. a & b, without building the conjunction first
+    pub fn and_exists(&mut self, a: BddId, b: BddId, vars: &HashSet<usize>) -> BddId {
+        let last = vars.iter().max().copied();
+        let mut cache = HashMap::new();
+        self.and_exists_rec(a, b, vars, last, &mut cache)
+    }
+
+    fn and_exists_rec(&mut self, a: BddId, b: BddId, vars: &HashSet<usize>, last: Option<usize>,
+                      cache: &mut HashMap<(BddId, BddId), BddId>) -> BddId {
+        if a == FALSE || b == FALSE {
+            return FALSE;
+        }
+        let var = self.var(a).min(self.var(b));
+        if last.is_none_or(|l| var > l) {
+            // Nothing left to quantify below this point
+            return self.and(a, b);
+        }
+        let key = (a.min(b), a.max(b));
+        if let Some(r) = cache.get(&key) {
+            return *r;
+        }
+        let ((a0, a1), (b0, b1)) = (self.cofactors(a, var), self.cofactors(b, var));
+        let low = self.and_exists_rec(a0, b0, vars, last, cache);
+        let r = if vars.contains(&var) {
+            if low == TRUE {
+                TRUE
+            }
+            else {
+                let high = self.and_exists_rec(a1, b1, vars, last, cache);
+                self.or(low, high)
+            }
+        }
+        else {
+            let high = self.and_exists_rec(a1, b1, vars, last, cache);
+            self.mk(var, low, high)
+        };
+        cache.insert(key, r);
+        r
+    }
+
+    // Substitute variables; the mapping must preserve the relative order of the variables in a
+    pub fn rename(&mut self, a: BddId, map: &HashMap<usize, usize>) -> BddId {
+        let mut cache = HashMap::new();
+        self.rename_rec(a, map, &mut cache)
+    }
+
+    fn rename_rec(&mut self, a: BddId, map: &HashMap<usize, usize>, cache: &mut HashMap<BddId, BddId>) -> BddId {
+        if a == FALSE || a == TRUE {
+            return a;
+        }
+        if let Some(r) = cache.get(&a) {
+            return *r;
+        }
+        let BddNode { var, low, high } = self.nodes[a].clone();
+        let low = self.rename_rec(low, map, cache);
+        let high = self.rename_rec(high, map, cache);
+        let r = self.mk(map.get(&var).copied().unwrap_or(var), low, high);
+        cache.insert(a, r);
+        r
+    }
+
+    // Number of satisfying assignments over vars, which must cover the support of a
+    pub fn count(&self, a: BddId, vars: &[usize]) -> u128 {
+        let mut sorted = vars.to_vec();
+        sorted.sort();
+        let pos: HashMap<usize, usize> = sorted.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+        let mut cache = HashMap::new();
+        let (n, first) = (sorted.len(), self.position(a, &pos, sorted.len()));
+        self.count_rec(a, &pos, n, &mut cache) << first
+    }
+
+    fn position(&self, a: BddId, pos: &HashMap<usize, usize>, n: usize) -> usize {
+        if a == FALSE || a == TRUE { n } else { pos[&self.var(a)] }
+    }
+
+    // Assignments to the variables from the position of a downwards
+    fn count_rec(&self, a: BddId, pos: &HashMap<usize, usize>, n: usize, cache: &mut HashMap<BddId, u128>) -> u128 {
+        if a == FALSE {
+            return 0;
+        }
+        if a == TRUE {
+            return 1;
+        }
+        if let Some(c) = cache.get(&a) {
+            return *c;
+        }
+        let node = &self.nodes[a];
+        let here = pos[&node.var];
+        let mut res = 0;
+        for c in [node.low, node.high] {
+            res += self.count_rec(c, pos, n, cache) << (self.position(c, pos, n) - here - 1);
+        }
+        cache.insert(a, res);
+        res
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::logics::{FormulaTy, PTAtom};
+use crate::petri::*;
+use crate::symbolic::bdd::*;
+use crate::symbolic::mdd::{Node, NodeId, EMPTY, ONE};
+use crate::symbolic::saturation::ReachableSet;
+
+// Transition relation of one transition, over the current and next copies of its support only;
+// places outside the support keep their value implicitly
+struct Relation {
+    rel: BddId,
+    cur: HashSet<usize>,
+    next: HashSet<usize>,
+    to_next: HashMap<usize, usize>,
+    to_cur: HashMap<usize, usize>,
+}
+
+// Symbolic CTL model checking over binary encoded markings of a bounded PTNet.
+// Bit i of the encoding uses variable 2i for the current marking and 2i + 1 for the next one.
+// Paths are maximal: a deadlock ends a path, so EG holds at a deadlock satisfying its argument.
+pub struct CtlChecker {
+    pub bdd: Bdd,
+    pub init: BddId,
+    pub reach: BddId,
+    pub dead: BddId,
+    // Current-state variables of each place, most significant bit first
+    pub bits: HashMap<usize, Vec<usize>>,
+    pub bounds: HashMap<usize, usize>,
+    relations: Vec<Relation>,
+    enabled: HashMap<usize, BddId>,
+    cache: HashMap<FormulaTy, BddId>,
+    // The saturated state space, and its MDD nodes already translated to BDDs
+    states: ReachableSet,
+    translated: HashMap<NodeId, BddId>,
+}
+
+impl CtlChecker {
+    // Place bounds and the reachable markings come from the saturation engine, so the net must be bounded.
+    // Variables follow the MDD place order, which keeps the places of a transition close together.
+    pub fn new(net: &PTNet) -> CtlChecker {
+        let saturated = ReachableSet::saturate(net);
+        let mut bits = HashMap::new();
+        let mut bounds = HashMap::new();
+        let mut var = 0;
+        for p in saturated.order.iter() {
+            let bound = saturated.bound(*p);
+            let width = (usize::BITS - bound.leading_zeros()).max(1) as usize;
+            bits.insert(*p, (0..width).map(|i| var + 2 * i).collect::<Vec<usize>>());
+            bounds.insert(*p, bound);
+            var += 2 * width;
+        }
+        let mut checker = CtlChecker {
+            bdd: Bdd::new(),
+            init: FALSE,
+            reach: FALSE,
+            dead: FALSE,
+            bits,
+            bounds,
+            relations: Vec::new(),
+            enabled: HashMap::new(),
+            cache: HashMap::new(),
+            states: saturated,
+            translated: HashMap::new(),
+        };
+        let init: Vec<(usize, usize)> = net.place_indices().iter().map(|p| (*p, net.places[p].get_tokens())).collect();
+        let mut literals = Vec::new();
+        for (p, v) in init {
+            literals.extend(checker.literals(p, v, false));
+        }
+        checker.init = checker.bdd.cube(&literals);
+        for t in net.transition_indices() {
+            let tran = &net.transitions[&t];
+            let enabled = checker.enabling(tran);
+            checker.enabled.insert(t, enabled);
+            let relation = checker.relation(tran);
+            checker.relations.push(relation);
+        }
+        checker.reach = checker.translate(checker.states.root);
+        let mut live = FALSE;
+        for e in checker.enabled.values().copied().collect::<Vec<BddId>>() {
+            live = checker.bdd.or(live, e);
+        }
+        checker.dead = checker.bdd.difference(checker.reach, live);
+        checker
+    }
+
+    // (variable, value) pairs encoding tokens in place, on the current or the next copy
+    fn literals(&self, place: usize, tokens: usize, next: bool) -> Vec<(usize, bool)> {
+        let vars = &self.bits[&place];
+        vars.iter().enumerate()
+            .map(|(i, v)| (v + next as usize, (tokens >> (vars.len() - 1 - i)) & 1 == 1))
+            .collect()
+    }
+
+    fn value(&mut self, place: usize, tokens: usize, next: bool) -> BddId {
+        let literals = self.literals(place, tokens, next);
+        self.bdd.cube(&literals)
+    }
+
+    fn at_least(&mut self, place: usize, tokens: usize) -> BddId {
+        let mut res = FALSE;
+        for v in tokens..=self.bounds[&place] {
+            let cube = self.value(place, v, false);
+            res = self.bdd.or(res, cube);
+        }
+        res
+    }
+
+    fn enabling(&mut self, tran: &Transition) -> BddId {
+        let mut res = TRUE;
+        for (p, w) in merged(tran).iter() {
+            if w.0 > 0 {
+                let ge = self.at_least(*p, w.0);
+                res = self.bdd.and(res, ge);
+            }
+        }
+        res
+    }
+
+    // Successor values past the bound can't occur from reachable markings and are left out
+    fn relation(&mut self, tran: &Transition) -> Relation {
+        let mut rel = TRUE;
+        let mut cur = HashSet::new();
+        let mut next = HashSet::new();
+        for (p, (pre, post)) in merged(tran).iter() {
+            let mut local = FALSE;
+            for v in *pre..=self.bounds[p] {
+                let succ = v - pre + post;
+                if succ > self.bounds[p] {
+                    continue;
+                }
+                let mut literals = self.literals(*p, v, false);
+                literals.extend(self.literals(*p, succ, true));
+                let pair = self.bdd.cube(&literals);
+                local = self.bdd.or(local, pair);
+            }
+            rel = self.bdd.and(rel, local);
+            cur.extend(self.bits[p].iter().copied());
+            next.extend(self.bits[p].iter().map(|v| v + 1));
+        }
+        Relation {
+            rel,
+            to_next: cur.iter().map(|v| (*v, v + 1)).collect(),
+            to_cur: next.iter().map(|v| (*v, v - 1)).collect(),
+            cur,
+            next,
+        }
+    }
+
+    fn preimage(&mut self, set: BddId) -> BddId {
+        let mut res = FALSE;
+        for i in 0..self.relations.len() {
+            let moved = self.preimage_of(i, set);
+            res = self.bdd.or(res, moved);
+        }
+        res
+    }
+
+    // Markings reached from the set by firing the i-th transition
+    fn image_of(&mut self, i: usize, set: BddId) -> BddId {
+        let r = &self.relations[i];
+        let moved = self.bdd.and_exists(r.rel, set, &r.cur);
+        self.bdd.rename(moved, &r.to_cur)
+    }
+
+    // Markings reaching the set by firing the i-th transition
+    fn preimage_of(&mut self, i: usize, set: BddId) -> BddId {
+        let r = &self.relations[i];
+        let shifted = self.bdd.rename(set, &r.to_next);
+        self.bdd.and_exists(r.rel, shifted, &r.next)
+    }
+
+    // The same set of markings as an MDD node of the saturated state space
+    fn translate(&mut self, node: NodeId) -> BddId {
+        if node == EMPTY {
+            return FALSE;
+        }
+        if node == ONE {
+            return TRUE;
+        }
+        if let Some(r) = self.translated.get(&node) {
+            return *r;
+        }
+        let Node { level, children } = self.states.mdd.node(node).clone();
+        let place = self.states.order[level - 1];
+        let mut res = FALSE;
+        for (v, child) in children.into_iter().enumerate() {
+            if child == EMPTY {
+                continue;
+            }
+            let below = self.translate(child);
+            let cube = self.value(place, v, false);
+            let part = self.bdd.and(cube, below);
+            res = self.bdd.or(res, part);
+        }
+        self.translated.insert(node, res);
+        res
+    }
+
+    // Number of markings in a set of reachable markings
+    pub fn count(&self, set: BddId) -> u128 {
+        let vars: Vec<usize> = self.bits.values().flatten().copied().collect();
+        self.bdd.count(set, &vars)
+    }
+
+    // Whether the initial marking satisfies f, None if f isn't a CTL state formula over supported atoms
+    pub fn check(&mut self, f: &FormulaTy, net: &PTNet) -> Option<bool> {
+        // Operation results rarely carry over between formulas, unlike the sets of the subformulas
+        self.bdd.clear_caches();
+        let sat = self.satisfying(f, net)?;
+        Some(self.bdd.and(self.init, sat) != FALSE)
+    }
+
+    // Reachable markings satisfying f
+    pub fn satisfying(&mut self, f: &FormulaTy, net: &PTNet) -> Option<BddId> {
+        if let Some(r) = self.cache.get(f) {
+            return Some(*r);
+        }
+        let res = match f {
+            FormulaTy::True => self.reach,
+            FormulaTy::False => FALSE,
+            FormulaTy::Prop(atom) => self.atom(atom, net)?,
+            FormulaTy::Neg(atom) => {
+                let inner = self.atom(atom, net)?;
+                self.bdd.difference(self.reach, inner)
+            },
+            FormulaTy::Not(inner) => {
+                let inner = self.satisfying(inner, net)?;
+                self.bdd.difference(self.reach, inner)
+            },
+            FormulaTy::And(lhs, rhs) => {
+                let (l, r) = (self.satisfying(lhs, net)?, self.satisfying(rhs, net)?);
+                self.bdd.and(l, r)
+            },
+            FormulaTy::Or(lhs, rhs) => {
+                let (l, r) = (self.satisfying(lhs, net)?, self.satisfying(rhs, net)?);
+                self.bdd.or(l, r)
+            },
+            FormulaTy::Exists(path) => match &**path {
+                FormulaTy::Next(inner) => {
+                    let inner = self.satisfying(inner, net)?;
+                    self.ex(inner)
+                },
+                FormulaTy::Finally(inner) => {
+                    let inner = self.satisfying(inner, net)?;
+                    self.eu(self.reach, inner)
+                },
+                FormulaTy::Global(inner) => {
+                    let inner = self.satisfying(inner, net)?;
+                    self.eg(inner)
+                },
+                FormulaTy::Until(lhs, rhs) => {
+                    let (l, r) = (self.satisfying(lhs, net)?, self.satisfying(rhs, net)?);
+                    self.eu(l, r)
+                },
+                // E[l R r] = E[r U (l & r)] | EG r
+                FormulaTy::Release(lhs, rhs) => {
+                    let (l, r) = (self.satisfying(lhs, net)?, self.satisfying(rhs, net)?);
+                    let both = self.bdd.and(l, r);
+                    let until = self.eu(r, both);
+                    let global = self.eg(r);
+                    self.bdd.or(until, global)
+                },
+                // E[l W r] = E[l U r] | EG l
+                FormulaTy::WeakUntil(lhs, rhs) => {
+                    let (l, r) = (self.satisfying(lhs, net)?, self.satisfying(rhs, net)?);
+                    let until = self.eu(l, r);
+                    let global = self.eg(l);
+                    self.bdd.or(until, global)
+                },
+                // E[l M r] = E[r U (l & r)]
+                FormulaTy::StrongRelease(lhs, rhs) => {
+                    let (l, r) = (self.satisfying(lhs, net)?, self.satisfying(rhs, net)?);
+                    let both = self.bdd.and(l, r);
+                    self.eu(r, both)
+                },
+                _ => return None,
+            },
+            // Universal path operators through their existential duals
+            FormulaTy::Forall(path) => {
+                let dual = match &**path {
+                    FormulaTy::Next(inner) => FormulaTy::Next(not(inner)),
+                    FormulaTy::Finally(inner) => FormulaTy::Global(not(inner)),
+                    FormulaTy::Global(inner) => FormulaTy::Finally(not(inner)),
+                    FormulaTy::Until(lhs, rhs) => FormulaTy::Release(not(lhs), not(rhs)),
+                    FormulaTy::Release(lhs, rhs) => FormulaTy::Until(not(lhs), not(rhs)),
+                    FormulaTy::WeakUntil(lhs, rhs) => FormulaTy::StrongRelease(not(lhs), not(rhs)),
+                    FormulaTy::StrongRelease(lhs, rhs) => FormulaTy::WeakUntil(not(lhs), not(rhs)),
+                    _ => return None,
+                };
+                let exists = self.satisfying(&FormulaTy::Exists(Box::new(dual)), net)?;
+                self.bdd.difference(self.reach, exists)
+            },
+            _ => return None,
+        };
+        self.cache.insert(f.clone(), res);
+        Some(res)
+    }
+
+    fn atom(&mut self, atom: &PTAtom, net: &PTNet) -> Option<BddId> {
+        match atom {
+            PTAtom::Fireability(names) => {
+                let mut res = FALSE;
+                for name in names.iter() {
+                    let enabled = *self.enabled.get(net.index_map.get_by_left(name)?)?;
+                    res = self.bdd.or(res, enabled);
+                }
+                Some(self.bdd.and(self.reach, res))
+            },
+            PTAtom::Deadlock => Some(self.dead),
+            // Selected on the MDD, which splits on a place value in one step
+            PTAtom::Cardinality(..) => {
+                let set = self.states.satisfying(&FormulaTy::Prop(atom.clone()), net)?;
+                Some(self.translate(set))
+            },
+        }
+    }
+
+    fn ex(&mut self, set: BddId) -> BddId {
+        let pre = self.preimage(set);
+        self.bdd.and(self.reach, pre)
+    }
+
+    // Least fixpoint Z = r | (l & EX Z)
+    fn eu(&mut self, l: BddId, r: BddId) -> BddId {
+        let l = self.bdd.and(self.reach, l);
+        let mut z = r;
+        let mut frontier = r;
+        while frontier != FALSE {
+            let mut added = FALSE;
+            for i in 0..self.relations.len() {
+                let step = self.preimage_of(i, frontier);
+                let step = self.bdd.and(l, step);
+                added = self.bdd.or(added, step);
+            }
+            frontier = self.bdd.difference(added, z);
+            z = self.bdd.or(z, frontier);
+        }
+        z
+    }
+
+    // Greatest fixpoint Z = f & (EX Z | deadlock). Once a marking leaves Z only its predecessors
+    // need to be checked again, and only they are intersected with the relations.
+    fn eg(&mut self, f: BddId) -> BddId {
+        let mut z = self.bdd.and(self.reach, f);
+        let mut candidates = z;
+        while candidates != FALSE {
+            let mut kept = self.dead;
+            for i in 0..self.relations.len() {
+                let succ = self.image_of(i, candidates);
+                let succ = self.bdd.and(succ, z);
+                let back = self.preimage_of(i, succ);
+                kept = self.bdd.or(kept, back);
+            }
+            let kept = self.bdd.and(candidates, kept);
+            let lost = self.bdd.difference(candidates, kept);
+            if lost == FALSE {
+                break;
+            }
+            z = self.bdd.difference(z, lost);
+            let pre = self.preimage(lost);
+            candidates = self.bdd.and(z, pre);
+        }
+        z
+    }
+}
+
+fn not(f: &FormulaTy) -> Box<FormulaTy> {
+    Box::new(FormulaTy::Not(Box::new(f.clone())))
+}
+
+// (consumed, produced) per place touched by the transition
+fn merged(tran: &Transition) -> Vec<(usize, (usize, usize))> {
+    let mut res: HashMap<usize, (usize, usize)> = HashMap::new();
+    for (p, w) in tran.conditions.iter() {
+        res.entry(*p).or_default().0 += w;
+    }
+    for (p, w) in tran.effects.iter() {
+        res.entry(*p).or_default().1 += w;
+    }
+    let mut res: Vec<(usize, (usize, usize))> = res.into_iter().collect();
+    res.sort();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::ctl::GlobalCtl;
+    use crate::logics::parser::parse_formulas;
+    use crate::logics::transys::TranSys;
+    use crate::petri::parser::parse_pnml;
+
+    // Every CTL formula of the model is decided, and the same way as by the explicit labelling
+    #[test]
+    fn bdd_agrees_with_explicit_labelling() {
+        let dir = "data/SatelliteMemory-PT-X00010Y0003";
+        let net = parse_pnml(&format!("{}/model.pnml", dir)).unwrap().remove(0);
+        let mut checker = CtlChecker::new(&net);
+        assert_eq!(checker.count(checker.reach), 398);
+        let tran = TranSys::from_petri(&net);
+        let mut explicit = GlobalCtl::new(&tran, &[]).unwrap();
+        for file in ["CTLFireability.xml", "CTLCardinality.xml"] {
+            for mut f in parse_formulas(&format!("{}/{}", dir, file)).unwrap() {
+                f.bind(&net).unwrap();
+                let expected = explicit.holds(&f.ty);
+                assert!(expected.is_some(), "{}", f.name);
+                assert_eq!(checker.check(&f.ty, &net), expected, "{}", f.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+// Multi-valued decision diagrams over variables with growable domains.
+// Level 0 holds the two terminals, variables live at levels 1..=K with K on top.
+pub type NodeId = usize;
+
+pub const EMPTY: NodeId = 0;
+pub const ONE: NodeId = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Node {
+    pub level: usize,
+    // Child for each local value; trailing EMPTY children are trimmed so nodes stay canonical
+    pub children: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Union,
+    Intersect,
+    Difference,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Mdd {
+    nodes: Vec<Node>,
+    unique: HashMap<Node, NodeId>,
+    union_cache: HashMap<(NodeId, NodeId), NodeId>,
+    intersect_cache: HashMap<(NodeId, NodeId), NodeId>,
+    difference_cache: HashMap<(NodeId, NodeId), NodeId>,
+}
+
+impl Mdd {
+    pub fn new() -> Mdd {
+        let mut mdd = Mdd::default();
+        mdd.nodes.push(Node { level: 0, children: Vec::new() });
+        mdd.nodes.push(Node { level: 0, children: Vec::new() });
+        mdd
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn level(&self, id: NodeId) -> usize {
+        self.nodes[id].level
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 2
+    }
+
+    pub fn child(&self, id: NodeId, value: usize) -> NodeId {
+        self.nodes[id].children.get(value).copied().unwrap_or(EMPTY)
+    }
+
+    // Hash-cons a node. Nodes without children collapse to EMPTY; levels are never skipped.
+    pub fn unique(&mut self, level: usize, mut children: Vec<NodeId>) -> NodeId {
+        while children.last() == Some(&EMPTY) {
+            children.pop();
+        }
+        if children.is_empty() {
+            return EMPTY;
+        }
+        let node = Node { level, children };
+        if let Some(id) = self.unique.get(&node) {
+            return *id;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(node.clone());
+        self.unique.insert(node, id);
+        id
+    }
+
+    // The set holding exactly one vector, values[k - 1] being the value at level k
+    pub fn singleton(&mut self, values: &[usize]) -> NodeId {
+        let mut node = ONE;
+        for (k, v) in values.iter().enumerate() {
+            let mut children = vec![EMPTY; v + 1];
+            children[*v] = node;
+            node = self.unique(k + 1, children);
+        }
+        node
+    }
+
+    pub fn union(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.apply(Op::Union, a, b)
+    }
+
+    pub fn intersect(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.apply(Op::Intersect, a, b)
+    }
+
+    pub fn difference(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.apply(Op::Difference, a, b)
+    }
+
+    fn apply(&mut self, op: Op, a: NodeId, b: NodeId) -> NodeId {
+        match op {
+            Op::Union => {
+                if a == EMPTY || a == b {
+                    return b;
+                }
+                if b == EMPTY {
+                    return a;
+                }
+            },
+            Op::Intersect => {
+                if a == EMPTY || b == EMPTY {
+                    return EMPTY;
+                }
+                if a == b {
+                    return a;
+                }
+            },
+            Op::Difference => {
+                if a == EMPTY || b == EMPTY {
+                    return a;
+                }
+                if a == b {
+                    return EMPTY;
+                }
+            },
+        }
+        // Both operands are now distinct non-empty nodes of the same level above the terminals
+        let key = match op {
+            Op::Difference => (a, b),
+            _ => (a.min(b), a.max(b)),
+        };
+        let cached = match op {
+            Op::Union => self.union_cache.get(&key),
+            Op::Intersect => self.intersect_cache.get(&key),
+            Op::Difference => self.difference_cache.get(&key),
+        };
+        if let Some(r) = cached {
+            return *r;
+        }
+        let level = self.level(a);
+        let len = match op {
+            Op::Union => self.nodes[a].children.len().max(self.nodes[b].children.len()),
+            Op::Intersect => self.nodes[a].children.len().min(self.nodes[b].children.len()),
+            Op::Difference => self.nodes[a].children.len(),
+        };
+        let mut children = Vec::with_capacity(len);
+        for i in 0..len {
+            let (ca, cb) = (self.child(a, i), self.child(b, i));
+            children.push(self.apply(op.clone(), ca, cb));
+        }
+        let r = self.unique(level, children);
+        match op {
+            Op::Union => self.union_cache.insert(key, r),
+            Op::Intersect => self.intersect_cache.insert(key, r),
+            Op::Difference => self.difference_cache.insert(key, r),
+        };
+        r
+    }
+
+    // Keep only the vectors whose value at the given level satisfies keep
+    pub fn restrict<F: Fn(usize) -> bool>(&mut self, a: NodeId, level: usize, keep: &F) -> NodeId {
+        let mut cache = HashMap::new();
+        self.restrict_rec(a, level, keep, &mut cache)
+    }
+
+    fn restrict_rec<F: Fn(usize) -> bool>(&mut self, a: NodeId, level: usize, keep: &F,
+                                          cache: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if a == EMPTY || self.level(a) < level {
+            return a;
+        }
+        if let Some(r) = cache.get(&a) {
+            return *r;
+        }
+        let node_level = self.level(a);
+        let mut children = self.nodes[a].children.clone();
+        for (i, c) in children.iter_mut().enumerate() {
+            if node_level == level {
+                if !keep(i) {
+                    *c = EMPTY;
+                }
+            }
+            else {
+                *c = self.restrict_rec(*c, level, keep, cache);
+            }
+        }
+        let r = self.unique(node_level, children);
+        cache.insert(a, r);
+        r
+    }
+
+    // Number of vectors in the set
+    pub fn count(&self, a: NodeId) -> u128 {
+        let mut cache = HashMap::new();
+        self.count_rec(a, &mut cache)
+    }
+
+    fn count_rec(&self, a: NodeId, cache: &mut HashMap<NodeId, u128>) -> u128 {
+        if a == EMPTY {
+            return 0;
+        }
+        if a == ONE {
+            return 1;
+        }
+        if let Some(c) = cache.get(&a) {
+            return *c;
+        }
+        let res = self.nodes[a].children.iter().map(|c| self.count_rec(*c, cache)).sum();
+        cache.insert(a, res);
+        res
+    }
+
+    pub fn contains(&self, a: NodeId, values: &[usize]) -> bool {
+        let mut node = a;
+        while node != EMPTY && node != ONE {
+            node = self.child(node, values[self.level(node) - 1]);
+        }
+        node == ONE
+    }
+
+    // Largest value taken at the given level by some vector of the set
+    pub fn max_value(&self, a: NodeId, level: usize) -> Option<usize> {
+        let mut seen = HashMap::new();
+        self.max_rec(a, level, &mut seen)
+    }
+
+    fn max_rec(&self, a: NodeId, level: usize, seen: &mut HashMap<NodeId, Option<usize>>) -> Option<usize> {
+        if a == EMPTY || a == ONE {
+            return None;
+        }
+        if let Some(r) = seen.get(&a) {
+            return *r;
+        }
+        let node = &self.nodes[a];
+        let res = if node.level == level {
+            (0..node.children.len()).rev().find(|i| node.children[*i] != EMPTY)
+        }
+        else {
+            node.children.iter().filter_map(|c| self.max_rec(*c, level, seen)).max()
+        };
+        seen.insert(a, res);
+        res
+    }
+
+    // Some vector of the set, values[k - 1] being the value at level k
+    pub fn pick(&self, a: NodeId) -> Option<Vec<usize>> {
+        if a == EMPTY {
+            return None;
+        }
+        let mut values = vec![0; self.level(a)];
+        let mut node = a;
+        while node != ONE {
+            let level = self.level(node);
+            let i = self.nodes[node].children.iter().position(|c| *c != EMPTY).unwrap();
+            values[level - 1] = i;
+            node = self.nodes[node].children[i];
+        }
+        Some(values)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod bdd;
+pub mod ctl;
+pub mod mdd;
+pub mod saturation;
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+
+use crate::logics::{eval_atom, CmpOp, FormulaTy, IntExpr, PTAtom};
+use crate::logics::transys::Config;
+use crate::petri::*;
+use crate::symbolic::mdd::*;
+
+// A transition restricted to the levels of the places it reads or writes
+#[derive(Debug, Clone)]
+struct Event {
+    top: usize,
+    bot: usize,
+    // (consumed, produced) per level, None when the level is outside the support
+    local: Vec<Option<(usize, usize)>>,
+}
+
+impl Event {
+    fn next(&self, level: usize, value: usize) -> Option<usize> {
+        match self.local[level] {
+            None => Some(value),
+            Some((pre, post)) => if value >= pre { Some(value - pre + post) } else { None },
+        }
+    }
+}
+
+// Reachable markings of a PTNet as an MDD, one level per place.
+pub struct ReachableSet {
+    pub mdd: Mdd,
+    pub root: NodeId,
+    // order[k - 1] is the place index at level k
+    pub order: Vec<usize>,
+    level_of: HashMap<usize, usize>,
+}
+
+impl ReachableSet {
+    // Saturation fixpoint: events are fired at the top level of their support,
+    // and every node is saturated before it is used by the levels above.
+    pub fn saturate(net: &PTNet) -> ReachableSet {
+        let order = variable_order(net);
+        let level_of: HashMap<usize, usize> = order.iter().enumerate().map(|(k, p)| (*p, k + 1)).collect();
+        let mut events = Vec::new();
+        for t in net.transition_indices() {
+            let tran = &net.transitions[&t];
+            let mut local = vec![None; order.len() + 1];
+            for (p, w) in tran.conditions.iter() {
+                local[level_of[p]].get_or_insert((0, 0)).0 += w;
+            }
+            for (p, w) in tran.effects.iter() {
+                local[level_of[p]].get_or_insert((0, 0)).1 += w;
+            }
+            let support: Vec<usize> = (1..=order.len()).filter(|k| local[*k].is_some()).collect();
+            if let (Some(bot), Some(top)) = (support.first(), support.last()) {
+                events.push(Event { top: *top, bot: *bot, local });
+            }
+        }
+        let init: Vec<usize> = order.iter().map(|p| net.places[p].get_tokens()).collect();
+        let mut mdd = Mdd::new();
+        let root = mdd.singleton(&init);
+        let mut sat = Saturation {
+            mdd,
+            by_top: (0..=order.len()).map(|k| (0..events.len()).filter(|e| events[*e].top == k).collect()).collect(),
+            events,
+            sat_cache: HashMap::new(),
+            fire_cache: HashMap::new(),
+        };
+        let root = sat.saturate(order.len(), root);
+        ReachableSet { mdd: sat.mdd, root, order, level_of }
+    }
+
+    pub fn count(&self) -> u128 {
+        self.mdd.count(self.root)
+    }
+
+    fn values(&self, config: &Config) -> Vec<usize> {
+        self.order.iter().map(|p| config.get(p).copied().unwrap_or(0)).collect()
+    }
+
+    fn to_config(&self, values: &[usize]) -> Config {
+        self.order.iter().zip(values.iter()).map(|(p, v)| (*p, *v)).collect()
+    }
+
+    pub fn contains(&self, config: &Config) -> bool {
+        self.mdd.contains(self.root, &self.values(config))
+    }
+
+    // Maximal number of tokens in the place over all reachable markings
+    pub fn bound(&self, place: usize) -> usize {
+        self.mdd.max_value(self.root, self.level_of[&place]).unwrap_or(0)
+    }
+
+    // Reachable markings satisfying a state formula, None if some atom isn't supported
+    pub fn satisfying(&mut self, f: &FormulaTy, net: &PTNet) -> Option<NodeId> {
+        match f {
+            FormulaTy::True => Some(self.root),
+            FormulaTy::False => Some(EMPTY),
+            FormulaTy::Prop(atom) => self.atom(atom, net),
+            FormulaTy::Neg(atom) => {
+                let inner = self.atom(atom, net)?;
+                Some(self.mdd.difference(self.root, inner))
+            },
+            FormulaTy::Not(inner) => {
+                let inner = self.satisfying(inner, net)?;
+                Some(self.mdd.difference(self.root, inner))
+            },
+            FormulaTy::And(lhs, rhs) => {
+                let (l, r) = (self.satisfying(lhs, net)?, self.satisfying(rhs, net)?);
+                Some(self.mdd.intersect(l, r))
+            },
+            FormulaTy::Or(lhs, rhs) => {
+                let (l, r) = (self.satisfying(lhs, net)?, self.satisfying(rhs, net)?);
+                Some(self.mdd.union(l, r))
+            },
+            _ => None,
+        }
+    }
+
+    fn atom(&mut self, atom: &PTAtom, net: &PTNet) -> Option<NodeId> {
+        match atom {
+            PTAtom::Fireability(names) => {
+                let mut res = EMPTY;
+                for name in names.iter() {
+                    let tran = net.transitions.get(net.index_map.get_by_left(name)?)?;
+                    let enabled = self.enabled(tran);
+                    res = self.mdd.union(res, enabled);
+                }
+                Some(res)
+            },
+            PTAtom::Deadlock => {
+                let mut live = EMPTY;
+                for tran in net.transitions.values() {
+                    let enabled = self.enabled(tran);
+                    live = self.mdd.union(live, enabled);
+                }
+                Some(self.mdd.difference(self.root, live))
+            },
+            PTAtom::Cardinality(lhs, op, rhs) => {
+                let mut places = lhs.places(net)?;
+                places.extend(rhs.places(net)?);
+                // Below the lowest place read the partial value is final
+                let bottom = places.iter().map(|p| self.level_of[p]).min().unwrap_or(self.order.len() + 1);
+                let mut coefs = HashMap::new();
+                let mut constant = 0;
+                let compare = if linear(lhs, 1, &mut coefs, &mut constant) && linear(rhs, -1, &mut coefs, &mut constant) {
+                    Compare::Linear(coefs, *op, constant)
+                }
+                else {
+                    // Places in the order the walk meets them, top level first
+                    places.sort_by_key(|p| std::cmp::Reverse(self.level_of[p]));
+                    places.dedup();
+                    Compare::General(atom, net, places)
+                };
+                let start = match &compare {
+                    Compare::Linear(_, _, constant) => vec![*constant],
+                    Compare::General(..) => Vec::new(),
+                };
+                Some(self.select(self.root, self.order.len(), bottom, start, &compare, &mut HashMap::new()))
+            },
+        }
+    }
+
+    // Reachable markings where the transition is fireable
+    fn enabled(&mut self, tran: &Transition) -> NodeId {
+        let mut res = self.root;
+        for (p, w) in tran.conditions.iter() {
+            let w = *w;
+            res = self.mdd.restrict(res, self.level_of[p], &|v| v >= w);
+        }
+        res
+    }
+
+    // Walk the set top-down carrying the partial value of the atom along each path, keeping the
+    // paths that satisfy it at the terminal; paths sharing a node and a partial value share the result
+    fn select(&mut self, set: NodeId, level: usize, bottom: usize, partial: Vec<i128>, compare: &Compare,
+              cache: &mut HashMap<(NodeId, Vec<i128>), NodeId>) -> NodeId {
+        if set == EMPTY {
+            return EMPTY;
+        }
+        if level < bottom {
+            return if compare.holds(&partial) { set } else { EMPTY };
+        }
+        if let Some(r) = cache.get(&(set, partial.clone())) {
+            return *r;
+        }
+        let place = self.order[level - 1];
+        let mut children = Vec::new();
+        for v in 0..self.mdd.node(set).children.len() {
+            let next = compare.step(&partial, place, v);
+            let child = self.mdd.child(set, v);
+            children.push(self.select(child, level - 1, bottom, next, compare, cache));
+        }
+        let res = self.mdd.unique(level, children);
+        cache.insert((set, partial), res);
+        res
+    }
+
+    pub fn witness(&self, set: NodeId) -> Option<Config> {
+        self.mdd.pick(set).map(|v| self.to_config(&v))
+    }
+}
+
+// How a cardinality atom is evaluated along a path of the MDD: a running value of lhs - rhs when
+// both sides are linear, otherwise the tokens of the places read (in the given order) for the atom
+enum Compare<'a> {
+    Linear(HashMap<usize, i128>, CmpOp, i128),
+    General(&'a PTAtom, &'a PTNet, Vec<usize>),
+}
+
+impl Compare<'_> {
+    fn step(&self, partial: &[i128], place: usize, value: usize) -> Vec<i128> {
+        match self {
+            Compare::Linear(coefs, _, _) => match coefs.get(&place) {
+                Some(c) => vec![partial[0] + c * value as i128],
+                None => partial.to_vec(),
+            },
+            Compare::General(_, _, places) => {
+                let mut next = partial.to_vec();
+                if places.contains(&place) {
+                    next.push(value as i128);
+                }
+                next
+            },
+        }
+    }
+
+    fn holds(&self, partial: &[i128]) -> bool {
+        match self {
+            // lhs - rhs = d compares to 0 as max(d, 0) does to max(-d, 0)
+            Compare::Linear(_, op, _) => op.holds(partial[0].max(0) as usize, (-partial[0]).max(0) as usize),
+            Compare::General(atom, net, places) => {
+                let config: Config = places.iter().zip(partial.iter()).map(|(p, v)| (*p, *v as usize)).collect();
+                eval_atom(atom, net, &config) == Some(true)
+            },
+        }
+    }
+}
+
+// Add sign times the expression to the coefficients per place and the constant, false if it
+// multiplies places together
+fn linear(e: &IntExpr, sign: i128, coefs: &mut HashMap<usize, i128>, constant: &mut i128) -> bool {
+    match e {
+        IntExpr::Const(n) => *constant += sign * *n as i128,
+        IntExpr::Tokens(names, places) => {
+            if places.len() != names.len() {
+                return false;
+            }
+            for p in places.iter() {
+                *coefs.entry(*p).or_insert(0) += sign;
+            }
+        },
+        IntExpr::Sum(terms) => return terms.iter().all(|t| linear(t, sign, coefs, constant)),
+        IntExpr::Product(terms) => {
+            let mut factor = sign;
+            let mut variable = None;
+            for t in terms.iter() {
+                match t.eval(&Config::new()) {
+                    Some(n) => factor *= n as i128,
+                    None if variable.is_none() => variable = Some(t),
+                    None => return false,
+                }
+            }
+            match variable {
+                Some(t) => return linear(t, factor, coefs, constant),
+                None => *constant += factor,
+            }
+        },
+    }
+    true
+}
+
+struct Saturation {
+    mdd: Mdd,
+    events: Vec<Event>,
+    by_top: Vec<Vec<usize>>,
+    sat_cache: HashMap<NodeId, NodeId>,
+    fire_cache: HashMap<(usize, NodeId), NodeId>,
+}
+
+impl Saturation {
+    fn saturate(&mut self, level: usize, node: NodeId) -> NodeId {
+        if level == 0 || node == EMPTY {
+            return node;
+        }
+        if let Some(r) = self.sat_cache.get(&node) {
+            return *r;
+        }
+        let mut children: Vec<NodeId> = self.mdd.node(node).children.clone();
+        for c in children.iter_mut() {
+            *c = self.saturate(level - 1, *c);
+        }
+        // Fire the events of this level until no local state gains new substates
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for e in self.by_top[level].clone() {
+                let mut i = 0;
+                while i < children.len() {
+                    if children[i] != EMPTY {
+                        if let Some(j) = self.events[e].next(level, i) {
+                            let f = self.fire(e, level - 1, children[i]);
+                            if f != EMPTY {
+                                if j >= children.len() {
+                                    children.resize(j + 1, EMPTY);
+                                }
+                                let u = self.mdd.union(children[j], f);
+                                if u != children[j] {
+                                    children[j] = u;
+                                    changed = true;
+                                }
+                            }
+                        }
+                    }
+                    i += 1;
+                }
+            }
+        }
+        let res = self.mdd.unique(level, children);
+        self.sat_cache.insert(node, res);
+        self.sat_cache.insert(res, res);
+        res
+    }
+
+    // Image of a saturated node under one event whose top lies above this level, saturated again
+    fn fire(&mut self, e: usize, level: usize, node: NodeId) -> NodeId {
+        if level < self.events[e].bot || node == EMPTY {
+            return node;
+        }
+        if let Some(r) = self.fire_cache.get(&(e, node)) {
+            return *r;
+        }
+        let mut children = Vec::new();
+        for i in 0..self.mdd.node(node).children.len() {
+            let c = self.mdd.child(node, i);
+            if c == EMPTY {
+                continue;
+            }
+            if let Some(j) = self.events[e].next(level, i) {
+                let f = self.fire(e, level - 1, c);
+                if f != EMPTY {
+                    if j >= children.len() {
+                        children.resize(j + 1, EMPTY);
+                    }
+                    children[j] = self.mdd.union(children[j], f);
+                }
+            }
+        }
+        let built = self.mdd.unique(level, children);
+        let res = self.saturate(level, built);
+        self.fire_cache.insert((e, node), res);
+        res
+    }
+}
+
+// Place order keeping the support of each transition compact (a few FORCE iterations),
+// place indices in ascending order as the starting point
+fn variable_order(net: &PTNet) -> Vec<usize> {
+    let mut order = net.place_indices();
+    let supports: Vec<Vec<usize>> = net.transition_indices().iter().map(|t| {
+        let tran = &net.transitions[t];
+        let mut s: Vec<usize> = tran.conditions.iter().chain(tran.effects.iter()).map(|(p, _)| *p).collect();
+        s.sort();
+        s.dedup();
+        s
+    }).collect();
+    for _ in 0..20 {
+        let pos: HashMap<usize, f64> = order.iter().enumerate().map(|(i, p)| (*p, i as f64)).collect();
+        let centers: Vec<f64> = supports.iter()
+            .map(|s| s.iter().map(|p| pos[p]).sum::<f64>() / s.len().max(1) as f64)
+            .collect();
+        let mut weight: HashMap<usize, (f64, usize)> = HashMap::new();
+        for (s, c) in supports.iter().zip(centers.iter()) {
+            for p in s.iter() {
+                let entry = weight.entry(*p).or_insert((0.0, 0));
+                entry.0 += c;
+                entry.1 += 1;
+            }
+        }
+        let mut next = order.clone();
+        next.sort_by(|a, b| {
+            let wa = weight.get(a).map_or(pos[a], |(s, n)| s / *n as f64);
+            let wb = weight.get(b).map_or(pos[b], |(s, n)| s / *n as f64);
+            wa.partial_cmp(&wb).unwrap().then(a.cmp(b))
+        });
+        if next == order {
+            break;
+        }
+        order = next;
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::parser::parse_formulas;
+    use crate::logics::props::parse_property;
+    use crate::logics::transys::TranSys;
+    use crate::logics::{eval_state, Formula};
+    use crate::petri::parser::parse_pnml;
+
+    // The saturated set holds exactly the markings of the explicit state graph, with the same
+    // bounds, and every reachability predicate selects the markings satisfying it there
+    #[test]
+    fn agrees_with_explicit_graph() {
+        let dir = "data/SatelliteMemory-PT-X00010Y0003";
+        let net = parse_pnml(&format!("{}/model.pnml", dir)).unwrap().remove(0);
+        let mut reach = ReachableSet::saturate(&net);
+        let tran = TranSys::from_petri(&net);
+        let states = tran.stats().states;
+        assert_eq!(states, 398);
+        assert_eq!(reach.count(), states as u128);
+        let configs: Vec<Config> = (0..states).map(|s| tran.config_of(s).unwrap().clone()).collect();
+        assert!(configs.iter().all(|c| reach.contains(c)));
+        for p in net.place_indices() {
+            assert_eq!(reach.bound(p), configs.iter().map(|c| c[&p]).max().unwrap(), "{}", net.places[&p].name);
+        }
+        let mut predicates = Vec::new();
+        for file in ["ReachabilityCardinality.xml", "ReachabilityFireability.xml"] {
+            for mut f in parse_formulas(&format!("{}/{}", dir, file)).unwrap() {
+                f.bind(&net).unwrap();
+                match f.ty {
+                    FormulaTy::Exists(inner) | FormulaTy::Forall(inner) => match *inner {
+                        FormulaTy::Finally(phi) | FormulaTy::Global(phi) => predicates.push((f.name, *phi)),
+                        _ => panic!("{}", f.name),
+                    },
+                    _ => panic!("{}", f.name),
+                }
+            }
+        }
+        // Products of places take the general path
+        for text in ["p10 + 2 * p3 >= 12", "p10 * p3 >= 4", "p1 * (p2 + 3) != p10", "!deadlock"] {
+            let mut f = Formula { name: String::from(text), ty: parse_property(text).unwrap() };
+            f.bind(&net).unwrap();
+            predicates.push((f.name, f.ty));
+        }
+        for (name, phi) in predicates {
+            let sat = reach.satisfying(&phi, &net).unwrap();
+            let expected: Vec<bool> = configs.iter().map(|c| eval_state(&phi, &net, c).unwrap()).collect();
+            assert_eq!(reach.mdd.count(sat), expected.iter().filter(|b| **b).count() as u128, "{}", name);
+            for (c, e) in configs.iter().zip(expected) {
+                assert_eq!(reach.mdd.contains(sat, &reach.values(c)), e, "{}", name);
+            }
+        }
+    }
+}
@@ -83,4 +83,150 @@ pub fn powerset<T: std::cmp::Eq + std::hash::Hash>(input: &HashSet<T>) -> Vec<Ha
         res.push(set.clone());
     }
     res
-}
\ No newline at end of file
+}
+
+// Growable bitset over dense indices, used where HashSet<usize> would be too heavy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> BitSet {
+        BitSet { words: Vec::new() }
+    }
+
+    pub fn insert(&mut self, i: usize) {
+        let w = i / 64;
+        if w >= self.words.len() {
+            self.words.resize(w + 1, 0);
+        }
+        self.words[w] |= 1 << (i % 64);
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        if let Some(word) = self.words.get_mut(i / 64) {
+            *word &= !(1 << (i % 64));
+        }
+        self.trim();
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.words.get(i / 64).is_some_and(|w| w & (1 << (i % 64)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn union_with(&mut self, other: &BitSet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (w, o) in self.words.iter_mut().zip(other.words.iter()) {
+            *w |= o;
+        }
+    }
+
+    pub fn intersect_with(&mut self, other: &BitSet) {
+        self.words.truncate(other.words.len());
+        for (w, o) in self.words.iter_mut().zip(other.words.iter()) {
+            *w &= o;
+        }
+        self.trim();
+    }
+
+    pub fn difference_with(&mut self, other: &BitSet) {
+        for (w, o) in self.words.iter_mut().zip(other.words.iter()) {
+            *w &= !o;
+        }
+        self.trim();
+    }
+
+    pub fn is_subset(&self, other: &BitSet) -> bool {
+        self.words.iter().enumerate().all(|(i, w)| w & !other.words.get(i).copied().unwrap_or(0) == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &w)| {
+            (0..64).filter(move |b| w & (1 << b) != 0).map(move |b| i * 64 + b)
+        })
+    }
+
+    // Keep the representation canonical so that derived Eq/Hash agree on equal sets
+    fn trim(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+    }
+}
+
+impl FromIterator<usize> for BitSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> BitSet {
+        let mut res = BitSet::new();
+        for i in iter {
+            res.insert(i);
+        }
+        res
+    }
+}
+
+// Strongly connected components of the nodes of mask, over the graph whose i-th edge from v is
+// succ(v, i) (Tarjan, with an explicit stack)
+pub fn sccs(mask: &[bool], succ: impl Fn(usize, usize) -> Option<usize>) -> Vec<Vec<usize>> {
+    let n = mask.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut res = Vec::new();
+    let mut next = 0;
+    for root in (0..n).filter(|r| mask[*r]) {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        // Node and position of the next edge to follow
+        let mut calls = vec![(root, 0)];
+        while let Some((v, i)) = calls.pop() {
+            if i == 0 {
+                index[v] = next;
+                low[v] = next;
+                next += 1;
+                stack.push(v);
+                on_stack[v] = true;
+            }
+            if let Some(w) = succ(v, i) {
+                calls.push((v, i + 1));
+                if !mask[w] {
+                    continue;
+                }
+                if index[w] == usize::MAX {
+                    calls.push((w, 0));
+                }
+                else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            if low[v] == index[v] {
+                let mut scc = Vec::new();
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    scc.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                res.push(scc);
+            }
+            if let Some((u, _)) = calls.last() {
+                low[*u] = low[*u].min(low[v]);
+            }
+        }
+    }
+    res
+}